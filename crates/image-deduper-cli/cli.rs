@@ -36,6 +36,10 @@ enum Commands {
         #[arg(long)]
         symlinks: bool,
 
+        /// Replace duplicates with hard links to originals (same filesystem only)
+        #[arg(long)]
+        hardlinks: bool,
+
         /// Verbosity level
         #[arg(short, long, action = clap::ArgAction::Count)]
         verbose: u8,
@@ -67,6 +71,7 @@ fn main() -> Result<(), anyhow::Error> {
             dry_run,
             delete,
             symlinks,
+            hardlinks,
             verbose,
             config,
         } => {
@@ -83,6 +88,7 @@ fn main() -> Result<(), anyhow::Error> {
             config.duplicates_dir = duplicates_dir;
             config.delete_duplicates = delete;
             config.create_symlinks = symlinks;
+            config.create_hardlinks = hardlinks;
 
             // Set log level based on verbosity
             config.log_level = match verbose {
@@ -95,7 +101,7 @@ fn main() -> Result<(), anyhow::Error> {
             config.validate()?;
 
             // Initialize deduplicator
-            let deduper = ImageDeduper::new(config);
+            let deduper = ImageDeduper::new(&config)?;
 
             // Run the deduplication process
             info!("Starting image deduplication...");
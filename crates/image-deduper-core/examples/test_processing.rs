@@ -40,7 +40,7 @@ fn run_app() -> Result<()> {
         ..Default::default()
     };
 
-    let deduper = ImageDeduper::new(&config);
+    let deduper = ImageDeduper::new(&config)?;
 
     info!("Indexing {} for images...", source_directory.display());
     let images = deduper.discover_images(&[source_directory])?;
@@ -48,13 +48,14 @@ fn run_app() -> Result<()> {
 
     // Use force_rescan=true to process all test images
     info!("Calling hash_and_persist...");
-    let (final_pc_count, final_pp_count) = deduper.hash_and_persist(&images, &config)?;
-
-    // Display final database statistics
-    println!("\nFinal database contents:");
-    println!("  - Cryptographic hashes: {}", final_pc_count);
-    println!("  - Perceptual hashes: {}", final_pp_count);
-    println!("  - Total unique images: {}", final_pc_count);
+    let summary = deduper.hash_and_persist(&images, &config)?;
+
+    // Display what the run did
+    println!("\nHashing results:");
+    println!("  - New images stored: {}", summary.new);
+    println!("  - Moved since stored: {}", summary.moved);
+    println!("  - Already stored: {}", summary.skipped);
+    println!("  - Failed: {}", summary.failed);
 
     shutdown_logger();
 
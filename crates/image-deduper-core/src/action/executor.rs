@@ -0,0 +1,541 @@
+/// Execution of the configured action on elected duplicate groups
+///
+/// The original of each group is never touched, nor is any file under
+/// `Config::protected_roots`. Every duplicate gets exactly one
+/// `ActionResult`; a failure on one file is recorded in its result and does not
+/// stop the run.
+///
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use blake3::Hash as Blake3Hash;
+use log::{info, warn};
+
+use super::file_ops::{free_name, replace_with_link, safe_move, same_device, same_file, symlink};
+use super::journal::{Journal, JournalEntry};
+use super::plan::ActionPlan;
+use super::trash;
+use crate::config::{Config, DeleteMode};
+use crate::deduplication::ElectedGroup;
+use crate::error::{Error, Result};
+use crate::log_fs_modification;
+use crate::safety::{protected_root, PendingAction, SafetyManager, VerifiedPair};
+use crate::types::{ActionResult, ActionType};
+
+/// Applies the configured action to the duplicates in elected groups
+#[derive(Debug, Clone)]
+pub struct ActionExecutor {
+    action_type: ActionType,
+    duplicates_dir: PathBuf,
+    journal_dir: PathBuf,
+    /// Home trash that deleted files go to, `None` to delete permanently
+    trash: Option<PathBuf>,
+    protected_roots: Vec<PathBuf>,
+    dry_run: bool,
+    safety: Arc<SafetyManager>,
+}
+
+/// Outcome of applying the action to a set of groups
+#[derive(Debug, Clone)]
+pub struct ActionRun {
+    /// Journal run to pass to `undo`, `None` for dry runs
+    pub run_id: Option<String>,
+
+    /// One result per duplicate
+    pub results: Vec<ActionResult>,
+
+    /// Why the preflight checks would refuse this run, for dry runs; a live
+    /// run that fails them is refused before anything is changed
+    pub preflight_error: Option<String>,
+}
+
+/// A file to act on, with the Blake3 hash recorded when it was scanned
+#[derive(Clone, Copy)]
+struct Scanned<'a> {
+    path: &'a Path,
+    hash: Option<Blake3Hash>,
+}
+
+/// A checked action on one duplicate, ready to apply
+enum Operation {
+    Move {
+        destination: PathBuf,
+    },
+    Delete,
+    Symlink {
+        target: PathBuf,
+    },
+    Hardlink {
+        target: PathBuf,
+    },
+    /// The duplicate is already a hard link to the original
+    AlreadyLinked,
+}
+
+impl ActionExecutor {
+    /// Create an executor from the action settings in `config`
+    ///
+    /// Moving to `duplicates_dir` is the default. Fails if more than one of
+    /// `delete_duplicates`, `create_symlinks` and `create_hardlinks` is set, or
+    /// if deleting to the trash but the home trash can't be located.
+    pub fn new(config: &Config) -> Result<Self> {
+        let selected: Vec<ActionType> = [
+            (config.delete_duplicates, ActionType::Delete),
+            (config.create_symlinks, ActionType::Symlink),
+            (config.create_hardlinks, ActionType::Hardlink),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, action_type)| enabled.then_some(action_type))
+        .collect();
+
+        let action_type = match selected.as_slice() {
+            [] => ActionType::Move,
+            [action_type] => *action_type,
+            _ => {
+                return Err(Error::Configuration(format!(
+                    "only one duplicate action can be selected, got {:?}",
+                    selected
+                )))
+            }
+        };
+
+        let trash = match config.delete_mode {
+            DeleteMode::Permanent => None,
+            DeleteMode::Trash => Some(trash::home_trash_dir().ok_or_else(|| {
+                Error::Configuration("cannot locate the home trash directory".to_string())
+            })?),
+        };
+
+        Ok(Self {
+            action_type,
+            duplicates_dir: config.duplicates_dir.clone(),
+            journal_dir: config.journal_dir.clone(),
+            trash,
+            protected_roots: config.protected_roots.clone(),
+            dry_run: config.dry_run,
+            safety: Arc::new(SafetyManager::new(config)),
+        })
+    }
+
+    /// Use a shared safety manager, so copies removed by earlier runs are known
+    pub fn with_safety_manager(mut self, safety: Arc<SafetyManager>) -> Self {
+        self.safety = safety;
+        self
+    }
+
+    /// Delete to the trash, with `dir` as the home trash instead of
+    /// `$XDG_DATA_HOME/Trash`
+    pub fn with_trash_dir(mut self, dir: &Path) -> Self {
+        self.trash = Some(dir.to_path_buf());
+        self
+    }
+
+    /// The action this executor performs
+    pub fn action_type(&self) -> ActionType {
+        self.action_type
+    }
+
+    /// Apply the action to every duplicate in `groups`, one result per file
+    ///
+    /// Unless this is a dry run, every change is journalled in `journal_dir`
+    /// before it is made. Fails, before anything is changed, if the run doesn't
+    /// pass the safety manager's preflight checks or the journal can't be
+    /// created; a dry run reports a preflight failure in its result instead.
+    pub fn execute(&self, groups: &[ElectedGroup]) -> Result<ActionRun> {
+        let pending: Vec<PendingAction> = groups
+            .iter()
+            .flat_map(|group| &group.duplicates)
+            .map(|duplicate| PendingAction {
+                action_type: self.action_type,
+                path: &duplicate.path,
+                hash: duplicate.crypto_hash,
+            })
+            .collect();
+        let preflight_error = self.preflight(&pending)?;
+        let mut journal = self.start_journal()?;
+
+        let mut results = Vec::new();
+        for group in groups {
+            for duplicate in &group.duplicates {
+                results.push(self.execute_one(
+                    self.action_type,
+                    Scanned {
+                        path: &duplicate.path,
+                        hash: duplicate.crypto_hash,
+                    },
+                    Scanned {
+                        path: &group.original.path,
+                        hash: group.original.crypto_hash,
+                    },
+                    || Ok(()),
+                    journal.as_mut(),
+                ));
+            }
+        }
+
+        Ok(self.finish_run(journal, results, preflight_error))
+    }
+
+    /// Apply a reviewed plan, performing the action recorded for each file
+    ///
+    /// Each duplicate and its original must still have the size, modification
+    /// time and Blake3 hash recorded in the plan; anything that changed since
+    /// is left alone and reported as failed. Files marked `Keep` are skipped.
+    pub fn apply_plan(&self, plan: &ActionPlan) -> Result<ActionRun> {
+        let pending: Vec<PendingAction> = plan
+            .groups
+            .iter()
+            .flat_map(|group| &group.duplicates)
+            .filter_map(|planned| {
+                Some(PendingAction {
+                    action_type: planned.action.action_type()?,
+                    path: &planned.file.path,
+                    hash: Some(planned.file.hash),
+                })
+            })
+            .collect();
+        let preflight_error = self.preflight(&pending)?;
+        let mut journal = self.start_journal()?;
+
+        let mut results = Vec::new();
+        for group in &plan.groups {
+            for planned in &group.duplicates {
+                let Some(action_type) = planned.action.action_type() else {
+                    continue;
+                };
+                results.push(self.execute_one(
+                    action_type,
+                    Scanned {
+                        path: &planned.file.path,
+                        hash: Some(planned.file.hash),
+                    },
+                    Scanned {
+                        path: &group.original.path,
+                        hash: Some(group.original.hash),
+                    },
+                    // The safety manager re-hashes both against the plan
+                    || {
+                        group.original.verify_metadata()?;
+                        planned.file.verify_metadata()
+                    },
+                    journal.as_mut(),
+                ));
+            }
+        }
+
+        Ok(self.finish_run(journal, results, preflight_error))
+    }
+
+    /// Run the safety manager's preflight checks
+    ///
+    /// A failure refuses a live run; a dry run carries on and returns it, to
+    /// be reported with the run's results.
+    fn preflight(&self, pending: &[PendingAction]) -> Result<Option<String>> {
+        match self.safety.preflight(pending, &self.duplicates_dir) {
+            Ok(()) => Ok(None),
+            Err(e) if self.dry_run => {
+                warn!("DRY RUN - a live run would be refused: {}", e);
+                Ok(Some(e.to_string()))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Open a journal for a new run, unless this is a dry run
+    fn start_journal(&self) -> Result<Option<Journal>> {
+        if self.dry_run {
+            return Ok(None);
+        }
+        Ok(Some(Journal::create(&self.journal_dir)?))
+    }
+
+    /// Log a summary of the run and close its journal
+    fn finish_run(
+        &self,
+        journal: Option<Journal>,
+        results: Vec<ActionResult>,
+        preflight_error: Option<String>,
+    ) -> ActionRun {
+        let failed = results.iter().filter(|result| !result.success).count();
+        info!(
+            "{}Acted on {} duplicates: {} succeeded, {} failed",
+            if self.dry_run { "DRY RUN - " } else { "" },
+            results.len(),
+            results.len() - failed,
+            failed
+        );
+        ActionRun {
+            run_id: journal.map(|journal| journal.run_id().to_string()),
+            results,
+            preflight_error,
+        }
+    }
+
+    /// Apply an action to a single duplicate of `original`, once `verify` and
+    /// the safety manager pass
+    ///
+    /// Without a journal this is a dry run: the preconditions are still
+    /// checked, so the result reports whether the action would have succeeded.
+    fn execute_one(
+        &self,
+        action_type: ActionType,
+        duplicate: Scanned,
+        original: Scanned,
+        verify: impl FnOnce() -> Result<()>,
+        journal: Option<&mut Journal>,
+    ) -> ActionResult {
+        let (duplicate_path, original_path) = (duplicate.path, original.path);
+        let outcome = self
+            .check_protected(duplicate.path)
+            .and_then(|()| verify())
+            .and_then(|()| {
+                self.safety.check_action(
+                    action_type,
+                    duplicate.path,
+                    duplicate.hash,
+                    original.path,
+                    original.hash,
+                )
+            })
+            .and_then(|verified| {
+                let operation =
+                    self.check(action_type, duplicate.path, original.path, &verified)?;
+                match journal {
+                    Some(journal) => {
+                        self.apply(
+                            action_type,
+                            duplicate.path,
+                            original.path,
+                            &operation,
+                            verified.duplicate,
+                            journal,
+                        )?;
+                        // Only a live run changes which copies remain
+                        self.safety
+                            .record_action(action_type, duplicate.path, &verified.duplicate);
+                    }
+                    None => info!(
+                        "DRY RUN - would {:?} {}",
+                        action_type,
+                        duplicate.path.display()
+                    ),
+                }
+                Ok(operation)
+            });
+
+        let (destination, error) = match outcome {
+            Ok(Operation::Move { destination }) => (Some(destination), None),
+            Ok(_) => (None, None),
+            Err(e) => {
+                warn!(
+                    "{:?} failed for {}: {}",
+                    action_type,
+                    duplicate_path.display(),
+                    e
+                );
+                (None, Some(e.to_string()))
+            }
+        };
+
+        ActionResult {
+            action_type,
+            duplicate_path: duplicate_path.to_path_buf(),
+            original_path: original_path.to_path_buf(),
+            destination,
+            success: error.is_none(),
+            error,
+        }
+    }
+
+    /// Refuse to act on a file under a protected root
+    fn check_protected(&self, path: &Path) -> Result<()> {
+        match protected_root(&self.protected_roots, path) {
+            Some(root) => {
+                info!("{} is protected by {}", path.display(), root.display());
+                Err(Error::ProtectedPath(path.to_path_buf()))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Work out how to apply the action to a verified duplicate
+    fn check(
+        &self,
+        action_type: ActionType,
+        duplicate: &Path,
+        original: &Path,
+        verified: &VerifiedPair,
+    ) -> Result<Operation> {
+        match action_type {
+            ActionType::Move => {
+                // Never overwrite: a taken name gets a numbered sibling
+                let destination =
+                    free_name(&duplicate_destination(&self.duplicates_dir, duplicate)?);
+                Ok(Operation::Move { destination })
+            }
+            ActionType::Delete => Ok(Operation::Delete),
+            ActionType::Symlink => Ok(Operation::Symlink {
+                // An absolute target keeps the link valid wherever it is resolved from
+                target: fs::canonicalize(original)?,
+            }),
+            ActionType::Hardlink => check_hardlink(duplicate, original, verified),
+        }
+    }
+
+    /// Back up the duplicate if the operation removes it, journal the
+    /// operation, then perform it
+    fn apply(
+        &self,
+        action_type: ActionType,
+        duplicate: &Path,
+        original: &Path,
+        operation: &Operation,
+        hash: Blake3Hash,
+        journal: &mut Journal,
+    ) -> Result<()> {
+        if let Operation::AlreadyLinked = operation {
+            info!("{} is already linked to its original", duplicate.display());
+            return Ok(());
+        }
+        let backup = self.safety.backup_before(action_type, duplicate, &hash)?;
+        // A trashed file's place is only known once its trash info is written
+        let trashed = match (operation, &self.trash) {
+            (Operation::Delete, Some(trash)) => Some(trash::reserve(duplicate, trash)?),
+            _ => None,
+        };
+
+        let destination = match operation {
+            Operation::Move { destination } => Some(destination.as_path()),
+            Operation::Delete => trashed.as_deref(),
+            Operation::Symlink { target } | Operation::Hardlink { target } => {
+                Some(target.as_path())
+            }
+            Operation::AlreadyLinked => None,
+        };
+        let entry = JournalEntry {
+            backup,
+            ..JournalEntry::new(action_type, duplicate, original, destination, hash)
+        };
+        let seq = journal.record_intent(entry)?;
+
+        let outcome = perform(duplicate, operation, &hash, trashed.as_deref());
+        let confirmed = match &outcome {
+            Ok(()) => journal.record_done(seq),
+            Err(e) => journal.record_failed(seq, &e.to_string()),
+        };
+        if let Err(e) = confirmed {
+            // Undo inspects the filesystem, so an unconfirmed intent is still safe
+            warn!("Failed to confirm journal entry {}: {}", seq, e);
+        }
+        outcome
+    }
+}
+
+/// Perform a checked operation on a duplicate with Blake3 hash `hash`,
+/// deleting to `trashed` if given
+fn perform(
+    duplicate: &Path,
+    operation: &Operation,
+    hash: &Blake3Hash,
+    trashed: Option<&Path>,
+) -> Result<()> {
+    match operation {
+        Operation::Move { destination } => {
+            safe_move(duplicate, destination, hash)?;
+            log_fs_modification!(
+                "MOVE",
+                duplicate,
+                Some(format!("to {}", destination.display()))
+            );
+        }
+        Operation::Delete => match trashed {
+            Some(trashed) => {
+                trash::put(duplicate, trashed)?;
+                log_fs_modification!(
+                    "TRASH",
+                    duplicate,
+                    Some(format!("to {}", trashed.display()))
+                );
+            }
+            None => {
+                fs::remove_file(duplicate)?;
+                log_fs_modification!("DELETE", duplicate, None::<String>);
+            }
+        },
+        Operation::Symlink { target } => {
+            replace_with_link(duplicate, |link| symlink(target, link))?;
+            log_fs_modification!(
+                "SYMLINK",
+                duplicate,
+                Some(format!("to {}", target.display()))
+            );
+        }
+        Operation::Hardlink { target } => {
+            replace_with_link(duplicate, |link| fs::hard_link(target, link))?;
+            log_fs_modification!(
+                "HARDLINK",
+                duplicate,
+                Some(format!("to {}", target.display()))
+            );
+        }
+        Operation::AlreadyLinked => {}
+    }
+    Ok(())
+}
+
+/// Where a moved duplicate is placed under `duplicates_dir`
+///
+/// The duplicate's absolute directory is mirrored below `duplicates_dir`, so
+/// `/photos/2020/a.jpg` becomes `<duplicates_dir>/photos/2020/a.jpg` and every
+/// moved file can be traced back to where it came from.
+pub fn duplicate_destination(duplicates_dir: &Path, path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::FileNotFound(path.to_path_buf()))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mirrored: PathBuf = fs::canonicalize(parent)?
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect();
+
+    Ok(duplicates_dir.join(mirrored).join(file_name))
+}
+
+/// Check that `duplicate` can be replaced by a hard link to `original`
+///
+/// A hard link can't span filesystems, and it makes both paths share the
+/// original's contents, so it is only allowed between byte-identical files on
+/// the same device.
+fn check_hardlink(duplicate: &Path, original: &Path, verified: &VerifiedPair) -> Result<Operation> {
+    let duplicate_metadata = fs::symlink_metadata(duplicate)?;
+    let original_metadata = fs::metadata(original)?;
+    if same_file(&duplicate_metadata, &original_metadata) == Some(true) {
+        return Ok(Operation::AlreadyLinked);
+    }
+    if same_device(&duplicate_metadata, &original_metadata) == Some(false) {
+        return Err(Error::SafetyCheck(format!(
+            "cannot hard link {} to {}: they are on different devices",
+            duplicate.display(),
+            original.display()
+        )));
+    }
+    if verified.duplicate != verified.original {
+        return Err(Error::SafetyCheck(format!(
+            "cannot hard link {} to {}: contents differ",
+            duplicate.display(),
+            original.display()
+        )));
+    }
+
+    Ok(Operation::Hardlink {
+        target: original.to_path_buf(),
+    })
+}
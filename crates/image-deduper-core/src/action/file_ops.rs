@@ -0,0 +1,236 @@
+/// Low-level file operations shared by the executor, undo and safety backups
+///
+/// Replacements go through a temporary sibling that is renamed into place, so a
+/// path is never missing if the process is interrupted halfway.
+///
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use blake3::Hash as Blake3Hash;
+
+use crate::error::{Error, Result};
+use crate::processing::compute_cryptographic;
+
+/// Move `from` to `to` without ever overwriting an existing file
+///
+/// An atomic rename is tried first. Across filesystems the file is copied to a
+/// temporary name beside `to`, fsynced and checked against `expected`, then
+/// renamed into place, and only then is `from` unlinked. Either way `to`
+/// appears complete or not at all, and `from` outlives any failure. Fails with
+/// `AlreadyExists` if `to` is taken.
+pub(super) fn safe_move(from: &Path, to: &Path, expected: &Blake3Hash) -> Result<()> {
+    fs::create_dir_all(parent_dir(to))?;
+    match rename_no_clobber(from, to) {
+        Ok(()) => {
+            sync_dir(parent_dir(to))?;
+            return sync_dir(parent_dir(from));
+        }
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let temp = temp_sibling(to, "copy");
+    let placed = fs::copy(from, &temp)
+        .and_then(|_| File::open(&temp)?.sync_all())
+        .map_err(Error::from)
+        .and_then(|()| verify_hash(&temp, expected))
+        .and_then(|()| rename_no_clobber(&temp, to).map_err(Error::from));
+    if let Err(e) = placed {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    sync_dir(parent_dir(to))?;
+
+    fs::remove_file(from)?;
+    sync_dir(parent_dir(from))
+}
+
+/// `path` if nothing is there yet, otherwise the first free `stem_N.ext` beside it
+pub(super) fn free_name(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+    let mut candidate = path.to_path_buf();
+    let mut n = 0;
+    while fs::symlink_metadata(&candidate).is_ok() {
+        n += 1;
+        candidate = path.with_file_name(numbered_name(name, n));
+    }
+    candidate
+}
+
+/// Rename `from` to `to`, failing with `AlreadyExists` rather than replacing `to`
+#[cfg(target_os = "linux")]
+fn rename_no_clobber(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_from = CString::new(from.as_os_str().as_bytes())?;
+    let c_to = CString::new(to.as_os_str().as_bytes())?;
+    // SAFETY: both paths are NUL-terminated and outlive the call
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            c_from.as_ptr(),
+            libc::AT_FDCWD,
+            c_to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        // The kernel or filesystem doesn't support RENAME_NOREPLACE
+        Some(libc::EINVAL) | Some(libc::ENOSYS) => link_no_clobber(from, to),
+        _ => Err(e),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rename_no_clobber(from: &Path, to: &Path) -> io::Result<()> {
+    link_no_clobber(from, to)
+}
+
+/// Rename by linking the new name and unlinking the old one, which fails if
+/// `to` exists
+///
+/// Where hard links aren't supported, falls back to a plain rename once `to`
+/// is seen to be free.
+fn link_no_clobber(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => fs::remove_file(from),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::AlreadyExists | io::ErrorKind::CrossesDevices
+            ) =>
+        {
+            Err(e)
+        }
+        Err(_) if fs::symlink_metadata(to).is_ok() => Err(io::ErrorKind::AlreadyExists.into()),
+        Err(_) => fs::rename(from, to),
+    }
+}
+
+/// Directory holding `path`, `.` for a bare file name
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Hidden sibling of `path` used while replacing it, e.g. `.a.jpg.dedup-link`
+pub(super) fn temp_sibling(path: &Path, purpose: &str) -> PathBuf {
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".dedup-");
+    temp_name.push(purpose);
+    path.with_file_name(temp_name)
+}
+
+/// `name` with a counter before its extension, e.g. `a_2.jpg`
+pub(super) fn numbered_name(name: &OsStr, n: usize) -> OsString {
+    let path = Path::new(name);
+    let mut numbered = path.file_stem().unwrap_or(name).to_os_string();
+    numbered.push(format!("_{}", n));
+    if let Some(extension) = path.extension() {
+        numbered.push(".");
+        numbered.push(extension);
+    }
+    numbered
+}
+
+/// Replace `path` with a link made by `create_link`
+pub(super) fn replace_with_link(
+    path: &Path,
+    create_link: impl FnOnce(&Path) -> io::Result<()>,
+) -> Result<()> {
+    let temp = temp_sibling(path, "link");
+
+    create_link(&temp)?;
+    if let Err(e) = fs::rename(&temp, path) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Copy `from` over `to`, checking the copy against `expected` before it lands
+///
+/// The copy is fsynced before the rename, so `to` either keeps its old state
+/// or holds a complete, verified copy.
+pub(crate) fn replace_with_copy(from: &Path, to: &Path, expected: &Blake3Hash) -> Result<()> {
+    let temp = temp_sibling(to, "copy");
+    let copied = fs::copy(from, &temp)
+        .and_then(|_| File::open(&temp)?.sync_all())
+        .map_err(Error::from)
+        .and_then(|()| verify_hash(&temp, expected));
+    if let Err(e) = copied.and_then(|()| fs::rename(&temp, to).map_err(Error::from)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    sync_dir(parent_dir(to))
+}
+
+/// Check that the contents of `path` hash to `expected`
+pub(crate) fn verify_hash(path: &Path, expected: &Blake3Hash) -> Result<()> {
+    if compute_cryptographic(path)? != *expected {
+        return Err(Error::SafetyCheck(format!(
+            "{} does not match its recorded Blake3 hash",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Whether two files are on the same device, if the platform can tell
+#[cfg(unix)]
+pub(super) fn same_device(a: &fs::Metadata, b: &fs::Metadata) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+    Some(a.dev() == b.dev())
+}
+
+#[cfg(not(unix))]
+pub(super) fn same_device(_a: &fs::Metadata, _b: &fs::Metadata) -> Option<bool> {
+    // Linking across devices fails with an error of its own
+    None
+}
+
+/// Whether two paths are the same file, if the platform can tell
+#[cfg(unix)]
+pub(super) fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+    Some(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+pub(super) fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> Option<bool> {
+    None
+}
+
+#[cfg(unix)]
+pub(super) fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub(super) fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Flush a directory entry change (create, rename) to disk
+#[cfg(unix)]
+pub(crate) fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_dir: &Path) -> Result<()> {
+    // Directories can't be opened as files here; rely on the filesystem
+    Ok(())
+}
@@ -0,0 +1,625 @@
+/// Append-only journal of filesystem changes, used to undo a run
+///
+/// Every change is written as an intent record and fsynced *before* it is made,
+/// then confirmed with a done (or failed) record. Undo never trusts the records
+/// alone: it inspects the filesystem and verifies Blake3 hashes, so an intent
+/// left unconfirmed by a crash is rolled back safely whether or not the change
+/// actually happened.
+///
+/// Each run is one JSON-lines file, `<journal_dir>/<run_id>.jsonl`.
+///
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use blake3::Hash as Blake3Hash;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::file_ops::{
+    replace_with_copy, safe_move, same_file, sync_dir, temp_sibling, verify_hash,
+};
+use super::trash;
+use crate::error::{Error, Result};
+use crate::log_fs_modification;
+use crate::types::{ActionResult, ActionType};
+
+const JOURNAL_EXTENSION: &str = "jsonl";
+
+/// One filesystem change as recorded in the journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The action applied
+    pub action_type: ActionType,
+
+    /// The duplicate that was acted on
+    #[serde(with = "raw_path")]
+    pub source: PathBuf,
+
+    /// The original kept for its group
+    #[serde(with = "raw_path")]
+    pub original: PathBuf,
+
+    /// Where the duplicate was moved or trashed to, or what its link points at
+    #[serde(default, with = "raw_path::option")]
+    pub destination: Option<PathBuf>,
+
+    /// Blake3 hash of the duplicate before the change
+    #[serde(with = "hex_hash")]
+    pub hash: Blake3Hash,
+
+    /// Verified safety backup of the duplicate's contents, if one was made
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "raw_path::option"
+    )]
+    pub backup: Option<PathBuf>,
+
+    /// When the change was journalled (RFC 3339)
+    pub timestamp: String,
+}
+
+/// State of a journalled change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStatus {
+    /// Intent recorded but never confirmed, e.g. after a crash
+    Pending,
+
+    /// The change was made
+    Done,
+
+    /// The change failed
+    Failed,
+
+    /// The change was rolled back
+    Undone,
+
+    /// Undo found a file in the way holding other contents and left both
+    /// alone; it is tried again by the next undo
+    Conflict,
+}
+
+/// A journalled change with its current state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalItem {
+    /// Sequence number within the run
+    pub seq: u64,
+
+    /// The change
+    pub entry: JournalEntry,
+
+    /// Its current state
+    pub status: EntryStatus,
+}
+
+/// A line of the journal file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum JournalRecord {
+    Intent {
+        seq: u64,
+        #[serde(flatten)]
+        entry: JournalEntry,
+    },
+    Done {
+        seq: u64,
+    },
+    Failed {
+        seq: u64,
+        error: String,
+    },
+    Undone {
+        seq: u64,
+    },
+    Conflict {
+        seq: u64,
+        reason: String,
+    },
+}
+
+/// Writer for one run's journal
+#[derive(Debug)]
+pub struct Journal {
+    run_id: String,
+    file: File,
+    next_seq: u64,
+}
+
+impl Journal {
+    /// Start a new run's journal in `dir`
+    pub fn create(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let run_id = format!(
+            "{}-{}",
+            chrono::Utc::now().format("%Y%m%d-%H%M%S-%6f"),
+            std::process::id()
+        );
+        let file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(journal_path(dir, &run_id))?;
+        sync_dir(dir)?;
+
+        info!("Journalling run {} in {}", run_id, dir.display());
+        Ok(Self {
+            run_id,
+            file,
+            next_seq: 0,
+        })
+    }
+
+    /// Reopen an existing run's journal for appending
+    ///
+    /// A torn last line left by a crash is cut off first, so new records start
+    /// on a line of their own.
+    pub fn open(dir: &Path, run_id: &str) -> Result<Self> {
+        let next_seq = read_journal(dir, run_id)?
+            .last()
+            .map_or(0, |item| item.seq + 1);
+        let path = journal_path(dir, run_id);
+        let contents = fs::read(&path)?;
+        let complete = contents
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |newline| newline + 1);
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        if complete < contents.len() {
+            file.set_len(complete as u64)?;
+            file.sync_data()?;
+        }
+
+        Ok(Self {
+            run_id: run_id.to_string(),
+            file,
+            next_seq,
+        })
+    }
+
+    /// Identifier of the run, to pass to `undo`
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Record a change that is about to be made, returning its sequence number
+    pub fn record_intent(&mut self, entry: JournalEntry) -> Result<u64> {
+        let seq = self.next_seq;
+        self.append(&JournalRecord::Intent { seq, entry })?;
+        self.next_seq += 1;
+        Ok(seq)
+    }
+
+    /// Record that a change was made
+    pub fn record_done(&mut self, seq: u64) -> Result<()> {
+        self.append(&JournalRecord::Done { seq })
+    }
+
+    /// Record that a change failed
+    pub fn record_failed(&mut self, seq: u64, error: &str) -> Result<()> {
+        self.append(&JournalRecord::Failed {
+            seq,
+            error: error.to_string(),
+        })
+    }
+
+    /// Record that a change was rolled back
+    fn record_undone(&mut self, seq: u64) -> Result<()> {
+        self.append(&JournalRecord::Undone { seq })
+    }
+
+    /// Record that rolling back a change hit a conflict
+    fn record_conflict(&mut self, seq: u64, reason: &str) -> Result<()> {
+        self.append(&JournalRecord::Conflict {
+            seq,
+            reason: reason.to_string(),
+        })
+    }
+
+    /// Append a record and flush it to disk before returning
+    fn append(&mut self, record: &JournalRecord) -> Result<()> {
+        let mut line =
+            serde_json::to_string(record).map_err(|e| Error::Serialization(e.to_string()))?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl JournalEntry {
+    /// Describe a change about to be made, stamped with the current time
+    pub fn new(
+        action_type: ActionType,
+        source: &Path,
+        original: &Path,
+        destination: Option<&Path>,
+        hash: Blake3Hash,
+    ) -> Self {
+        Self {
+            action_type,
+            source: source.to_path_buf(),
+            original: original.to_path_buf(),
+            destination: destination.map(Path::to_path_buf),
+            hash,
+            backup: None,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Path of a run's journal file
+fn journal_path(dir: &Path, run_id: &str) -> PathBuf {
+    dir.join(run_id).with_extension(JOURNAL_EXTENSION)
+}
+
+/// List the runs journalled in `dir`, oldest first
+pub fn list_runs(dir: &Path) -> Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut runs: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == JOURNAL_EXTENSION))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    runs.sort();
+    Ok(runs)
+}
+
+/// Read a run's journal, folding confirmations into each entry's status
+///
+/// A torn final line (a crash mid-write) is skipped: its change was never
+/// started, since intents are flushed before anything is touched.
+pub fn read_journal(dir: &Path, run_id: &str) -> Result<Vec<JournalItem>> {
+    let path = journal_path(dir, run_id);
+    let file = File::open(&path).map_err(|_| Error::FileNotFound(path.clone()))?;
+
+    let lines: Vec<String> = BufReader::new(file)
+        .lines()
+        .collect::<std::io::Result<_>>()?;
+    let mut items: Vec<JournalItem> = Vec::new();
+    for (number, line) in lines.iter().enumerate() {
+        let record: JournalRecord = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(e) if number + 1 == lines.len() => {
+                warn!("Ignoring torn last line of {}: {}", path.display(), e);
+                break;
+            }
+            Err(e) => {
+                return Err(Error::Serialization(format!(
+                    "{} line {}: {}",
+                    path.display(),
+                    number + 1,
+                    e
+                )))
+            }
+        };
+
+        let (seq, status) = match record {
+            JournalRecord::Intent { seq, entry } => {
+                items.push(JournalItem {
+                    seq,
+                    entry,
+                    status: EntryStatus::Pending,
+                });
+                continue;
+            }
+            JournalRecord::Done { seq } => (seq, EntryStatus::Done),
+            JournalRecord::Failed { seq, .. } => (seq, EntryStatus::Failed),
+            JournalRecord::Undone { seq } => (seq, EntryStatus::Undone),
+            JournalRecord::Conflict { seq, .. } => (seq, EntryStatus::Conflict),
+        };
+        if let Some(item) = items.iter_mut().find(|item| item.seq == seq) {
+            item.status = status;
+        }
+    }
+
+    Ok(items)
+}
+
+/// Roll back a run, newest change first
+///
+/// Every restore is verified against the journalled Blake3 hash. Rolled back
+/// changes are recorded in the journal, so undo can safely be run again after
+/// an interruption. Returns one result per change that needed restoring.
+pub fn undo(dir: &Path, run_id: &str) -> Result<Vec<ActionResult>> {
+    let items = read_journal(dir, run_id)?;
+    let mut journal = Journal::open(dir, run_id)?;
+
+    let mut results = Vec::new();
+    for item in items.iter().rev() {
+        if item.status == EntryStatus::Undone {
+            continue;
+        }
+
+        let error = match restore(&item.entry) {
+            Ok(Restored::Restored) => {
+                journal.record_undone(item.seq)?;
+                None
+            }
+            Ok(Restored::Conflict(reason)) => {
+                warn!(
+                    "Undo conflict for {}: {}",
+                    item.entry.source.display(),
+                    reason
+                );
+                journal.record_conflict(item.seq, &reason)?;
+                Some(reason)
+            }
+            Err(e) => {
+                warn!("Undo failed for {}: {}", item.entry.source.display(), e);
+                Some(e.to_string())
+            }
+        };
+        results.push(ActionResult {
+            action_type: item.entry.action_type,
+            duplicate_path: item.entry.source.clone(),
+            original_path: item.entry.original.clone(),
+            destination: item.entry.destination.clone(),
+            success: error.is_none(),
+            error,
+        });
+    }
+
+    info!(
+        "Undo of run {}: {} of {} changes restored",
+        run_id,
+        results.iter().filter(|result| result.success).count(),
+        results.len()
+    );
+    Ok(results)
+}
+
+/// What undoing one change came to
+enum Restored {
+    /// The duplicate is back as it was
+    Restored,
+
+    /// A file in the way holds other contents, so nothing was touched
+    Conflict(String),
+}
+
+/// Put one journalled duplicate back as it was, whether or not the change happened
+fn restore(entry: &JournalEntry) -> Result<Restored> {
+    match entry.action_type {
+        ActionType::Move => return restore_moved(entry),
+        ActionType::Delete => {
+            if let Some(trashed) = entry.destination.as_deref() {
+                restore_trashed(entry, trashed)?;
+            } else if fs::symlink_metadata(&entry.source).is_err() {
+                restore_contents(entry)?;
+            }
+        }
+        ActionType::Symlink | ActionType::Hardlink => restore_linked(entry)?,
+    }
+    Ok(Restored::Restored)
+}
+
+/// Move a duplicate back from where it was moved to
+fn restore_moved(entry: &JournalEntry) -> Result<Restored> {
+    let destination = entry.destination.as_deref().ok_or_else(|| {
+        Error::Serialization(format!(
+            "journalled move of {} has no destination",
+            entry.source.display()
+        ))
+    })?;
+    let source_present = fs::symlink_metadata(&entry.source).is_ok();
+    let destination_present = fs::symlink_metadata(destination).is_ok();
+
+    match (source_present, destination_present) {
+        // Never moved, though a copy may have been interrupted
+        (true, false) => {
+            let temp = temp_sibling(destination, "copy");
+            if fs::symlink_metadata(&temp).is_ok() {
+                fs::remove_file(&temp)?;
+            }
+        }
+        (false, false) => return Err(Error::FileNotFound(destination.to_path_buf())),
+        (false, true) => {
+            verify_hash(destination, &entry.hash)?;
+            safe_move(destination, &entry.source, &entry.hash)?;
+            log_fs_modification!(
+                "UNDO MOVE",
+                entry.source,
+                Some(format!("from {}", destination.display()))
+            );
+        }
+        // Interrupted after placing the file but before unlinking the source,
+        // unless the destination was since replaced by another file
+        (true, true) => {
+            verify_hash(&entry.source, &entry.hash)?;
+            if verify_hash(destination, &entry.hash).is_err() {
+                return Ok(Restored::Conflict(format!(
+                    "{} holds other contents than the moved {}; both were left in place",
+                    destination.display(),
+                    entry.source.display()
+                )));
+            }
+            fs::remove_file(destination)?;
+        }
+    }
+    Ok(Restored::Restored)
+}
+
+/// Take a duplicate back out of the trash, along with its trash info
+///
+/// If the trash was emptied since, the duplicate is recreated instead.
+fn restore_trashed(entry: &JournalEntry, trashed: &Path) -> Result<()> {
+    let source_present = fs::symlink_metadata(&entry.source).is_ok();
+    let trashed_present = fs::symlink_metadata(trashed).is_ok();
+
+    match (source_present, trashed_present) {
+        // Never trashed, or already restored by the user
+        (true, _) => {}
+        (false, true) => {
+            verify_hash(trashed, &entry.hash)?;
+            safe_move(trashed, &entry.source, &entry.hash)?;
+            log_fs_modification!(
+                "UNDO TRASH",
+                entry.source,
+                Some(format!("from {}", trashed.display()))
+            );
+        }
+        (false, false) => restore_contents(entry)?,
+    }
+
+    // A reservation left by an interruption, or the info of the file taken back
+    if fs::symlink_metadata(trashed).is_err() {
+        if let Some(info) = trash::info_path(trashed).filter(|info| info.exists()) {
+            fs::remove_file(info)?;
+        }
+    }
+    Ok(())
+}
+
+/// Turn a duplicate replaced by a link back into a file of its own
+fn restore_linked(entry: &JournalEntry) -> Result<()> {
+    // A link left under its temporary name by an interruption
+    let temp = temp_sibling(&entry.source, "link");
+    if fs::symlink_metadata(&temp).is_ok() {
+        fs::remove_file(&temp)?;
+    }
+
+    let linked = match fs::symlink_metadata(&entry.source) {
+        Ok(metadata) if metadata.file_type().is_symlink() => true,
+        Ok(metadata) => {
+            // A hard link shares the original's inode; a file that doesn't was never replaced
+            entry.action_type == ActionType::Hardlink
+                && same_file(&metadata, &fs::metadata(&entry.original)?) != Some(false)
+        }
+        Err(_) => true,
+    };
+    if !linked {
+        return Ok(());
+    }
+    restore_contents(entry)
+}
+
+/// Recreate a duplicate from its safety backup, or else by copying the original
+///
+/// The original only serves if it still holds the duplicate's exact contents.
+fn restore_contents(entry: &JournalEntry) -> Result<()> {
+    let backup = entry
+        .backup
+        .as_deref()
+        .filter(|backup| verify_hash(backup, &entry.hash).is_ok());
+    let from = match backup {
+        Some(backup) => backup,
+        None => {
+            verify_hash(&entry.original, &entry.hash).map_err(|_| {
+                Error::SafetyCheck(format!(
+                    "cannot restore {}: no intact backup, and original {} no longer holds its contents",
+                    entry.source.display(),
+                    entry.original.display()
+                ))
+            })?;
+            &entry.original
+        }
+    };
+    replace_with_copy(from, &entry.source, &entry.hash)?;
+    log_fs_modification!(
+        "UNDO",
+        entry.source,
+        Some(format!("restored from {}", from.display()))
+    );
+    Ok(())
+}
+
+/// Serialize Blake3 hashes as hex strings, so journals and plans stay readable
+pub(super) mod hex_hash {
+    use blake3::Hash as Blake3Hash;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(hash: &Blake3Hash, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(hash.to_hex().as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Blake3Hash, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Blake3Hash::from_hex(hex).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serialize paths as strings when they are valid UTF-8 and as their raw bytes
+/// otherwise, so a file with a badly encoded name can still be journalled
+pub(super) mod raw_path {
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum RawPath {
+        Text(String),
+        Bytes { bytes: Vec<u8> },
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        match path.to_str() {
+            Some(text) => serializer.serialize_str(text),
+            None => RawPath::Bytes {
+                bytes: to_bytes::<S::Error>(path)?,
+            }
+            .serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        match RawPath::deserialize(deserializer)? {
+            RawPath::Text(text) => Ok(PathBuf::from(text)),
+            RawPath::Bytes { bytes } => from_bytes(bytes),
+        }
+    }
+
+    #[cfg(unix)]
+    fn to_bytes<E: serde::ser::Error>(path: &Path) -> Result<Vec<u8>, E> {
+        Ok(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec())
+    }
+
+    #[cfg(not(unix))]
+    fn to_bytes<E: serde::ser::Error>(path: &Path) -> Result<Vec<u8>, E> {
+        Err(E::custom(format!(
+            "path is not valid Unicode: {}",
+            path.display()
+        )))
+    }
+
+    #[cfg(unix)]
+    fn from_bytes<E: serde::de::Error>(bytes: Vec<u8>) -> Result<PathBuf, E> {
+        Ok(PathBuf::from(
+            <std::ffi::OsString as std::os::unix::ffi::OsStringExt>::from_vec(bytes),
+        ))
+    }
+
+    #[cfg(not(unix))]
+    fn from_bytes<E: serde::de::Error>(_bytes: Vec<u8>) -> Result<PathBuf, E> {
+        Err(E::custom("raw byte paths are only supported on Unix"))
+    }
+
+    pub mod option {
+        use std::path::PathBuf;
+
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            path: &Option<PathBuf>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match path {
+                Some(path) => super::serialize(path, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<PathBuf>, D::Error> {
+            #[derive(Deserialize)]
+            struct Wrapped(#[serde(with = "super")] PathBuf);
+
+            Ok(Option::<Wrapped>::deserialize(deserializer)?.map(|Wrapped(path)| path))
+        }
+    }
+}
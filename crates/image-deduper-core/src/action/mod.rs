@@ -0,0 +1,27 @@
+//! Actions on duplicate images
+//!
+//! Once the original in each group has been elected, the executor applies the
+//! configured action to every other file in the group:
+//! - Move it under `Config::duplicates_dir`, mirroring its directory structure
+//! - Delete it (`Config::delete_duplicates`), permanently or to the desktop
+//!   trash (`Config::delete_mode`)
+//! - Replace it with a symbolic link to the original (`Config::create_symlinks`)
+//! - Replace it with a hard link to the original (`Config::create_hardlinks`)
+//!
+//! Every change is journalled in `Config::journal_dir` before it is made, so a
+//! run can be rolled back with `undo`. For review before anything is touched,
+//! the actions can be written out as an `ActionPlan`, edited, and applied later.
+//!
+mod executor;
+pub(crate) mod file_ops;
+mod journal;
+mod plan;
+mod trash;
+
+pub use executor::{duplicate_destination, ActionExecutor, ActionRun};
+pub use journal::{list_runs, read_journal, undo, EntryStatus, Journal, JournalEntry, JournalItem};
+pub use plan::{ActionPlan, PlannedAction, PlannedDuplicate, PlannedFile, PlannedGroup};
+pub use trash::home_trash_dir;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,272 @@
+/// Reviewable action plans
+///
+/// A plan lists every duplicate group with its elected original, why it was
+/// elected and the action intended for each other file. It is written as JSON
+/// for review; any file can be switched to another action, or to `Keep`, before
+/// the plan is applied with `ActionExecutor::apply_plan`.
+///
+/// The size, modification time and Blake3 hash of every file are recorded, and
+/// checked again when the plan is applied, so nothing that changed after review
+/// is touched.
+///
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use blake3::Hash as Blake3Hash;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::deduplication::{Candidate, ElectedGroup};
+use crate::error::{Error, Result};
+use crate::processing::compute_cryptographic;
+use crate::types::ActionType;
+
+const PLAN_FORMAT_VERSION: u32 = 1;
+
+/// The proposed actions for a set of duplicate groups
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionPlan {
+    /// Plan file format version
+    pub version: u32,
+
+    /// When the plan was made (RFC 3339)
+    pub created: String,
+
+    /// One entry per duplicate group
+    pub groups: Vec<PlannedGroup>,
+}
+
+/// A duplicate group in a plan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedGroup {
+    /// The elected original, which is never acted on
+    pub original: PlannedFile,
+
+    /// Why the original was elected
+    pub reason: String,
+
+    /// Largest perceptual distance within the group (0 for exact duplicates)
+    pub max_distance: u32,
+
+    /// The other files in the group and what to do with each
+    pub duplicates: Vec<PlannedDuplicate>,
+}
+
+/// A file as it was when the plan was made
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    /// Path to the file
+    #[serde(with = "super::journal::raw_path")]
+    pub path: PathBuf,
+
+    /// File size in bytes
+    pub size: u64,
+
+    /// Last modified time
+    pub modified: SystemTime,
+
+    /// Blake3 hash of the contents
+    #[serde(with = "super::journal::hex_hash")]
+    pub hash: Blake3Hash,
+}
+
+/// A duplicate and the action intended for it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedDuplicate {
+    /// The duplicate as it was when the plan was made
+    #[serde(flatten)]
+    pub file: PlannedFile,
+
+    /// What to do with it
+    pub action: PlannedAction,
+}
+
+/// Action for one file in a plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlannedAction {
+    /// Leave the file alone
+    Keep,
+
+    /// Move to the duplicates directory
+    Move,
+
+    /// Delete the file
+    Delete,
+
+    /// Replace with a symbolic link to the original
+    Symlink,
+
+    /// Replace with a hard link to the original
+    Hardlink,
+}
+
+impl PlannedAction {
+    /// The action to perform, `None` for `Keep`
+    pub fn action_type(self) -> Option<ActionType> {
+        match self {
+            Self::Keep => None,
+            Self::Move => Some(ActionType::Move),
+            Self::Delete => Some(ActionType::Delete),
+            Self::Symlink => Some(ActionType::Symlink),
+            Self::Hardlink => Some(ActionType::Hardlink),
+        }
+    }
+}
+
+impl From<ActionType> for PlannedAction {
+    fn from(action_type: ActionType) -> Self {
+        match action_type {
+            ActionType::Move => Self::Move,
+            ActionType::Delete => Self::Delete,
+            ActionType::Symlink => Self::Symlink,
+            ActionType::Hardlink => Self::Hardlink,
+        }
+    }
+}
+
+impl PlannedFile {
+    /// Record the current size, modification time and hash of a file
+    pub fn capture(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path).map_err(|_| Error::FileNotFound(path.to_path_buf()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified: metadata.modified()?,
+            hash: compute_cryptographic(path)?,
+        })
+    }
+
+    /// Check that the file is still as recorded
+    pub fn verify(&self) -> Result<()> {
+        self.verify_metadata()?;
+        if compute_cryptographic(&self.path)? != self.hash {
+            return Err(self.changed("Blake3 hash"));
+        }
+        Ok(())
+    }
+
+    /// Check the size and modification time only
+    ///
+    /// Used when applying a plan, where the safety manager re-hashes the file.
+    pub(super) fn verify_metadata(&self) -> Result<()> {
+        let metadata =
+            fs::metadata(&self.path).map_err(|_| Error::FileNotFound(self.path.clone()))?;
+        if metadata.len() != self.size {
+            return Err(self.changed("size"));
+        }
+        if metadata.modified()? != self.modified {
+            return Err(self.changed("modification time"));
+        }
+        Ok(())
+    }
+
+    fn changed(&self, what: &str) -> Error {
+        Error::SafetyCheck(format!(
+            "{} changed since the plan was made ({} differs)",
+            self.path.display(),
+            what
+        ))
+    }
+}
+
+impl ActionPlan {
+    /// Plan `action_type` for every duplicate in `groups`
+    ///
+    /// Files that can't be read, or whose contents no longer match the Blake3
+    /// hash recorded at scan time, are left out with a warning, as is any group
+    /// whose original is left out.
+    pub fn from_groups(groups: &[ElectedGroup], action_type: ActionType) -> Self {
+        let planned = groups
+            .iter()
+            .filter_map(|group| {
+                let original = capture_or_warn(&group.original)?;
+                let duplicates: Vec<PlannedDuplicate> = group
+                    .duplicates
+                    .iter()
+                    .filter_map(|duplicate| {
+                        Some(PlannedDuplicate {
+                            file: capture_or_warn(duplicate)?,
+                            action: action_type.into(),
+                        })
+                    })
+                    .collect();
+
+                (!duplicates.is_empty()).then(|| PlannedGroup {
+                    original,
+                    reason: group.reason.to_string(),
+                    max_distance: group.max_distance,
+                    duplicates,
+                })
+            })
+            .collect();
+
+        Self {
+            version: PLAN_FORMAT_VERSION,
+            created: chrono::Utc::now().to_rfc3339(),
+            groups: planned,
+        }
+    }
+
+    /// Number of files the plan would act on
+    pub fn action_count(&self) -> usize {
+        self.groups
+            .iter()
+            .flat_map(|group| &group.duplicates)
+            .filter(|duplicate| duplicate.action != PlannedAction::Keep)
+            .count()
+    }
+
+    /// Write the plan as pretty-printed JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)
+            .map_err(|e| Error::Serialization(format!("Failed to encode plan: {}", e)))?;
+        writer.flush()?;
+
+        info!(
+            "Saved plan with {} groups ({} actions) to {}",
+            self.groups.len(),
+            self.action_count(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Load a plan previously written by `save`, possibly edited since
+    pub fn load(path: &Path) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let plan: Self = serde_json::from_reader(reader)
+            .map_err(|e| Error::Serialization(format!("Failed to decode plan: {}", e)))?;
+
+        if plan.version != PLAN_FORMAT_VERSION {
+            return Err(Error::Serialization(format!(
+                "Unsupported plan format version: {}",
+                plan.version
+            )));
+        }
+        Ok(plan)
+    }
+}
+
+/// Capture a candidate for a plan, warning if it can't be read or changed
+/// since the scan
+fn capture_or_warn(candidate: &Candidate) -> Option<PlannedFile> {
+    let path = &candidate.path;
+    let file = PlannedFile::capture(path)
+        .map_err(|e| warn!("Leaving {} out of the plan: {}", path.display(), e))
+        .ok()?;
+
+    if candidate
+        .crypto_hash
+        .is_some_and(|scanned| scanned != file.hash)
+    {
+        warn!(
+            "Leaving {} out of the plan: it changed since the scan",
+            path.display()
+        );
+        return None;
+    }
+    Some(file)
+}
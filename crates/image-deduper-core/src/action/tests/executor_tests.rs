@@ -0,0 +1,396 @@
+use std::fs;
+use std::sync::Arc;
+
+use tempfile::TempDir;
+
+use super::test_utils::{elected_group, live_config, scanned};
+use crate::action::{duplicate_destination, ActionExecutor};
+use crate::config::Config;
+use crate::deduplication::{ElectedGroup, ElectionReason};
+use crate::error::Error;
+use crate::safety::SafetyManager;
+use crate::types::ActionType;
+
+/// Test that moved duplicates keep their directory structure
+#[test]
+fn test_move_mirrors_directory_structure() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("photos/a.jpg");
+    let duplicate = dir.path().join("photos/2020/copy.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = live_config(dir.path());
+
+    let expected = duplicate_destination(&config.duplicates_dir, &duplicate).unwrap();
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap()
+        .results;
+
+    assert_eq!(results.len(), 1);
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert_eq!(results[0].action_type, ActionType::Move);
+    assert_eq!(results[0].destination.as_ref(), Some(&expected));
+    assert!(expected.starts_with(&config.duplicates_dir));
+    assert!(expected.ends_with("photos/2020/copy.jpg"));
+    assert!(!duplicate.exists());
+    assert!(original.exists());
+    assert_eq!(fs::read(&expected).unwrap(), b"image");
+}
+
+/// Test that a move never overwrites an existing file, taking a numbered name
+#[test]
+fn test_move_resolves_name_collision() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = live_config(dir.path());
+
+    let destination = duplicate_destination(&config.duplicates_dir, &duplicate).unwrap();
+    fs::create_dir_all(destination.parent().unwrap()).unwrap();
+    fs::write(&destination, b"unrelated").unwrap();
+
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap()
+        .results;
+
+    let renamed = destination.with_file_name("b_1.jpg");
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert_eq!(results[0].destination.as_ref(), Some(&renamed));
+    assert!(!duplicate.exists());
+    assert_eq!(fs::read(&destination).unwrap(), b"unrelated");
+    assert_eq!(fs::read(&renamed).unwrap(), b"image");
+}
+
+/// Test that a duplicate under a protected root is never touched
+#[test]
+fn test_protected_duplicate_is_refused() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("archive/b.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = Config {
+        delete_duplicates: true,
+        protected_roots: vec![dir.path().join("archive")],
+        ..live_config(dir.path())
+    };
+
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap()
+        .results;
+
+    assert!(!results[0].success);
+    assert!(results[0]
+        .error
+        .as_ref()
+        .unwrap()
+        .contains("Protected path"));
+    assert!(duplicate.exists());
+}
+
+/// Test deleting duplicates, which are backed up first
+#[test]
+fn test_delete() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = Config {
+        delete_duplicates: true,
+        ..live_config(dir.path())
+    };
+
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap()
+        .results;
+
+    assert!(results[0].success);
+    assert_eq!(results[0].action_type, ActionType::Delete);
+    assert!(!duplicate.exists());
+    assert!(original.exists());
+    let hex = blake3::hash(b"image").to_hex();
+    let backup = dir.path().join("backup").join(&hex[..2]).join(hex.as_str());
+    assert_eq!(fs::read(backup).unwrap(), b"image");
+}
+
+/// Test that a similar image's duplicate is deleted, though its contents
+/// differ from the original's
+#[test]
+fn test_delete_similar_duplicate() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    fs::write(&original, b"image").unwrap();
+    fs::write(&duplicate, b"resized image").unwrap();
+    let group = ElectedGroup {
+        original: scanned(&original),
+        duplicates: vec![scanned(&duplicate)],
+        reason: ElectionReason::PathOrder,
+        max_distance: 3,
+    };
+    let config = Config {
+        delete_duplicates: true,
+        ..live_config(dir.path())
+    };
+
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap()
+        .results;
+
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert!(!duplicate.exists());
+    assert_eq!(fs::read(&original).unwrap(), b"image");
+}
+
+/// Test replacing duplicates with symbolic links to the original
+#[test]
+fn test_symlink() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("sub/b.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = Config {
+        create_symlinks: true,
+        ..live_config(dir.path())
+    };
+
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap()
+        .results;
+
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert!(fs::symlink_metadata(&duplicate)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(
+        fs::canonicalize(&duplicate).unwrap(),
+        fs::canonicalize(&original).unwrap()
+    );
+}
+
+/// Test replacing duplicates with hard links to the original
+#[cfg(unix)]
+#[test]
+fn test_hardlink() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("album1/a.jpg");
+    let duplicate = dir.path().join("album2/a.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = Config {
+        create_hardlinks: true,
+        ..live_config(dir.path())
+    };
+    let executor = ActionExecutor::new(&config).unwrap();
+
+    let results = executor
+        .execute(std::slice::from_ref(&group))
+        .unwrap()
+        .results;
+
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert_eq!(results[0].action_type, ActionType::Hardlink);
+    let duplicate_meta = fs::metadata(&duplicate).unwrap();
+    assert_eq!(duplicate_meta.ino(), fs::metadata(&original).unwrap().ino());
+    assert_eq!(duplicate_meta.nlink(), 2);
+
+    // Running again finds the link already in place
+    let results = executor.execute(&[group]).unwrap().results;
+    assert!(results[0].success, "{:?}", results[0].error);
+}
+
+/// Test that a hard link is refused when the contents differ
+#[test]
+fn test_hardlink_refuses_different_contents() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let mut group = elected_group(&original, &duplicate);
+    fs::write(&duplicate, b"edited image").unwrap();
+    group.duplicates = vec![scanned(&duplicate)];
+    let config = Config {
+        create_hardlinks: true,
+        ..live_config(dir.path())
+    };
+
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap()
+        .results;
+
+    assert!(!results[0].success);
+    assert_eq!(fs::read(&duplicate).unwrap(), b"edited image");
+}
+
+/// Test that a dry run reports results without touching any file
+#[test]
+fn test_dry_run_changes_nothing() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = Config {
+        dry_run: true,
+        ..live_config(dir.path())
+    };
+
+    let run = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap();
+    let results = run.results;
+
+    assert_eq!(run.preflight_error, None);
+    assert!(results[0].success);
+    assert!(results[0].destination.is_some());
+    assert!(duplicate.exists());
+    assert!(!config.duplicates_dir.exists());
+}
+
+/// Test that a dry run does not mark files as removed for a later live run
+/// sharing its safety manager
+#[test]
+fn test_dry_run_keeps_known_copies() {
+    let dir = TempDir::new().unwrap();
+    let kept = dir.path().join("a.jpg");
+    let copy = dir.path().join("b.jpg");
+    let exact = elected_group(&copy, &kept);
+    let config = Config {
+        delete_duplicates: true,
+        ..live_config(dir.path())
+    };
+    let safety = Arc::new(SafetyManager::new(&config));
+
+    let dry_run = Config {
+        dry_run: true,
+        ..config.clone()
+    };
+    let results = ActionExecutor::new(&dry_run)
+        .unwrap()
+        .with_safety_manager(Arc::clone(&safety))
+        .execute(&[exact])
+        .unwrap()
+        .results;
+    assert!(results[0].success, "{:?}", results[0].error);
+
+    // `kept` still holds the contents, so `copy` may go
+    let similar = dir.path().join("c.jpg");
+    fs::write(&similar, b"similar image").unwrap();
+    let group = ElectedGroup {
+        original: scanned(&similar),
+        duplicates: vec![scanned(&copy)],
+        reason: ElectionReason::PathOrder,
+        max_distance: 3,
+    };
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .with_safety_manager(safety)
+        .execute(&[group])
+        .unwrap()
+        .results;
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert!(!copy.exists());
+    assert!(kept.exists());
+}
+
+/// Test that duplicates are kept when the original has gone
+#[test]
+fn test_missing_original_is_refused() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let group = elected_group(&original, &duplicate);
+    fs::remove_file(&original).unwrap();
+    let config = Config {
+        delete_duplicates: true,
+        ..live_config(dir.path())
+    };
+
+    let results = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap()
+        .results;
+
+    assert!(!results[0].success);
+    assert!(results[0].error.is_some());
+    assert!(duplicate.exists());
+}
+
+/// Test that conflicting action settings are rejected
+#[test]
+fn test_conflicting_actions_rejected() {
+    let config = Config {
+        delete_duplicates: true,
+        create_symlinks: true,
+        ..Default::default()
+    };
+    assert!(ActionExecutor::new(&config).is_err());
+
+    let config = Config {
+        create_symlinks: true,
+        create_hardlinks: true,
+        ..Default::default()
+    };
+    assert!(ActionExecutor::new(&config).is_err());
+}
+
+/// Test that a run over its limits changes nothing
+#[test]
+fn test_run_limits_abort_before_any_change() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = Config {
+        delete_duplicates: true,
+        max_files_per_run: Some(0),
+        ..live_config(dir.path())
+    };
+
+    let result = ActionExecutor::new(&config).unwrap().execute(&[group]);
+
+    assert!(matches!(result, Err(Error::SafetyCheck(_))), "{:?}", result);
+    assert!(duplicate.exists());
+    assert!(!config.journal_dir.exists());
+}
+
+/// Test that a dry run over its limits reports what a live run would be
+/// refused for, alongside its results
+#[test]
+fn test_dry_run_reports_preflight_failure() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let group = elected_group(&original, &duplicate);
+    let config = Config {
+        dry_run: true,
+        delete_duplicates: true,
+        max_files_per_run: Some(0),
+        ..live_config(dir.path())
+    };
+
+    let run = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap();
+
+    let reason = run.preflight_error.unwrap();
+    assert!(reason.contains("override_run_limits"), "{}", reason);
+    assert_eq!(run.results.len(), 1);
+    assert!(duplicate.exists());
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::io;
+
+use tempfile::TempDir;
+
+use crate::action::file_ops::{free_name, safe_move};
+use crate::error::Error;
+
+/// Test moving a file into a directory that doesn't exist yet
+#[test]
+fn test_safe_move() {
+    let dir = TempDir::new().unwrap();
+    let from = dir.path().join("a.jpg");
+    let to = dir.path().join("sub/dir/a.jpg");
+    fs::write(&from, b"image").unwrap();
+
+    safe_move(&from, &to, &blake3::hash(b"image")).unwrap();
+
+    assert!(!from.exists());
+    assert_eq!(fs::read(&to).unwrap(), b"image");
+}
+
+/// Test that an existing destination is never overwritten
+#[test]
+fn test_safe_move_never_overwrites() {
+    let dir = TempDir::new().unwrap();
+    let from = dir.path().join("a.jpg");
+    let to = dir.path().join("b.jpg");
+    fs::write(&from, b"image").unwrap();
+    fs::write(&to, b"unrelated").unwrap();
+
+    let result = safe_move(&from, &to, &blake3::hash(b"image"));
+
+    assert!(
+        matches!(&result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::AlreadyExists),
+        "{:?}",
+        result
+    );
+    assert_eq!(fs::read(&from).unwrap(), b"image");
+    assert_eq!(fs::read(&to).unwrap(), b"unrelated");
+}
+
+/// Test numbering taken names
+#[test]
+fn test_free_name() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("a.jpg");
+    assert_eq!(free_name(&path), path);
+
+    fs::write(&path, b"image").unwrap();
+    fs::write(dir.path().join("a_1.jpg"), b"image").unwrap();
+    assert_eq!(free_name(&path), dir.path().join("a_2.jpg"));
+
+    let no_extension = dir.path().join("README");
+    fs::write(&no_extension, b"text").unwrap();
+    assert_eq!(free_name(&no_extension), dir.path().join("README_1"));
+}
@@ -0,0 +1,284 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use tempfile::TempDir;
+
+use super::test_utils::{elected_group, live_config};
+use crate::action::{
+    list_runs, read_journal, undo, ActionExecutor, EntryStatus, Journal, JournalEntry,
+};
+use crate::config::Config;
+use crate::deduplication::ElectedGroup;
+use crate::types::ActionType;
+
+/// Run `config`'s action on one group and return the journal run id
+fn run(config: &Config, group: ElectedGroup) -> String {
+    let run = ActionExecutor::new(config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap();
+    assert!(run.results.iter().all(|result| result.success));
+    run.run_id.unwrap()
+}
+
+/// Test that a move is journalled and undone
+#[test]
+fn test_undo_move() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("sub/b.jpg");
+    let config = live_config(dir.path());
+    let run_id = run(&config, elected_group(&original, &duplicate));
+    assert!(!duplicate.exists());
+
+    let items = read_journal(&config.journal_dir, &run_id).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].status, EntryStatus::Done);
+    assert_eq!(items[0].entry.hash, blake3::hash(b"image"));
+
+    let results = undo(&config.journal_dir, &run_id).unwrap();
+
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert_eq!(fs::read(&duplicate).unwrap(), b"image");
+    assert!(!items[0].entry.destination.as_ref().unwrap().exists());
+    let items = read_journal(&config.journal_dir, &run_id).unwrap();
+    assert_eq!(items[0].status, EntryStatus::Undone);
+
+    // Undoing again has nothing left to do
+    assert!(undo(&config.journal_dir, &run_id).unwrap().is_empty());
+}
+
+/// Test that a file whose name is not valid UTF-8 is moved, journalled and undone
+#[cfg(unix)]
+#[test]
+fn test_undo_move_of_non_utf8_name() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join(OsStr::from_bytes(b"caf\xe9.jpg"));
+    let config = live_config(dir.path());
+    let run_id = run(&config, elected_group(&original, &duplicate));
+    assert!(!duplicate.exists());
+
+    let items = read_journal(&config.journal_dir, &run_id).unwrap();
+    assert_eq!(items[0].entry.source, duplicate);
+
+    let results = undo(&config.journal_dir, &run_id).unwrap();
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert_eq!(fs::read(&duplicate).unwrap(), b"image");
+}
+
+/// Test that symlinked and deleted exact duplicates are restored from the original
+#[test]
+fn test_undo_symlink_and_delete() {
+    for config_action in [ActionType::Symlink, ActionType::Delete] {
+        let dir = TempDir::new().unwrap();
+        let original = dir.path().join("a.jpg");
+        let duplicate = dir.path().join("b.jpg");
+        let config = Config {
+            create_symlinks: config_action == ActionType::Symlink,
+            delete_duplicates: config_action == ActionType::Delete,
+            ..live_config(dir.path())
+        };
+        let run_id = run(&config, elected_group(&original, &duplicate));
+
+        let results = undo(&config.journal_dir, &run_id).unwrap();
+
+        assert!(results[0].success, "{:?}", results[0].error);
+        let metadata = fs::symlink_metadata(&duplicate).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(fs::read(&duplicate).unwrap(), b"image");
+    }
+}
+
+/// Test that a hard link is broken back into a separate file
+#[cfg(unix)]
+#[test]
+fn test_undo_hardlink() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let config = Config {
+        create_hardlinks: true,
+        ..live_config(dir.path())
+    };
+    let run_id = run(&config, elected_group(&original, &duplicate));
+    assert_eq!(fs::metadata(&original).unwrap().nlink(), 2);
+
+    let results = undo(&config.journal_dir, &run_id).unwrap();
+
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert_eq!(fs::metadata(&original).unwrap().nlink(), 1);
+    assert_eq!(fs::read(&duplicate).unwrap(), b"image");
+}
+
+/// Test that a deleted duplicate is restored from its backup after the original changed
+#[test]
+fn test_undo_restores_from_backup() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let config = Config {
+        delete_duplicates: true,
+        ..live_config(dir.path())
+    };
+    let run_id = run(&config, elected_group(&original, &duplicate));
+    fs::write(&original, b"edited image").unwrap();
+
+    let items = read_journal(&config.journal_dir, &run_id).unwrap();
+    assert!(items[0].entry.backup.is_some());
+    let results = undo(&config.journal_dir, &run_id).unwrap();
+
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert_eq!(fs::read(&duplicate).unwrap(), b"image");
+}
+
+/// Test that a deleted duplicate isn't faked from an original that changed since
+#[test]
+fn test_undo_refuses_unrecoverable_contents() {
+    let dir = TempDir::new().unwrap();
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let config = Config {
+        delete_duplicates: true,
+        backup_dir: None,
+        ..live_config(dir.path())
+    };
+    let run_id = run(&config, elected_group(&original, &duplicate));
+    fs::write(&original, b"edited image").unwrap();
+
+    let results = undo(&config.journal_dir, &run_id).unwrap();
+
+    assert!(!results[0].success);
+    assert!(!duplicate.exists());
+    let items = read_journal(&config.journal_dir, &run_id).unwrap();
+    assert_eq!(items[0].status, EntryStatus::Done);
+}
+
+/// Test rolling back intents left unconfirmed by a crash, done or not
+#[test]
+fn test_undo_after_crash() {
+    let dir = TempDir::new().unwrap();
+    let journal_dir = dir.path().join("journal");
+    let moved = dir.path().join("moved.jpg");
+    let moved_to = dir.path().join("duplicates/moved.jpg");
+    let untouched = dir.path().join("untouched.jpg");
+    let original = dir.path().join("a.jpg");
+    fs::write(&original, b"image").unwrap();
+    fs::write(&moved, b"image").unwrap();
+    fs::write(&untouched, b"image").unwrap();
+
+    let mut journal = Journal::create(&journal_dir).unwrap();
+    let hash = blake3::hash(b"image");
+    journal
+        .record_intent(JournalEntry::new(
+            ActionType::Move,
+            &moved,
+            &original,
+            Some(&moved_to),
+            hash,
+        ))
+        .unwrap();
+    fs::create_dir_all(moved_to.parent().unwrap()).unwrap();
+    fs::rename(&moved, &moved_to).unwrap();
+    journal
+        .record_intent(JournalEntry::new(
+            ActionType::Delete,
+            &untouched,
+            &original,
+            None,
+            hash,
+        ))
+        .unwrap();
+    let run_id = journal.run_id().to_string();
+    drop(journal);
+
+    // The crash also tore the next line
+    let journal_file = journal_dir.join(format!("{}.jsonl", run_id));
+    let mut file = OpenOptions::new().append(true).open(journal_file).unwrap();
+    file.write_all(b"{\"record\":\"inte").unwrap();
+
+    let items = read_journal(&journal_dir, &run_id).unwrap();
+    assert_eq!(items.len(), 2);
+    assert!(items.iter().all(|item| item.status == EntryStatus::Pending));
+
+    let results = undo(&journal_dir, &run_id).unwrap();
+
+    assert!(results.iter().all(|result| result.success), "{:?}", results);
+    assert_eq!(fs::read(&moved).unwrap(), b"image");
+    assert!(!moved_to.exists());
+    assert_eq!(fs::read(&untouched).unwrap(), b"image");
+    let items = read_journal(&journal_dir, &run_id).unwrap();
+    assert!(items.iter().all(|item| item.status == EntryStatus::Undone));
+    assert_eq!(list_runs(&journal_dir).unwrap(), vec![run_id]);
+}
+
+/// Test that an interrupted move whose destination was since replaced by
+/// another file is reported as a conflict, leaving both files alone
+#[test]
+fn test_undo_move_conflict() {
+    let dir = TempDir::new().unwrap();
+    let journal_dir = dir.path().join("journal");
+    let original = dir.path().join("a.jpg");
+    let duplicate = dir.path().join("b.jpg");
+    let destination = dir.path().join("duplicates/b.jpg");
+    fs::write(&original, b"image").unwrap();
+    fs::write(&duplicate, b"image").unwrap();
+    fs::create_dir_all(destination.parent().unwrap()).unwrap();
+    fs::write(&destination, b"someone else's file").unwrap();
+
+    let mut journal = Journal::create(&journal_dir).unwrap();
+    journal
+        .record_intent(JournalEntry::new(
+            ActionType::Move,
+            &duplicate,
+            &original,
+            Some(&destination),
+            blake3::hash(b"image"),
+        ))
+        .unwrap();
+    let run_id = journal.run_id().to_string();
+    drop(journal);
+
+    let results = undo(&journal_dir, &run_id).unwrap();
+    assert!(!results[0].success);
+    assert!(results[0]
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("other contents"));
+    assert_eq!(fs::read(&duplicate).unwrap(), b"image");
+    assert_eq!(fs::read(&destination).unwrap(), b"someone else's file");
+    let items = read_journal(&journal_dir, &run_id).unwrap();
+    assert_eq!(items[0].status, EntryStatus::Conflict);
+
+    // Once the conflict is resolved, undo finishes the job
+    fs::remove_file(&destination).unwrap();
+    let results = undo(&journal_dir, &run_id).unwrap();
+    assert!(results[0].success, "{:?}", results[0].error);
+    let items = read_journal(&journal_dir, &run_id).unwrap();
+    assert_eq!(items[0].status, EntryStatus::Undone);
+}
+
+/// Test that dry runs write no journal
+#[test]
+fn test_dry_run_has_no_journal() {
+    let dir = TempDir::new().unwrap();
+    let config = Config {
+        dry_run: true,
+        ..live_config(dir.path())
+    };
+    let group = elected_group(&dir.path().join("a.jpg"), &dir.path().join("b.jpg"));
+
+    let run = ActionExecutor::new(&config)
+        .unwrap()
+        .execute(&[group])
+        .unwrap();
+
+    assert!(run.run_id.is_none());
+    assert!(list_runs(&config.journal_dir).unwrap().is_empty());
+}
@@ -0,0 +1,7 @@
+// Tests for the action module
+mod executor_tests;
+mod file_ops_tests;
+mod journal_tests;
+mod plan_tests;
+mod test_utils;
+mod trash_tests;
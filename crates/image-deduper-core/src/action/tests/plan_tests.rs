@@ -0,0 +1,112 @@
+use std::fs;
+
+use tempfile::TempDir;
+
+use super::test_utils::{elected_group_in, live_config};
+use crate::action::{ActionExecutor, ActionPlan, PlannedAction};
+use crate::deduplication::ElectionReason;
+use crate::types::ActionType;
+
+/// Test that a plan records every group and survives a save and load
+#[test]
+fn test_plan_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let group = elected_group_in(dir.path(), "a.jpg", &["b.jpg", "c.jpg"]);
+    let plan = ActionPlan::from_groups(&[group], ActionType::Delete);
+
+    assert_eq!(plan.groups.len(), 1);
+    assert_eq!(plan.groups[0].original.path, dir.path().join("a.jpg"));
+    assert_eq!(plan.groups[0].original.size, 5);
+    assert_eq!(plan.groups[0].original.hash, blake3::hash(b"image"));
+    assert_eq!(plan.groups[0].reason, ElectionReason::PathOrder.to_string());
+    assert_eq!(plan.action_count(), 2);
+
+    let plan_path = dir.path().join("plan.json");
+    plan.save(&plan_path).unwrap();
+    let json = fs::read_to_string(&plan_path).unwrap();
+    assert!(json.contains("\"action\": \"Delete\""));
+    assert!(json.contains(&blake3::hash(b"image").to_hex().to_string()));
+
+    assert_eq!(ActionPlan::load(&plan_path).unwrap(), plan);
+}
+
+/// Test applying an edited plan: kept files and changed files are left alone
+#[test]
+fn test_apply_edited_plan() {
+    let dir = TempDir::new().unwrap();
+    let group = elected_group_in(dir.path(), "a.jpg", &["b.jpg", "c.jpg", "d.jpg"]);
+    let mut plan = ActionPlan::from_groups(&[group], ActionType::Delete);
+
+    // Reviewer keeps c.jpg; d.jpg is edited after the plan was made
+    plan.groups[0].duplicates[1].action = PlannedAction::Keep;
+    fs::write(dir.path().join("d.jpg"), b"edited image").unwrap();
+
+    let config = live_config(dir.path());
+    let run = ActionExecutor::new(&config)
+        .unwrap()
+        .apply_plan(&plan)
+        .unwrap();
+
+    assert_eq!(run.results.len(), 2);
+    assert!(run.results[0].success, "{:?}", run.results[0].error);
+    assert!(!dir.path().join("b.jpg").exists());
+    assert!(dir.path().join("c.jpg").exists());
+    assert!(!run.results[1].success);
+    assert!(run.results[1]
+        .error
+        .as_ref()
+        .unwrap()
+        .contains("changed since the plan"));
+    assert!(dir.path().join("d.jpg").exists());
+    assert!(run.run_id.is_some());
+}
+
+/// Test that a plan is refused when its original changed
+#[test]
+fn test_apply_plan_with_changed_original() {
+    let dir = TempDir::new().unwrap();
+    let group = elected_group_in(dir.path(), "a.jpg", &["b.jpg"]);
+    let plan = ActionPlan::from_groups(&[group], ActionType::Delete);
+    fs::write(dir.path().join("a.jpg"), b"replaced").unwrap();
+
+    let run = ActionExecutor::new(&live_config(dir.path()))
+        .unwrap()
+        .apply_plan(&plan)
+        .unwrap();
+
+    assert!(!run.results[0].success);
+    assert!(dir.path().join("b.jpg").exists());
+}
+
+/// Test that files changed since the scan are left out of a plan
+#[test]
+fn test_plan_leaves_out_files_changed_since_scan() {
+    let dir = TempDir::new().unwrap();
+    let group = elected_group_in(dir.path(), "a.jpg", &["b.jpg", "c.jpg"]);
+    fs::write(dir.path().join("c.jpg"), b"edited image").unwrap();
+
+    let plan = ActionPlan::from_groups(std::slice::from_ref(&group), ActionType::Delete);
+    assert_eq!(plan.groups[0].duplicates.len(), 1);
+    assert_eq!(
+        plan.groups[0].duplicates[0].file.path,
+        dir.path().join("b.jpg")
+    );
+
+    // A changed original drops the whole group
+    fs::write(dir.path().join("a.jpg"), b"edited image").unwrap();
+    assert!(ActionPlan::from_groups(&[group], ActionType::Delete)
+        .groups
+        .is_empty());
+}
+
+/// Test that plans from an unknown format version are rejected
+#[test]
+fn test_load_rejects_unknown_version() {
+    let dir = TempDir::new().unwrap();
+    let mut plan = ActionPlan::from_groups(&[], ActionType::Move);
+    plan.version = 99;
+    let plan_path = dir.path().join("plan.json");
+    plan.save(&plan_path).unwrap();
+
+    assert!(ActionPlan::load(&plan_path).is_err());
+}
@@ -0,0 +1,54 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::deduplication::{Candidate, ElectedGroup, ElectionReason};
+
+/// Write `original` and `duplicate` with the same contents and group them
+pub fn elected_group(original: &Path, duplicate: &Path) -> ElectedGroup {
+    for path in [original, duplicate] {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"image").unwrap();
+    }
+    ElectedGroup {
+        original: scanned(original),
+        duplicates: vec![scanned(duplicate)],
+        reason: ElectionReason::PathOrder,
+        max_distance: 0,
+    }
+}
+
+/// Write an original and its duplicates, named relative to `dir`, with the
+/// same contents and group them
+pub fn elected_group_in(dir: &Path, original: &str, duplicates: &[&str]) -> ElectedGroup {
+    let write = |name: &str| {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"image").unwrap();
+        scanned(&path)
+    };
+    ElectedGroup {
+        original: write(original),
+        duplicates: duplicates.iter().map(|name| write(name)).collect(),
+        reason: ElectionReason::PathOrder,
+        max_distance: 0,
+    }
+}
+
+/// A candidate with the hash its file has now, as a scan would record
+pub fn scanned(path: &Path) -> Candidate {
+    Candidate::inspect(path, Some(blake3::hash(&fs::read(path).unwrap())))
+}
+
+/// A config performing real changes, moving duplicates under `dir`
+pub fn live_config(dir: &Path) -> Config {
+    Config {
+        dry_run: false,
+        duplicates_dir: dir.join("duplicates"),
+        journal_dir: dir.join("journal"),
+        backup_dir: Some(dir.join("backup")),
+        ..Default::default()
+    }
+}
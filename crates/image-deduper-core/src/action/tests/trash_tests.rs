@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use super::test_utils::elected_group_in;
+use crate::action::{read_journal, undo, ActionExecutor};
+use crate::config::{Config, DeleteMode};
+use crate::deduplication::ElectedGroup;
+
+/// Delete `group`'s duplicates to the trash in `dir`, returning the journal run id
+fn trash_group(dir: &Path, group: ElectedGroup) -> String {
+    let config = Config {
+        dry_run: false,
+        delete_duplicates: true,
+        delete_mode: DeleteMode::Trash,
+        journal_dir: dir.join("journal"),
+        backup_dir: None,
+        ..Default::default()
+    };
+    let run = ActionExecutor::new(&config)
+        .unwrap()
+        .with_trash_dir(&dir.join("Trash"))
+        .execute(&[group])
+        .unwrap();
+    assert!(
+        run.results.iter().all(|result| result.success),
+        "{:?}",
+        run.results
+    );
+    run.run_id.unwrap()
+}
+
+/// Test that a trashed duplicate gets a trash info entry and can be undone
+#[test]
+fn test_trash_and_undo() {
+    let dir = TempDir::new().unwrap();
+    let group = elected_group_in(dir.path(), "a.jpg", &["photo 100%.jpg"]);
+    let duplicate = dir.path().join("photo 100%.jpg");
+    let location = fs::canonicalize(&duplicate).unwrap();
+
+    let run_id = trash_group(dir.path(), group);
+
+    let trashed = dir.path().join("Trash/files/photo 100%.jpg");
+    let info_path = dir.path().join("Trash/info/photo 100%.jpg.trashinfo");
+    assert!(!duplicate.exists());
+    assert_eq!(fs::read(&trashed).unwrap(), b"image");
+    let info = fs::read_to_string(&info_path).unwrap();
+    let expected_path = location
+        .to_str()
+        .unwrap()
+        .replace(' ', "%20")
+        .replace("100%", "100%25");
+    assert!(info.starts_with("[Trash Info]\n"), "{}", info);
+    assert!(
+        info.contains(&format!("\nPath={}\n", expected_path)),
+        "{}",
+        info
+    );
+    assert!(info.contains("\nDeletionDate="), "{}", info);
+
+    let journal_dir = dir.path().join("journal");
+    let items = read_journal(&journal_dir, &run_id).unwrap();
+    assert_eq!(
+        items[0].entry.destination.as_deref(),
+        Some(trashed.as_path())
+    );
+
+    let results = undo(&journal_dir, &run_id).unwrap();
+
+    assert!(results[0].success, "{:?}", results[0].error);
+    assert_eq!(fs::read(&duplicate).unwrap(), b"image");
+    assert!(!trashed.exists());
+    assert!(!info_path.exists());
+}
+
+/// Test that files with the same name get distinct places in the trash
+#[test]
+fn test_trash_name_collision() {
+    let dir = TempDir::new().unwrap();
+    let group = elected_group_in(dir.path(), "a.jpg", &["x/b.jpg", "y/b.jpg"]);
+
+    trash_group(dir.path(), group);
+
+    for name in ["b.jpg", "b_1.jpg"] {
+        assert!(dir.path().join("Trash/files").join(name).exists());
+        let info = dir.path().join(format!("Trash/info/{}.trashinfo", name));
+        assert!(info.exists());
+    }
+}
+
+/// Test that a symlink is reserved under its own name and location, not its
+/// target's
+#[cfg(unix)]
+#[test]
+fn test_reserve_symlink_keeps_its_location() {
+    use crate::action::trash::reserve;
+
+    let dir = TempDir::new().unwrap();
+    let target = dir.path().join("real/x.jpg");
+    fs::create_dir_all(target.parent().unwrap()).unwrap();
+    fs::write(&target, b"image").unwrap();
+    let link = dir.path().join("link.jpg");
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    let trashed = reserve(&link, &dir.path().join("Trash")).unwrap();
+
+    assert_eq!(trashed, dir.path().join("Trash/files/link.jpg"));
+    let info = fs::read_to_string(dir.path().join("Trash/info/link.jpg.trashinfo")).unwrap();
+    let location = fs::canonicalize(dir.path()).unwrap().join("link.jpg");
+    assert!(
+        info.contains(&format!("\nPath={}\n", location.display())),
+        "{}",
+        info
+    );
+}
+
+/// Test choosing the trash at the top of another volume
+#[cfg(unix)]
+#[test]
+fn test_volume_trash_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::action::trash::volume_trash_dir;
+
+    let dir = TempDir::new().unwrap();
+    let uid = unsafe { libc::getuid() };
+    let own = dir.path().join(format!(".Trash-{}", uid));
+
+    assert_eq!(volume_trash_dir(dir.path()).unwrap(), own);
+
+    // A shared .Trash is only trusted with the sticky bit set
+    let shared = dir.path().join(".Trash");
+    fs::create_dir(&shared).unwrap();
+    assert_eq!(volume_trash_dir(dir.path()).unwrap(), own);
+
+    fs::set_permissions(&shared, fs::Permissions::from_mode(0o1777)).unwrap();
+    assert_eq!(
+        volume_trash_dir(dir.path()).unwrap(),
+        shared.join(uid.to_string())
+    );
+}
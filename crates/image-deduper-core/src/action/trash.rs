@@ -0,0 +1,208 @@
+/// Deleting to the desktop trash, following the freedesktop.org Trash specification
+///
+/// A file is trashed by first writing `info/<name>.trashinfo`, which reserves
+/// `<name>` and records where the file came from, then renaming the file to
+/// `files/<name>`. Files on the same filesystem as the home trash
+/// (`$XDG_DATA_HOME/Trash`) go there. Files on other mounts go to the trash at
+/// the top of their volume: `$topdir/.Trash/$uid` when the administrator has
+/// set up a sticky `.Trash` directory, otherwise `$topdir/.Trash-$uid`.
+///
+use std::ffi::OsString;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use percent_encoding::{percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use super::file_ops::{numbered_name, sync_dir};
+use crate::error::{Error, Result};
+
+const INFO_EXTENSION: &str = "trashinfo";
+
+/// Bytes left unescaped in a trash info `Path`, as in a URI path
+const PATH_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// The user's home trash, `$XDG_DATA_HOME/Trash`
+pub fn home_trash_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|data| data.join("Trash"))
+}
+
+/// Reserve a place in the trash for `path` by writing its trash info
+///
+/// Returns the path under `files/` the file must then be moved to with `put`.
+pub(super) fn reserve(path: &Path, home_trash: &Path) -> Result<PathBuf> {
+    // Only the directory is resolved: a symlink is trashed as itself
+    let path = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if parent.as_os_str().is_empty() => {
+            fs::canonicalize(".")?.join(name)
+        }
+        (Some(parent), Some(name)) => fs::canonicalize(parent)?.join(name),
+        _ => return Err(Error::FileNotFound(path.to_path_buf())),
+    };
+    let (trash, topdir) = trash_for(&path, home_trash)?;
+    let files = trash.join("files");
+    let info = trash.join("info");
+    create_private_dir(&files)?;
+    create_private_dir(&info)?;
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::FileNotFound(path.clone()))?;
+    // Paths in a volume's trash are relative to the top of the volume
+    let location = topdir
+        .as_deref()
+        .and_then(|topdir| path.strip_prefix(topdir).ok())
+        .unwrap_or(&path);
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(location),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    for n in 0.. {
+        let candidate = match n {
+            0 => name.to_os_string(),
+            n => numbered_name(name, n),
+        };
+        let trashed = files.join(&candidate);
+        if fs::symlink_metadata(&trashed).is_ok() {
+            continue;
+        }
+
+        let info_path = info.join(info_file_name(candidate));
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Err(e) = file
+            .write_all(contents.as_bytes())
+            .and_then(|()| file.sync_all())
+        {
+            let _ = fs::remove_file(&info_path);
+            return Err(e.into());
+        }
+        sync_dir(&info)?;
+
+        debug!("Reserved {} in the trash", trashed.display());
+        return Ok(trashed);
+    }
+    unreachable!("ran out of trash names for {}", path.display())
+}
+
+/// Move `path` to the place reserved for it in the trash
+///
+/// The reservation is released if the move fails.
+pub(super) fn put(path: &Path, trashed: &Path) -> Result<()> {
+    // The trash is on the file's own filesystem, so this never copies
+    if let Err(e) = fs::rename(path, trashed) {
+        if let Some(info) = info_path(trashed) {
+            let _ = fs::remove_file(info);
+        }
+        return Err(e.into());
+    }
+    if let Some(files) = trashed.parent() {
+        sync_dir(files)?;
+    }
+    Ok(())
+}
+
+/// The trash info file describing a trashed file
+pub(super) fn info_path(trashed: &Path) -> Option<PathBuf> {
+    let trash = trashed.parent()?.parent()?;
+    Some(
+        trash
+            .join("info")
+            .join(info_file_name(trashed.file_name()?.to_os_string())),
+    )
+}
+
+fn info_file_name(name: OsString) -> OsString {
+    let mut info_name = name;
+    info_name.push(".");
+    info_name.push(INFO_EXTENSION);
+    info_name
+}
+
+/// The trash an absolute `path` belongs in, with the top of its volume when
+/// that isn't the home trash
+#[cfg(unix)]
+fn trash_for(path: &Path, home_trash: &Path) -> Result<(PathBuf, Option<PathBuf>)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = fs::symlink_metadata(path)?.dev();
+    create_private_dir(home_trash)?;
+    if fs::metadata(home_trash)?.dev() == device {
+        return Ok((home_trash.to_path_buf(), None));
+    }
+
+    // The topmost directory still on the file's device
+    let mut topdir = path.parent().unwrap_or(path);
+    for ancestor in path.ancestors().skip(2) {
+        match fs::metadata(ancestor) {
+            Ok(metadata) if metadata.dev() == device => topdir = ancestor,
+            _ => break,
+        }
+    }
+    Ok((volume_trash_dir(topdir)?, Some(topdir.to_path_buf())))
+}
+
+#[cfg(not(unix))]
+fn trash_for(_path: &Path, home_trash: &Path) -> Result<(PathBuf, Option<PathBuf>)> {
+    create_private_dir(home_trash)?;
+    Ok((home_trash.to_path_buf(), None))
+}
+
+/// The current user's trash at the top of a volume
+///
+/// `$topdir/.Trash/$uid` is only used under a `.Trash` directory with the
+/// sticky bit set, as the specification requires; otherwise the user's own
+/// `$topdir/.Trash-$uid`.
+#[cfg(unix)]
+pub(super) fn volume_trash_dir(topdir: &Path) -> Result<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // SAFETY: getuid has no preconditions and cannot fail
+    let uid = unsafe { libc::getuid() };
+    let shared = topdir.join(".Trash");
+    match fs::symlink_metadata(&shared) {
+        Ok(metadata) if metadata.is_dir() && metadata.permissions().mode() & 0o1000 != 0 => {
+            let trash = shared.join(uid.to_string());
+            if create_private_dir(&trash).is_ok() {
+                return Ok(trash);
+            }
+        }
+        Ok(_) => debug!("Ignoring {}: not a sticky directory", shared.display()),
+        Err(_) => {}
+    }
+    Ok(topdir.join(format!(".Trash-{}", uid)))
+}
+
+/// Create a directory and its parents, readable only by the user
+fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)?;
+    Ok(())
+}
+
+/// Percent-encode a path for a trash info file
+fn encode_path(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().replace('\\', "/").into_bytes();
+    percent_encode(&bytes, PATH_SET).to_string()
+}
@@ -1,7 +1,13 @@
 use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::types::ImageFormat;
 
 /// Priority rules for choosing which image to keep as original
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Rules are applied in order as tie-breakers: each rule only decides between
+/// the images that all earlier rules ranked equally.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PriorityRule {
     /// Prefer higher resolution images
     HighestResolution,
@@ -9,11 +15,11 @@ pub enum PriorityRule {
     /// Prefer images with earlier creation date
     OldestCreationDate,
 
-    /// Prefer images with specific file format
-    PreferredFormat,
+    /// Prefer images whose format comes earliest in the list
+    PreferredFormat(Vec<ImageFormat>),
 
-    /// Prefer images in specific directories
-    PreferredDirectory,
+    /// Prefer images under the directory that comes earliest in the list
+    PreferredDirectory(Vec<PathBuf>),
 
     /// Prefer smallest file size
     SmallestFileSize,
@@ -22,6 +28,78 @@ pub enum PriorityRule {
     LargestFileSize,
 }
 
+/// Index used to search for near-duplicate perceptual hashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimilarityIndexKind {
+    /// Metric tree over Hamming distance (exact)
+    BkTree,
+
+    /// Multi-index hashing over disjoint bit substrings (exact)
+    MultiIndex {
+        /// Number of substrings the hash is split into
+        substrings: usize,
+    },
+
+    /// Bit-sampling locality-sensitive hashing (approximate, may miss matches)
+    Lsh {
+        /// Number of hash tables
+        tables: usize,
+
+        /// Number of sampled bits per table (at most 64)
+        bits_per_table: usize,
+    },
+}
+
+/// How near-duplicate pairs are merged into groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterLinkage {
+    /// Transitive grouping: A~B and B~C put A, B and C together (union-find)
+    Single,
+
+    /// Every pair of images in a group must be within the threshold
+    Complete,
+
+    /// Every image in a group must be within the threshold of an elected centre
+    Star,
+}
+
+/// How duplicates are deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Remove the file for good
+    Permanent,
+
+    /// Move the file to the desktop trash (freedesktop.org Trash specification)
+    Trash,
+}
+
+/// How long safety backups are kept
+///
+/// Backups past `max_age` are pruned first, then the oldest ones until the
+/// total is within `max_bytes`. The default keeps everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackupRetention {
+    /// Remove backups older than this
+    pub max_age: Option<Duration>,
+
+    /// Keep the backup directory within this many bytes
+    pub max_bytes: Option<u64>,
+}
+
+/// Where image hashes are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// A RocksDB database
+    #[default]
+    RocksDb,
+
+    /// A SQLite database, which can be queried with plain SQL
+    Sqlite,
+
+    /// An in-memory map, discarded when the run ends
+    Memory,
+}
+
 /// Log level for the application
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LogLevel {
@@ -44,18 +122,47 @@ pub struct Config {
     /// Whether to delete duplicates instead of moving them
     pub delete_duplicates: bool,
 
+    /// How duplicates are deleted when `delete_duplicates` is set
+    pub delete_mode: DeleteMode,
+
     /// Whether to create symbolic links to originals instead of keeping duplicates
     pub create_symlinks: bool,
 
+    /// Whether to replace duplicates with hard links to originals
+    /// (only on the same filesystem, and only for byte-identical files)
+    pub create_hardlinks: bool,
+
     /// Threshold for perceptual hash similarity (0-100)
     pub phash_threshold: u8,
 
+    /// Index used for near-duplicate search
+    pub similarity_index: SimilarityIndexKind,
+
+    /// Linkage used to group near-duplicate pairs
+    pub cluster_linkage: ClusterLinkage,
+
     /// Whether to generate thumbnails for visual comparison
     pub generate_thumbnails: bool,
 
+    /// Directory holding the undo journal of each run
+    pub journal_dir: PathBuf,
+
     /// Backup directory for safety copies
     pub backup_dir: Option<PathBuf>,
 
+    /// When safety backups are pruned
+    pub backup_retention: BackupRetention,
+
+    /// Abort a run that would act on more files than this
+    pub max_files_per_run: Option<usize>,
+
+    /// Abort a run that would act on more than this percentage of the files
+    /// in any one directory
+    pub max_directory_percent: Option<u8>,
+
+    /// Run even when `max_files_per_run` or `max_directory_percent` is exceeded
+    pub override_run_limits: bool,
+
     /// Maximum directory depth for scanning
     pub max_depth: Option<usize>,
 
@@ -74,6 +181,13 @@ pub struct Config {
     /// Path to the database file
     pub database_name: Option<String>,
 
+    /// Backend storing the hashes
+    pub storage_backend: StorageBackend,
+
+    /// Open the database for reading only, so reports can run while another
+    /// process writes to it
+    pub read_only_database: bool,
+
     /// Reinitialise the database
     pub reinitialise_database: bool,
 
@@ -86,6 +200,12 @@ pub struct Config {
     /// Log level
     pub log_level: LogLevel,
 
+    /// Directories whose files are never moved or deleted
+    ///
+    /// They are still scanned, and their files are always preferred as
+    /// originals.
+    pub protected_roots: Vec<PathBuf>,
+
     /// Directories to exclude from image scanning
     pub excluded_directories: Vec<PathBuf>,
 
@@ -101,10 +221,19 @@ impl Default for Config {
             dry_run: true,
             duplicates_dir: PathBuf::from("duplicates"),
             delete_duplicates: false,
+            delete_mode: DeleteMode::Permanent,
             create_symlinks: false,
+            create_hardlinks: false,
             phash_threshold: 90,
+            similarity_index: SimilarityIndexKind::BkTree,
+            cluster_linkage: ClusterLinkage::Complete,
             generate_thumbnails: true,
+            journal_dir: PathBuf::from("journal"),
             backup_dir: Some(PathBuf::from("backup")),
+            backup_retention: BackupRetention::default(),
+            max_files_per_run: None,
+            max_directory_percent: None,
+            override_run_limits: false,
             max_depth: None,
             process_unsupported_formats: false,
             threads: num_cpus::get(), // Use all available CPUs
@@ -115,10 +244,13 @@ impl Default for Config {
             ],
             use_database: true,
             database_name: Some(String::from("image_hash_db")),
+            storage_backend: StorageBackend::RocksDb,
+            read_only_database: false,
             reinitialise_database: false,
             force_rescan: false,
             batch_size: Some(100),
             log_level: LogLevel::Info,
+            protected_roots: Vec::new(),
             excluded_directories: Vec::new(),
             use_gpu_acceleration: false, // Disabled by default due to performance considerations
         }
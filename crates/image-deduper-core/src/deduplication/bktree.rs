@@ -0,0 +1,297 @@
+/// BK-tree index over perceptual hashes
+///
+/// A BK-tree is a metric tree: every child edge is labelled with the Hamming
+/// distance from its parent, so a range query only has to descend into children
+/// whose label lies within `[d - r, d + r]` of the query distance `d`.
+///
+/// Hamming distance is only a metric between hashes of the same length, so
+/// images whose hash length differs from the root's are kept out of the tree
+/// and scanned linearly, as multi-index hashing and LSH do.
+///
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use super::similarity::{
+    collect_pairs, to_neighbours, IndexedImage, Neighbour, SimilarPair, SimilarityIndex,
+};
+use super::HashedImage;
+use crate::error::{Error, Result};
+use crate::processing::types::PHash;
+
+/// Format version written by `BkTree::save`
+const BK_TREE_FORMAT_VERSION: u32 = 2;
+
+/// Tree links for a single image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BkNode {
+    /// Child node indices, keyed by their distance from this node
+    children: Vec<(u32, usize)>,
+
+    /// Removed images stay in the tree to keep routing intact
+    removed: bool,
+}
+
+/// Metric tree over the Hamming distance between perceptual hashes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BkTree {
+    version: u32,
+
+    /// Indexed images; image `i` sits at node `i`
+    images: Vec<IndexedImage>,
+
+    /// Node arena; the root is node 0
+    nodes: Vec<BkNode>,
+
+    /// Images whose hash length differs from the root's; always scanned linearly
+    unindexed: Vec<usize>,
+
+    /// Live node for each path (rebuilt on load)
+    #[serde(skip)]
+    by_path: HashMap<PathBuf, usize>,
+}
+
+impl Default for BkTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BkTree {
+    /// Create an empty tree
+    pub fn new() -> Self {
+        Self {
+            version: BK_TREE_FORMAT_VERSION,
+            images: Vec::new(),
+            nodes: Vec::new(),
+            unindexed: Vec::new(),
+            by_path: HashMap::new(),
+        }
+    }
+
+    /// Build a tree from hashed records, skipping those without a perceptual hash
+    pub fn from_records<R: HashedImage>(records: &[R]) -> Self {
+        let mut tree = Self::new();
+        tree.extend_from_records(records);
+        tree
+    }
+
+    /// Insert records that are new or whose hashes changed; returns the number inserted
+    pub fn extend_from_records<R: HashedImage>(&mut self, records: &[R]) -> usize {
+        let inserted = records
+            .iter()
+            .filter_map(IndexedImage::from_record)
+            .filter(|image| self.insert(image.clone()))
+            .count();
+
+        info!(
+            "Inserted {} images into BK-tree ({} indexed)",
+            inserted,
+            self.len()
+        );
+        inserted
+    }
+
+    /// Insert an image. Returns false if the same path is already indexed with
+    /// identical hashes; an entry with different hashes is replaced.
+    pub fn insert(&mut self, image: IndexedImage) -> bool {
+        if let Some(&existing) = self.by_path.get(&image.path) {
+            if self.images[existing] == image {
+                return false;
+            }
+            self.nodes[existing].removed = true;
+        }
+
+        let new_idx = self.nodes.len();
+        if self
+            .bits()
+            .is_some_and(|bits| bits != image.perceptual_hash.bits())
+        {
+            self.unindexed.push(new_idx);
+        } else if new_idx > 0 {
+            let mut current = 0;
+            loop {
+                let distance = self.images[current]
+                    .perceptual_hash
+                    .distance(&image.perceptual_hash);
+                let child = self.nodes[current]
+                    .children
+                    .iter()
+                    .find(|(label, _)| *label == distance)
+                    .map(|&(_, child)| child);
+
+                match child {
+                    Some(child) => current = child,
+                    None => {
+                        self.nodes[current].children.push((distance, new_idx));
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.by_path.insert(image.path.clone(), new_idx);
+        self.images.push(image);
+        self.nodes.push(BkNode {
+            children: Vec::new(),
+            removed: false,
+        });
+        true
+    }
+
+    /// Remove the image at `path`; returns false if it was not indexed
+    pub fn remove(&mut self, path: &Path) -> bool {
+        match self.by_path.remove(path) {
+            Some(idx) => {
+                self.nodes[idx].removed = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether an image with this path is indexed
+    pub fn contains(&self, path: &Path) -> bool {
+        self.by_path.contains_key(path)
+    }
+
+    /// Rebuild the tree without removed nodes
+    pub fn compact(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        let live: Vec<IndexedImage> = std::mem::take(&mut self.images)
+            .into_iter()
+            .zip(nodes)
+            .filter(|(_, node)| !node.removed)
+            .map(|(image, _)| image)
+            .collect();
+
+        self.by_path.clear();
+        self.unindexed.clear();
+        for image in live {
+            self.insert(image);
+        }
+    }
+
+    /// Save the tree to a file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serde::encode_into_std_write(self, &mut writer, bincode::config::standard())
+            .map_err(|e| Error::Serialization(format!("Failed to encode BK-tree: {}", e)))?;
+        writer.flush()?;
+
+        info!(
+            "Saved BK-tree with {} images to {}",
+            self.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Load a tree previously written by `save`
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut tree: Self =
+            bincode::serde::decode_from_std_read(&mut reader, bincode::config::standard())
+                .map_err(|e| Error::Serialization(format!("Failed to decode BK-tree: {}", e)))?;
+
+        if tree.version != BK_TREE_FORMAT_VERSION {
+            return Err(Error::Serialization(format!(
+                "Unsupported BK-tree format version: {}",
+                tree.version
+            )));
+        }
+
+        tree.by_path = tree
+            .images
+            .iter()
+            .zip(&tree.nodes)
+            .enumerate()
+            .filter(|(_, (_, node))| !node.removed)
+            .map(|(idx, (image, _))| (image.path.clone(), idx))
+            .collect();
+
+        info!(
+            "Loaded BK-tree with {} images from {}",
+            tree.len(),
+            path.display()
+        );
+        Ok(tree)
+    }
+
+    /// Hash length of the root, which every image in the tree shares
+    fn bits(&self) -> Option<u32> {
+        self.images.first().map(|root| root.perceptual_hash.bits())
+    }
+
+    /// Indices and distances of live nodes within `max_distance` of `hash`
+    fn query(&self, hash: &PHash, max_distance: u32) -> Vec<(usize, u32)> {
+        let within = |idx: usize| {
+            let distance = self.images[idx].perceptual_hash.distance(hash);
+            (distance <= max_distance && !self.nodes[idx].removed).then_some((idx, distance))
+        };
+
+        match self.bits() {
+            None => return Vec::new(),
+            Some(bits) if bits != hash.bits() => {
+                return (0..self.images.len()).filter_map(within).collect()
+            }
+            Some(_) => {}
+        }
+
+        let mut matches: Vec<(usize, u32)> = self
+            .unindexed
+            .iter()
+            .filter_map(|&idx| within(idx))
+            .collect();
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            let distance = self.images[idx].perceptual_hash.distance(hash);
+
+            if distance <= max_distance && !node.removed {
+                matches.push((idx, distance));
+            }
+
+            // Triangle inequality: only children labelled within [d - r, d + r] can match
+            let low = distance.saturating_sub(max_distance);
+            let high = distance.saturating_add(max_distance);
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(label, _)| *label >= low && *label <= high)
+                    .map(|&(_, child)| child),
+            );
+        }
+
+        matches
+    }
+}
+
+impl SimilarityIndex for BkTree {
+    fn len(&self) -> usize {
+        self.by_path.len()
+    }
+
+    fn neighbours(&self, hash: &PHash, max_distance: u32) -> Vec<Neighbour> {
+        to_neighbours(&self.images, self.query(hash, max_distance))
+    }
+
+    fn similar_pairs(&self, max_distance: u32) -> Vec<SimilarPair> {
+        let pairs = collect_pairs(
+            &self.images,
+            |idx| !self.nodes[idx].removed,
+            |hash| self.query(hash, max_distance),
+        );
+
+        info!(
+            "Found {} similar pairs within distance {}",
+            pairs.len(),
+            max_distance
+        );
+        pairs
+    }
+}
@@ -0,0 +1,292 @@
+/// Grouping of near-duplicate pairs into clusters
+///
+/// Transitive grouping (single linkage) lets groups drift: A~B and B~C put A and
+/// C together even when they look nothing alike. Complete and star linkage bound
+/// how far apart two members of a group can be.
+///
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use log::info;
+
+use super::similarity::{IndexedImage, SimilarPair};
+use crate::config::ClusterLinkage;
+
+/// A group of perceptually similar images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarityGroup {
+    /// Images in the group, sorted by path
+    pub members: Vec<IndexedImage>,
+
+    /// Image the group was built around (star linkage only)
+    pub centre: Option<PathBuf>,
+
+    /// Largest Hamming distance between any two members
+    pub max_distance: u32,
+}
+
+impl SimilarityGroup {
+    /// Create a group, sorting members and measuring its spread
+    pub fn new(mut members: Vec<IndexedImage>, centre: Option<PathBuf>) -> Self {
+        members.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let max_distance = members
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| {
+                members[i + 1..]
+                    .iter()
+                    .map(move |b| a.perceptual_hash.distance(&b.perceptual_hash))
+            })
+            .max()
+            .unwrap_or(0);
+
+        Self {
+            members,
+            centre,
+            max_distance,
+        }
+    }
+
+    /// Number of images in the group
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Whether the group has no images
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Paths of the images in the group
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.members.iter().map(|member| member.path.as_path())
+    }
+}
+
+/// Chooses the centre of a star cluster from a set of candidates
+pub trait CentreElector {
+    /// Index of the elected centre in `candidates` (never empty)
+    fn elect(&self, candidates: &[IndexedImage]) -> usize;
+}
+
+impl<F> CentreElector for F
+where
+    F: Fn(&[IndexedImage]) -> usize,
+{
+    fn elect(&self, candidates: &[IndexedImage]) -> usize {
+        self(candidates)
+    }
+}
+
+/// Elect the candidate with the smallest maximum distance to all others,
+/// breaking ties by path
+pub fn elect_medoid(candidates: &[IndexedImage]) -> usize {
+    candidates
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            let spread = |x: &IndexedImage| {
+                candidates
+                    .iter()
+                    .map(|y| x.perceptual_hash.distance(&y.perceptual_hash))
+                    .max()
+                    .unwrap_or(0)
+            };
+            spread(a).cmp(&spread(b)).then_with(|| a.path.cmp(&b.path))
+        })
+        .map(|(idx, _)| idx)
+        .unwrap_or(0)
+}
+
+/// Group similar pairs using the given linkage; star centres are elected by medoid
+pub fn cluster_pairs(pairs: &[SimilarPair], linkage: ClusterLinkage) -> Vec<SimilarityGroup> {
+    cluster_pairs_with(pairs, linkage, &elect_medoid)
+}
+
+/// Group similar pairs using the given linkage and star centre elector.
+///
+/// Only pairs present in `pairs` count as similar, so pairs should come from a
+/// single `similar_pairs` query. Groups with fewer than two images are dropped.
+pub fn cluster_pairs_with(
+    pairs: &[SimilarPair],
+    linkage: ClusterLinkage,
+    elector: &dyn CentreElector,
+) -> Vec<SimilarityGroup> {
+    let graph = PairGraph::new(pairs);
+
+    let mut groups: Vec<SimilarityGroup> = match linkage {
+        ClusterLinkage::Single => graph
+            .components()
+            .into_iter()
+            .map(|ids| graph.group(&ids, None))
+            .collect(),
+        ClusterLinkage::Complete => graph
+            .complete_clusters()
+            .into_iter()
+            .filter(|ids| ids.len() > 1)
+            .map(|ids| graph.group(&ids, None))
+            .collect(),
+        ClusterLinkage::Star => graph
+            .components()
+            .into_iter()
+            .flat_map(|ids| graph.stars(ids, elector))
+            .collect(),
+    };
+
+    groups.sort_by(|a, b| a.members[0].path.cmp(&b.members[0].path));
+
+    info!(
+        "Clustered {} similar pairs into {} groups ({:?} linkage)",
+        pairs.len(),
+        groups.len(),
+        linkage
+    );
+    groups
+}
+
+/// Similar pairs as a graph over image ids
+struct PairGraph {
+    images: Vec<IndexedImage>,
+
+    /// Edges keyed by (smaller id, larger id)
+    edges: HashMap<(usize, usize), u32>,
+
+    /// Edges sorted by distance, then ids
+    sorted_edges: Vec<(u32, usize, usize)>,
+}
+
+impl PairGraph {
+    fn new(pairs: &[SimilarPair]) -> Self {
+        let mut images = Vec::new();
+        let mut ids: HashMap<PathBuf, usize> = HashMap::new();
+        let mut id_of = |image: &IndexedImage| {
+            *ids.entry(image.path.clone()).or_insert_with(|| {
+                images.push(image.clone());
+                images.len() - 1
+            })
+        };
+
+        let mut edges = HashMap::new();
+        for pair in pairs {
+            let (a, b) = (id_of(&pair.a), id_of(&pair.b));
+            if a != b {
+                edges.insert((a.min(b), a.max(b)), pair.distance);
+            }
+        }
+
+        let mut sorted_edges: Vec<(u32, usize, usize)> =
+            edges.iter().map(|(&(a, b), &d)| (d, a, b)).collect();
+        sorted_edges.sort_unstable();
+
+        Self {
+            images,
+            edges,
+            sorted_edges,
+        }
+    }
+
+    fn is_edge(&self, a: usize, b: usize) -> bool {
+        self.edges.contains_key(&(a.min(b), a.max(b)))
+    }
+
+    fn group(&self, ids: &[usize], centre: Option<usize>) -> SimilarityGroup {
+        SimilarityGroup::new(
+            ids.iter().map(|&id| self.images[id].clone()).collect(),
+            centre.map(|id| self.images[id].path.clone()),
+        )
+    }
+
+    /// Connected components (single linkage), via union-find
+    fn components(&self) -> Vec<Vec<usize>> {
+        let mut parent: Vec<usize> = (0..self.images.len()).collect();
+        fn find(parent: &mut [usize], mut x: usize) -> usize {
+            while parent[x] != x {
+                parent[x] = parent[parent[x]];
+                x = parent[x];
+            }
+            x
+        }
+
+        for &(_, a, b) in &self.sorted_edges {
+            let (root_a, root_b) = (find(&mut parent, a), find(&mut parent, b));
+            if root_a != root_b {
+                parent[root_a.max(root_b)] = root_a.min(root_b);
+            }
+        }
+
+        let mut components: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for id in 0..self.images.len() {
+            let root = find(&mut parent, id);
+            components.entry(root).or_default().push(id);
+        }
+        components.into_values().collect()
+    }
+
+    /// Greedy complete linkage: merge closest clusters while every cross pair is similar
+    fn complete_clusters(&self) -> Vec<Vec<usize>> {
+        let mut clusters: Vec<Vec<usize>> = (0..self.images.len()).map(|id| vec![id]).collect();
+        let mut cluster_of: Vec<usize> = (0..self.images.len()).collect();
+
+        for &(_, a, b) in &self.sorted_edges {
+            let (ca, cb) = (cluster_of[a], cluster_of[b]);
+            if ca == cb {
+                continue;
+            }
+
+            let fully_linked = clusters[ca]
+                .iter()
+                .all(|&x| clusters[cb].iter().all(|&y| self.is_edge(x, y)));
+            if !fully_linked {
+                continue;
+            }
+
+            // Merge the smaller cluster into the larger one
+            let (keep, absorb) = if clusters[ca].len() >= clusters[cb].len() {
+                (ca, cb)
+            } else {
+                (cb, ca)
+            };
+            let moved = std::mem::take(&mut clusters[absorb]);
+            for &id in &moved {
+                cluster_of[id] = keep;
+            }
+            clusters[keep].extend(moved);
+        }
+
+        clusters.retain(|cluster| !cluster.is_empty());
+        clusters
+    }
+
+    /// Split a component into stars around elected centres
+    fn stars(
+        &self,
+        mut remaining: Vec<usize>,
+        elector: &dyn CentreElector,
+    ) -> Vec<SimilarityGroup> {
+        let mut groups = Vec::new();
+
+        while remaining.len() > 1 {
+            let candidates: Vec<IndexedImage> = remaining
+                .iter()
+                .map(|&id| self.images[id].clone())
+                .collect();
+            let centre = remaining[elector.elect(&candidates).min(remaining.len() - 1)];
+
+            let members: Vec<usize> = remaining
+                .iter()
+                .copied()
+                .filter(|&id| id == centre || self.is_edge(centre, id))
+                .collect();
+
+            let taken: HashSet<usize> = members.iter().copied().collect();
+            remaining.retain(|id| !taken.contains(id));
+
+            if members.len() > 1 {
+                groups.push(self.group(&members, Some(centre)));
+            }
+        }
+
+        groups
+    }
+}
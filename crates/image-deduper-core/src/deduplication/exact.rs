@@ -0,0 +1,143 @@
+/// Exact duplicate grouping by cryptographic hash
+///
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use blake3::Hash as Blake3Hash;
+use log::{info, warn};
+
+use crate::persistence::DBImageData;
+use crate::processing::types::{ImageHashResult, PHash};
+
+/// A hashed image record that can take part in duplicate grouping
+pub trait HashedImage {
+    /// Path to the image file
+    fn path(&self) -> &Path;
+
+    /// Blake3 hash of the file contents, if known
+    fn crypto_hash(&self) -> Option<Blake3Hash>;
+
+    /// Perceptual hash of the image, if known
+    fn perceptual_hash(&self) -> Option<PHash>;
+}
+
+impl HashedImage for DBImageData {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn crypto_hash(&self) -> Option<Blake3Hash> {
+        self.crypto_hash
+    }
+
+    fn perceptual_hash(&self) -> Option<PHash> {
+        self.perceptual_hash
+    }
+}
+
+impl HashedImage for ImageHashResult {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn crypto_hash(&self) -> Option<Blake3Hash> {
+        Some(self.cryptographic)
+    }
+
+    fn perceptual_hash(&self) -> Option<PHash> {
+        Some(self.perceptual)
+    }
+}
+
+/// A set of files with identical contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Blake3 hash shared by every file in the group
+    pub hash: Blake3Hash,
+
+    /// Paths of the files in the group, sorted
+    pub paths: Vec<PathBuf>,
+
+    /// Size of a single copy in bytes
+    pub file_size: u64,
+
+    /// Bytes used by all copies together
+    pub total_bytes: u64,
+
+    /// Bytes freed if all but one copy were removed
+    pub reclaimable_bytes: u64,
+}
+
+impl DuplicateGroup {
+    /// Number of files in the group
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Whether the group has no files
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+/// Group records by Blake3 hash and return every group with two or more files.
+///
+/// Records without a cryptographic hash are ignored, as are repeated entries for
+/// the same path. File sizes are read from the filesystem; groups are ordered by
+/// reclaimable bytes, largest first.
+pub fn find_exact_duplicates<R: HashedImage>(records: &[R]) -> Vec<DuplicateGroup> {
+    // Group paths by cryptographic hash (BTreeSet drops repeated paths and keeps order)
+    let mut hash_map: HashMap<Blake3Hash, BTreeSet<PathBuf>> = HashMap::new();
+    for record in records {
+        if let Some(hash) = record.crypto_hash() {
+            hash_map
+                .entry(hash)
+                .or_default()
+                .insert(record.path().to_path_buf());
+        }
+    }
+
+    // Keep only groups with 2+ paths
+    let mut groups: Vec<DuplicateGroup> = hash_map
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(hash, paths)| {
+            let paths: Vec<PathBuf> = paths.into_iter().collect();
+            let file_size = group_file_size(&paths);
+            let copies = paths.len() as u64;
+
+            DuplicateGroup {
+                hash,
+                paths,
+                file_size,
+                total_bytes: file_size * copies,
+                reclaimable_bytes: file_size * (copies - 1),
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| {
+        b.reclaimable_bytes
+            .cmp(&a.reclaimable_bytes)
+            .then_with(|| a.hash.as_bytes().cmp(b.hash.as_bytes()))
+    });
+
+    info!(
+        "Found {} exact duplicate groups ({} bytes reclaimable)",
+        groups.len(),
+        groups.iter().map(|g| g.reclaimable_bytes).sum::<u64>()
+    );
+
+    groups
+}
+
+/// Size of one copy in a group, taken from the first readable file
+fn group_file_size(paths: &[PathBuf]) -> u64 {
+    for path in paths {
+        match std::fs::metadata(path) {
+            Ok(metadata) => return metadata.len(),
+            Err(e) => warn!("Could not read size of {}: {}", path.display(), e),
+        }
+    }
+    0
+}
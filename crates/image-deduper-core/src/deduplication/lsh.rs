@@ -0,0 +1,170 @@
+/// Bit-sampling locality-sensitive hashing over perceptual hashes
+///
+/// Each table keys images by a fixed random sample of hash bits. Two hashes at
+/// distance `d` out of `B` bits share a bucket in one table with probability
+/// `(1 - d / B)^k` for `k` sampled bits, so more tables raise recall and more
+/// bits per table shrink buckets. Unlike the BK-tree and multi-index hashing,
+/// this index is approximate and can miss matches.
+///
+use std::collections::{HashMap, HashSet};
+
+use log::info;
+
+use super::mih::dedup_by_path;
+use super::similarity::{
+    bit_at, collect_pairs, to_neighbours, IndexedImage, Neighbour, SimilarPair, SimilarityIndex,
+};
+use super::HashedImage;
+use crate::processing::types::PHash;
+
+/// Seed for bit sampling, fixed so that results are reproducible
+const LSH_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Approximate near-duplicate index using bit-sampling LSH
+#[derive(Debug, Clone)]
+pub struct LshIndex {
+    images: Vec<IndexedImage>,
+
+    /// Bit length of the indexed hashes
+    bits: u32,
+
+    /// Sampled bit positions for each table
+    samples: Vec<Vec<u32>>,
+
+    /// One table per sample: bucket key -> image indices
+    tables: Vec<HashMap<u64, Vec<usize>>>,
+
+    /// Images whose hash length differs from `bits`; always scanned linearly
+    unindexed: Vec<usize>,
+}
+
+impl LshIndex {
+    /// Build an index with `tables` tables of `bits_per_table` sampled bits each
+    pub fn new(images: Vec<IndexedImage>, tables: usize, bits_per_table: usize) -> Self {
+        let images = dedup_by_path(images);
+        let bits = images
+            .first()
+            .map(|image| image.perceptual_hash.bits())
+            .unwrap_or(64);
+        let bits_per_table = (bits_per_table as u32).clamp(1, bits.min(64));
+
+        let mut rng = LSH_SEED;
+        let samples: Vec<Vec<u32>> = (0..tables.max(1))
+            .map(|_| sample_bits(&mut rng, bits, bits_per_table))
+            .collect();
+
+        let mut lsh_tables = vec![HashMap::new(); samples.len()];
+        let mut unindexed = Vec::new();
+        for (idx, image) in images.iter().enumerate() {
+            if image.perceptual_hash.bits() != bits {
+                unindexed.push(idx);
+                continue;
+            }
+            for (table, sample) in lsh_tables.iter_mut().zip(&samples) {
+                table
+                    .entry(bucket_key(&image.perceptual_hash, sample))
+                    .or_insert_with(Vec::new)
+                    .push(idx);
+            }
+        }
+
+        info!(
+            "Built LSH index over {} images ({} tables of {} bits, {} unindexed)",
+            images.len(),
+            samples.len(),
+            bits_per_table,
+            unindexed.len()
+        );
+
+        Self {
+            images,
+            bits,
+            samples,
+            tables: lsh_tables,
+            unindexed,
+        }
+    }
+
+    /// Build an index from hashed records, skipping those without a perceptual hash
+    pub fn from_records<R: HashedImage>(
+        records: &[R],
+        tables: usize,
+        bits_per_table: usize,
+    ) -> Self {
+        Self::new(
+            records
+                .iter()
+                .filter_map(IndexedImage::from_record)
+                .collect(),
+            tables,
+            bits_per_table,
+        )
+    }
+
+    /// Indices and distances of bucket-mates within `max_distance` of `hash`
+    fn query(&self, hash: &PHash, max_distance: u32) -> Vec<(usize, u32)> {
+        let candidates: HashSet<usize> = if hash.bits() == self.bits {
+            self.samples
+                .iter()
+                .zip(&self.tables)
+                .filter_map(|(sample, table)| table.get(&bucket_key(hash, sample)))
+                .flatten()
+                .chain(&self.unindexed)
+                .copied()
+                .collect()
+        } else {
+            (0..self.images.len()).collect()
+        };
+
+        candidates
+            .into_iter()
+            .filter_map(|idx| {
+                let distance = self.images[idx].perceptual_hash.distance(hash);
+                (distance <= max_distance).then_some((idx, distance))
+            })
+            .collect()
+    }
+}
+
+impl SimilarityIndex for LshIndex {
+    fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    fn neighbours(&self, hash: &PHash, max_distance: u32) -> Vec<Neighbour> {
+        to_neighbours(&self.images, self.query(hash, max_distance))
+    }
+
+    fn similar_pairs(&self, max_distance: u32) -> Vec<SimilarPair> {
+        collect_pairs(
+            &self.images,
+            |_| true,
+            |hash| self.query(hash, max_distance),
+        )
+    }
+}
+
+/// Pick `count` distinct bit positions out of `bits` (xorshift, deterministic)
+fn sample_bits(rng: &mut u64, bits: u32, count: u32) -> Vec<u32> {
+    let mut chosen = Vec::with_capacity(count as usize);
+    while chosen.len() < count as usize {
+        *rng ^= *rng << 13;
+        *rng ^= *rng >> 7;
+        *rng ^= *rng << 17;
+        let bit = (*rng % u64::from(bits)) as u32;
+        if !chosen.contains(&bit) {
+            chosen.push(bit);
+        }
+    }
+    chosen
+}
+
+/// Concatenate the sampled bits of a hash into a bucket key
+fn bucket_key(hash: &PHash, sample: &[u32]) -> u64 {
+    let words = hash.as_words();
+    sample
+        .iter()
+        .enumerate()
+        .filter(|&(_, &bit)| bit_at(words, bit))
+        .fold(0u64, |key, (offset, _)| key | (1u64 << offset))
+}
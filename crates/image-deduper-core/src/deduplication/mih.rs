@@ -0,0 +1,223 @@
+/// Multi-index hashing over perceptual hashes
+///
+/// Each hash is split into `m` disjoint bit substrings and every substring is
+/// indexed in its own table. If two hashes are within distance `r`, at least one
+/// substring pair is within `r / m` (pigeonhole principle), so probing every
+/// table with all substring values within `r / m` finds every match exactly.
+/// Candidates are then verified with `PHash::distance`.
+///
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use log::{debug, info};
+
+use super::similarity::{
+    bit_at, collect_pairs, to_neighbours, IndexedImage, Neighbour, SimilarPair, SimilarityIndex,
+};
+use super::HashedImage;
+use crate::processing::types::PHash;
+
+/// Maximum number of probes per table before falling back to a linear scan
+const MAX_PROBES_PER_TABLE: u64 = 100_000;
+
+/// Exact near-duplicate index using multi-index hashing
+#[derive(Debug, Clone)]
+pub struct MultiIndexHash {
+    images: Vec<IndexedImage>,
+
+    /// Bit length of the indexed hashes
+    bits: u32,
+
+    /// Bit range covered by each substring table
+    substrings: Vec<Range<u32>>,
+
+    /// One table per substring: substring value -> image indices
+    tables: Vec<HashMap<u64, Vec<usize>>>,
+
+    /// Images whose hash length differs from `bits`; always scanned linearly
+    unindexed: Vec<usize>,
+}
+
+impl MultiIndexHash {
+    /// Build an index that splits each hash into `substrings` tables.
+    ///
+    /// The bit length is taken from the first image; the substring count is raised
+    /// where needed so that no substring is wider than 64 bits.
+    pub fn new(images: Vec<IndexedImage>, substrings: usize) -> Self {
+        let images = dedup_by_path(images);
+        let bits = images
+            .first()
+            .map(|image| image.perceptual_hash.bits())
+            .unwrap_or(64);
+        let count = (substrings.max(1) as u32).clamp(bits.div_ceil(64), bits);
+
+        // Spread the remainder over the first substrings so widths differ by at most one
+        let substrings: Vec<Range<u32>> = (0..count)
+            .map(|j| (j * bits / count)..((j + 1) * bits / count))
+            .collect();
+
+        let mut tables = vec![HashMap::new(); substrings.len()];
+        let mut unindexed = Vec::new();
+        for (idx, image) in images.iter().enumerate() {
+            if image.perceptual_hash.bits() != bits {
+                unindexed.push(idx);
+                continue;
+            }
+            let words = image.perceptual_hash.as_words();
+            for (table, range) in tables.iter_mut().zip(&substrings) {
+                table
+                    .entry(extract_bits(words, range))
+                    .or_insert_with(Vec::new)
+                    .push(idx);
+            }
+        }
+
+        info!(
+            "Built multi-index hash over {} images ({} tables of ~{} bits, {} unindexed)",
+            images.len(),
+            substrings.len(),
+            bits / count,
+            unindexed.len()
+        );
+
+        Self {
+            images,
+            bits,
+            substrings,
+            tables,
+            unindexed,
+        }
+    }
+
+    /// Build an index from hashed records, skipping those without a perceptual hash
+    pub fn from_records<R: HashedImage>(records: &[R], substrings: usize) -> Self {
+        Self::new(
+            records
+                .iter()
+                .filter_map(IndexedImage::from_record)
+                .collect(),
+            substrings,
+        )
+    }
+
+    /// Number of substring tables
+    pub fn table_count(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Indices and distances of images within `max_distance` of `hash`
+    fn query(&self, hash: &PHash, max_distance: u32) -> Vec<(usize, u32)> {
+        let candidates = match self.candidates(hash, max_distance) {
+            Some(candidates) => candidates,
+            None => (0..self.images.len()).collect(),
+        };
+
+        candidates
+            .into_iter()
+            .filter_map(|idx| {
+                let distance = self.images[idx].perceptual_hash.distance(hash);
+                (distance <= max_distance).then_some((idx, distance))
+            })
+            .collect()
+    }
+
+    /// Candidate indices from substring probes, or `None` if a linear scan is needed
+    fn candidates(&self, hash: &PHash, max_distance: u32) -> Option<HashSet<usize>> {
+        if hash.bits() != self.bits {
+            return None;
+        }
+
+        let radius = max_distance / self.substrings.len() as u32;
+        let words = hash.as_words();
+        let mut candidates: HashSet<usize> = self.unindexed.iter().copied().collect();
+
+        for (table, range) in self.tables.iter().zip(&self.substrings) {
+            let width = range.end - range.start;
+            if probe_count(width, radius) > MAX_PROBES_PER_TABLE {
+                debug!(
+                    "Substring radius {} too large for {}-bit tables, scanning linearly",
+                    radius, width
+                );
+                return None;
+            }
+
+            for_each_within(extract_bits(words, range), width, radius, &mut |probe| {
+                if let Some(indices) = table.get(&probe) {
+                    candidates.extend(indices);
+                }
+            });
+        }
+
+        Some(candidates)
+    }
+}
+
+impl SimilarityIndex for MultiIndexHash {
+    fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    fn neighbours(&self, hash: &PHash, max_distance: u32) -> Vec<Neighbour> {
+        to_neighbours(&self.images, self.query(hash, max_distance))
+    }
+
+    fn similar_pairs(&self, max_distance: u32) -> Vec<SimilarPair> {
+        collect_pairs(
+            &self.images,
+            |_| true,
+            |hash| self.query(hash, max_distance),
+        )
+    }
+}
+
+/// Keep the last image for each path
+pub(super) fn dedup_by_path(images: Vec<IndexedImage>) -> Vec<IndexedImage> {
+    let mut by_path = HashMap::with_capacity(images.len());
+    let mut unique: Vec<IndexedImage> = Vec::with_capacity(images.len());
+    for image in images {
+        match by_path.get(&image.path) {
+            Some(&idx) => unique[idx] = image,
+            None => {
+                by_path.insert(image.path.clone(), unique.len());
+                unique.push(image);
+            }
+        }
+    }
+    unique
+}
+
+/// Read the bits in `range` as an integer (range must be at most 64 bits wide)
+fn extract_bits(words: &[u64], range: &Range<u32>) -> u64 {
+    range
+        .clone()
+        .enumerate()
+        .filter(|&(_, bit)| bit_at(words, bit))
+        .fold(0u64, |value, (offset, _)| value | (1u64 << offset))
+}
+
+/// Number of values within `radius` bit flips of a `width`-bit value
+fn probe_count(width: u32, radius: u32) -> u64 {
+    let mut total: u64 = 0;
+    let mut combinations: u64 = 1;
+    for k in 0..=radius.min(width) {
+        if k > 0 {
+            combinations = combinations.saturating_mul(u64::from(width - k + 1)) / u64::from(k);
+        }
+        total = total.saturating_add(combinations);
+    }
+    total
+}
+
+/// Call `visit` for every `width`-bit value within `radius` bit flips of `value`
+fn for_each_within(value: u64, width: u32, radius: u32, visit: &mut dyn FnMut(u64)) {
+    fn flip(value: u64, from_bit: u32, width: u32, remaining: u32, visit: &mut dyn FnMut(u64)) {
+        visit(value);
+        if remaining == 0 {
+            return;
+        }
+        for bit in from_bit..width {
+            flip(value ^ (1u64 << bit), bit + 1, width, remaining - 1, visit);
+        }
+    }
+    flip(value, 0, width, radius, visit);
+}
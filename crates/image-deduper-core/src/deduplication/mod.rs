@@ -1,32 +1,35 @@
-use crate::ImageFile;
+//! Duplicate detection
+//!
+//! This module turns hashed images into groups of duplicates that the
+//! action layer can work on:
+//! - Exact duplicates, grouped by identical Blake3 hash
+//! - Near duplicates, found through an index over perceptual hashes
+//!   (BK-tree, multi-index hashing or LSH, see `SimilarityIndexKind`)
+//! - Similarity groups, built from near-duplicate pairs with a selectable
+//!   linkage (see `ClusterLinkage`)
+//! - Election of the original in each group from `Config::prioritization`
+//!
+mod bktree;
+mod clustering;
+mod exact;
+mod lsh;
+mod mih;
+mod priority;
+mod report;
+mod similarity;
 
-/// Finds potential duplicate images by grouping them based on cryptographic hashes.
-///
-/// Takes a vector of ImageData structs and returns a vector of vectors, where each inner
-/// vector contains ImageData with identical cryptographic hashes.
-/// Only groups with 2 or more images (potential duplicates) are included in the result.
-fn _find_duplicate_images(_images: Vec<ImageFile>) -> Vec<Vec<ImageFile>> {
-    todo!()
-}
+pub use bktree::BkTree;
+pub use clustering::{
+    cluster_pairs, cluster_pairs_with, elect_medoid, CentreElector, SimilarityGroup,
+};
+pub use exact::{find_exact_duplicates, DuplicateGroup, HashedImage};
+pub use lsh::LshIndex;
+pub use mih::MultiIndexHash;
+pub use priority::{Candidate, ElectedGroup, Election, ElectionReason, PriorityEngine};
+pub use report::{compare_indexes, IndexReport};
+pub use similarity::{
+    build_index, max_distance_for_threshold, IndexedImage, Neighbour, SimilarPair, SimilarityIndex,
+};
 
-//     // Create a HashMap to group images by their cryptographic hash
-//     let mut hash_map: HashMap<String, Vec<ImageData>> = HashMap::new();
-
-//     // Group images by cryptographic hash
-//     for image in images {
-//         hash_map
-//             .entry(image.crypto_hash.clone())
-//             .or_insert_with(Vec::new)
-//             .push(image);
-//     }
-
-//     // Filter out unique images (groups with only one image)
-//     // and collect groups with 2+ images (potential duplicates)
-//     let duplicates: Vec<Vec<ImageData>> = hash_map
-//         .into_iter()
-//         .map(|(_, group)| group)
-//         .filter(|group| group.len() > 1)
-//         .collect();
-
-//     duplicates
-// }
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,353 @@
+/// Election of the original image in each duplicate group
+///
+/// Files under a protected root always win over the rest. The configured
+/// `PriorityRule`s are then applied in order as tie-breakers. The rule that
+/// first singles out one candidate is recorded with the result, so every
+/// keep/remove decision can be audited later.
+///
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use blake3::Hash as Blake3Hash;
+use log::{debug, info};
+
+use super::clustering::{CentreElector, SimilarityGroup};
+use super::exact::DuplicateGroup;
+use super::similarity::IndexedImage;
+use crate::config::{Config, PriorityRule};
+use crate::path_identity::is_within;
+use crate::safety::protected_root;
+use crate::types::ImageFormat;
+
+/// File facts used to rank candidate originals
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Path to the image file
+    pub path: PathBuf,
+
+    /// Blake3 hash of the file contents recorded at scan time, if known
+    pub crypto_hash: Option<Blake3Hash>,
+
+    /// File size in bytes
+    pub size: Option<u64>,
+
+    /// Creation time, if the filesystem reports it
+    pub created: Option<SystemTime>,
+
+    /// Last modified time
+    pub modified: Option<SystemTime>,
+
+    /// Image format, from the file extension
+    pub format: ImageFormat,
+
+    /// Image dimensions (width, height), if the header could be read
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl Candidate {
+    /// Gather facts about a file from the filesystem and its image header
+    pub fn inspect(path: &Path, crypto_hash: Option<Blake3Hash>) -> Self {
+        let metadata = std::fs::metadata(path).ok();
+        let format = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(ImageFormat::from_extension)
+            .unwrap_or_else(|| ImageFormat::Other(String::new()));
+
+        Self {
+            path: path.to_path_buf(),
+            crypto_hash,
+            size: metadata.as_ref().map(|m| m.len()),
+            created: metadata.as_ref().and_then(|m| m.created().ok()),
+            modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+            format,
+            dimensions: image::image_dimensions(path).ok(),
+        }
+    }
+
+    /// Number of pixels, if dimensions are known
+    fn pixels(&self) -> Option<u64> {
+        self.dimensions
+            .map(|(width, height)| u64::from(width) * u64::from(height))
+    }
+}
+
+/// Why a candidate was elected as the original
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElectionReason {
+    /// The group had a single candidate
+    OnlyCandidate,
+
+    /// It was the only candidate under a protected root
+    Protected,
+
+    /// This rule was the first to single out the original
+    Rule(PriorityRule),
+
+    /// Every rule tied; the first path in sort order was kept
+    PathOrder,
+}
+
+impl fmt::Display for ElectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OnlyCandidate => write!(f, "only candidate"),
+            Self::Protected => write!(f, "only candidate under a protected root"),
+            Self::Rule(rule) => write!(f, "decided by rule {:?}", rule),
+            Self::PathOrder => write!(f, "all rules tied, kept first path"),
+        }
+    }
+}
+
+/// Result of electing an original among candidates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Election {
+    /// Index of the elected original in the candidate list
+    pub original: usize,
+
+    /// Why it was elected
+    pub reason: ElectionReason,
+}
+
+/// A duplicate group with its elected original
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElectedGroup {
+    /// The image to keep
+    pub original: Candidate,
+
+    /// The images the action layer may act on, sorted by path
+    pub duplicates: Vec<Candidate>,
+
+    /// Why the original was elected
+    pub reason: ElectionReason,
+
+    /// Largest perceptual distance within the group (0 for exact duplicates)
+    pub max_distance: u32,
+}
+
+/// Applies priority rules to elect the original in each group
+#[derive(Debug, Clone)]
+pub struct PriorityEngine {
+    rules: Vec<PriorityRule>,
+    protected_roots: Vec<PathBuf>,
+}
+
+impl PriorityEngine {
+    /// Create an engine applying `rules` in order
+    pub fn new(rules: &[PriorityRule]) -> Self {
+        Self {
+            rules: rules.to_vec(),
+            protected_roots: Vec::new(),
+        }
+    }
+
+    /// Create an engine from `Config::prioritization` and `Config::protected_roots`
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(&config.prioritization).with_protected_roots(&config.protected_roots)
+    }
+
+    /// Always prefer candidates under one of `roots`
+    pub fn with_protected_roots(mut self, roots: &[PathBuf]) -> Self {
+        self.protected_roots = roots.to_vec();
+        self
+    }
+
+    /// Elect the original among `candidates` (which must not be empty)
+    pub fn elect(&self, candidates: &[Candidate]) -> Election {
+        if candidates.len() <= 1 {
+            return Election {
+                original: 0,
+                reason: ElectionReason::OnlyCandidate,
+            };
+        }
+
+        // Indices still tied for first place, narrowed by each rule in turn
+        let mut tied: Vec<usize> = (0..candidates.len()).collect();
+        let protected: Vec<usize> = tied
+            .iter()
+            .copied()
+            .filter(|&idx| protected_root(&self.protected_roots, &candidates[idx].path).is_some())
+            .collect();
+        match protected.as_slice() {
+            [] => {}
+            [only] => {
+                debug!("Protected {} elected", candidates[*only].path.display());
+                return Election {
+                    original: *only,
+                    reason: ElectionReason::Protected,
+                };
+            }
+            _ => tied = protected,
+        }
+
+        for rule in &self.rules {
+            let best = tied
+                .iter()
+                .copied()
+                .min_by(|&a, &b| compare(rule, &candidates[a], &candidates[b]))
+                .unwrap_or(0);
+            tied.retain(|&idx| {
+                compare(rule, &candidates[idx], &candidates[best]) == Ordering::Equal
+            });
+
+            if tied.len() == 1 {
+                debug!(
+                    "Rule {:?} elected {}",
+                    rule,
+                    candidates[tied[0]].path.display()
+                );
+                return Election {
+                    original: tied[0],
+                    reason: ElectionReason::Rule(rule.clone()),
+                };
+            }
+        }
+
+        let original = tied
+            .into_iter()
+            .min_by(|&a, &b| candidates[a].path.cmp(&candidates[b].path))
+            .unwrap_or(0);
+        Election {
+            original,
+            reason: ElectionReason::PathOrder,
+        }
+    }
+
+    /// Elect the original in an exact duplicate group
+    pub fn elect_duplicate_group(&self, group: &DuplicateGroup) -> ElectedGroup {
+        let candidates = group
+            .paths
+            .iter()
+            .map(|path| Candidate::inspect(path, Some(group.hash)))
+            .collect();
+        self.elect_group(candidates, 0)
+    }
+
+    /// Elect the original in a group of perceptually similar images
+    pub fn elect_similarity_group(&self, group: &SimilarityGroup) -> ElectedGroup {
+        let candidates = group
+            .members
+            .iter()
+            .map(|member| Candidate::inspect(&member.path, member.crypto_hash))
+            .collect();
+        self.elect_group(candidates, group.max_distance)
+    }
+
+    /// Elect originals for many exact duplicate groups
+    pub fn elect_duplicate_groups(&self, groups: &[DuplicateGroup]) -> Vec<ElectedGroup> {
+        let elected: Vec<ElectedGroup> = groups
+            .iter()
+            .map(|group| self.elect_duplicate_group(group))
+            .collect();
+        info!("Elected originals for {} exact groups", elected.len());
+        elected
+    }
+
+    /// Elect originals for many similarity groups
+    pub fn elect_similarity_groups(&self, groups: &[SimilarityGroup]) -> Vec<ElectedGroup> {
+        let elected: Vec<ElectedGroup> = groups
+            .iter()
+            .map(|group| self.elect_similarity_group(group))
+            .collect();
+        info!("Elected originals for {} similarity groups", elected.len());
+        elected
+    }
+
+    /// Elect originals for exact and similarity groups found in one scan
+    ///
+    /// Byte-identical files are also perceptually identical, so the duplicates
+    /// of each exact group are dropped from the similarity groups, leaving its
+    /// original to stand for them. Similarity groups left with fewer than two
+    /// members are discarded, so no file is acted on twice.
+    pub fn elect_groups(
+        &self,
+        exact: &[DuplicateGroup],
+        similar: &[SimilarityGroup],
+    ) -> Vec<ElectedGroup> {
+        let mut elected = self.elect_duplicate_groups(exact);
+        let exact_duplicates: HashSet<&Path> = elected
+            .iter()
+            .flat_map(|group| &group.duplicates)
+            .map(|duplicate| duplicate.path.as_path())
+            .collect();
+
+        let similar: Vec<SimilarityGroup> = similar
+            .iter()
+            .filter_map(|group| {
+                let members: Vec<IndexedImage> = group
+                    .members
+                    .iter()
+                    .filter(|member| !exact_duplicates.contains(member.path.as_path()))
+                    .cloned()
+                    .collect();
+                let centre = group
+                    .centre
+                    .clone()
+                    .filter(|centre| !exact_duplicates.contains(centre.as_path()));
+                (members.len() > 1).then(|| SimilarityGroup::new(members, centre))
+            })
+            .collect();
+
+        elected.extend(self.elect_similarity_groups(&similar));
+        elected
+    }
+
+    fn elect_group(&self, mut candidates: Vec<Candidate>, max_distance: u32) -> ElectedGroup {
+        let election = self.elect(&candidates);
+        let original = candidates.remove(election.original);
+        candidates.sort_by(|a, b| a.path.cmp(&b.path));
+
+        ElectedGroup {
+            original,
+            duplicates: candidates,
+            reason: election.reason,
+            max_distance,
+        }
+    }
+}
+
+impl CentreElector for PriorityEngine {
+    fn elect(&self, candidates: &[IndexedImage]) -> usize {
+        let candidates: Vec<Candidate> = candidates
+            .iter()
+            .map(|image| Candidate::inspect(&image.path, image.crypto_hash))
+            .collect();
+        PriorityEngine::elect(self, &candidates).original
+    }
+}
+
+/// Order two candidates under one rule; `Less` means `a` is preferred.
+/// Candidates missing the information a rule needs rank last.
+fn compare(rule: &PriorityRule, a: &Candidate, b: &Candidate) -> Ordering {
+    match rule {
+        PriorityRule::HighestResolution => prefer_some(a.pixels(), b.pixels(), |x, y| y.cmp(&x)),
+        PriorityRule::OldestCreationDate => {
+            let date = |c: &Candidate| c.created.or(c.modified);
+            prefer_some(date(a), date(b), |x, y| x.cmp(&y))
+        }
+        PriorityRule::PreferredFormat(formats) => {
+            let rank = |c: &Candidate| formats.iter().position(|f| *f == c.format);
+            prefer_some(rank(a), rank(b), |x, y| x.cmp(&y))
+        }
+        PriorityRule::PreferredDirectory(directories) => {
+            // Matched as protected roots are, see `path_identity::is_within`
+            let rank = |c: &Candidate| directories.iter().position(|d| is_within(&c.path, d));
+            prefer_some(rank(a), rank(b), |x, y| x.cmp(&y))
+        }
+        PriorityRule::SmallestFileSize => prefer_some(a.size, b.size, |x, y| x.cmp(&y)),
+        PriorityRule::LargestFileSize => prefer_some(a.size, b.size, |x, y| y.cmp(&x)),
+    }
+}
+
+/// Compare optional values, ranking `None` after any value
+fn prefer_some<T>(a: Option<T>, b: Option<T>, cmp: impl Fn(T, T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(x), Some(y)) => cmp(x, y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
@@ -0,0 +1,98 @@
+/// Recall and speed comparison between similarity indexes
+///
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use super::similarity::SimilarityIndex;
+use crate::processing::types::PHash;
+
+/// Recall and query time of an index, measured against a reference index
+#[derive(Debug, Clone)]
+pub struct IndexReport {
+    /// Number of query hashes
+    pub queries: usize,
+
+    /// Matches returned by the reference index
+    pub expected_matches: usize,
+
+    /// Reference matches also returned by the index under test
+    pub found_matches: usize,
+
+    /// Fraction of reference matches found (1.0 when there were none)
+    pub recall: f64,
+
+    /// Total query time of the index under test
+    pub index_time: Duration,
+
+    /// Total query time of the reference index
+    pub reference_time: Duration,
+}
+
+impl IndexReport {
+    /// How many times faster the index under test answered than the reference
+    pub fn speedup(&self) -> f64 {
+        self.reference_time.as_secs_f64() / self.index_time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Run the same neighbour queries on `index` and an exact `reference` index and
+/// report recall and timing, e.g. to choose LSH parameters for a library
+pub fn compare_indexes(
+    index: &dyn SimilarityIndex,
+    reference: &dyn SimilarityIndex,
+    queries: &[PHash],
+    max_distance: u32,
+) -> IndexReport {
+    let (expected, reference_time) = timed_matches(reference, queries, max_distance);
+    let (found, index_time) = timed_matches(index, queries, max_distance);
+
+    let found_matches = expected.intersection(&found).count();
+    let recall = if expected.is_empty() {
+        1.0
+    } else {
+        found_matches as f64 / expected.len() as f64
+    };
+
+    let report = IndexReport {
+        queries: queries.len(),
+        expected_matches: expected.len(),
+        found_matches,
+        recall,
+        index_time,
+        reference_time,
+    };
+
+    info!(
+        "Index comparison: recall {:.3} ({}/{}), {:.2?} vs {:.2?} reference ({:.1}x)",
+        report.recall,
+        report.found_matches,
+        report.expected_matches,
+        report.index_time,
+        report.reference_time,
+        report.speedup()
+    );
+    report
+}
+
+/// Run all queries and collect (query number, matched path) pairs with the elapsed time
+fn timed_matches(
+    index: &dyn SimilarityIndex,
+    queries: &[PHash],
+    max_distance: u32,
+) -> (HashSet<(usize, PathBuf)>, Duration) {
+    let start = Instant::now();
+    let matches = queries
+        .iter()
+        .enumerate()
+        .flat_map(|(query_idx, hash)| {
+            index
+                .neighbours(hash, max_distance)
+                .into_iter()
+                .map(move |neighbour| (query_idx, neighbour.image.path))
+        })
+        .collect();
+    (matches, start.elapsed())
+}
@@ -0,0 +1,176 @@
+/// Shared types for near-duplicate search over perceptual hashes
+///
+use std::path::PathBuf;
+
+use blake3::Hash as Blake3Hash;
+use log::info;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{BkTree, HashedImage, LshIndex, MultiIndexHash};
+use crate::config::SimilarityIndexKind;
+use crate::processing::types::PHash;
+
+/// An image stored in a similarity index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedImage {
+    /// Path to the image file
+    pub path: PathBuf,
+
+    /// Blake3 hash of the file contents, if known
+    pub crypto_hash: Option<Blake3Hash>,
+
+    /// Perceptual hash used for distance queries
+    pub perceptual_hash: PHash,
+}
+
+impl IndexedImage {
+    /// Build an indexed image from a hashed record, if it has a perceptual hash
+    pub fn from_record<R: HashedImage>(record: &R) -> Option<Self> {
+        record.perceptual_hash().map(|perceptual_hash| Self {
+            path: record.path().to_path_buf(),
+            crypto_hash: record.crypto_hash(),
+            perceptual_hash,
+        })
+    }
+}
+
+/// An indexed image found near a query hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbour {
+    /// The matching image
+    pub image: IndexedImage,
+
+    /// Hamming distance from the query hash
+    pub distance: u32,
+}
+
+/// Two indexed images within the query threshold of each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarPair {
+    /// First image of the pair (ordered by path)
+    pub a: IndexedImage,
+
+    /// Second image of the pair (ordered by path)
+    pub b: IndexedImage,
+
+    /// Hamming distance between the two perceptual hashes
+    pub distance: u32,
+}
+
+impl SimilarPair {
+    /// Create a pair, ordering the two images by path
+    pub fn new(first: IndexedImage, second: IndexedImage, distance: u32) -> Self {
+        if first.path <= second.path {
+            Self {
+                a: first,
+                b: second,
+                distance,
+            }
+        } else {
+            Self {
+                a: second,
+                b: first,
+                distance,
+            }
+        }
+    }
+}
+
+/// A searchable collection of perceptual hashes
+pub trait SimilarityIndex {
+    /// Number of live images in the index
+    fn len(&self) -> usize;
+
+    /// Whether the index holds no images
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every indexed image within `max_distance` of `hash`
+    fn neighbours(&self, hash: &PHash, max_distance: u32) -> Vec<Neighbour>;
+
+    /// Every pair of indexed images within `max_distance` of each other
+    fn similar_pairs(&self, max_distance: u32) -> Vec<SimilarPair>;
+}
+
+/// Convert a similarity threshold (0-100, as in `Config::phash_threshold`) into a
+/// maximum Hamming distance for hashes of the given bit length
+pub fn max_distance_for_threshold(threshold: u8, bits: u32) -> u32 {
+    let threshold = u32::from(threshold.min(100));
+    bits * (100 - threshold) / 100
+}
+
+/// Build the index selected by `kind` from hashed records
+pub fn build_index<R: HashedImage>(
+    records: &[R],
+    kind: SimilarityIndexKind,
+) -> Box<dyn SimilarityIndex + Send + Sync> {
+    info!("Building {:?} similarity index", kind);
+    match kind {
+        SimilarityIndexKind::BkTree => Box::new(BkTree::from_records(records)),
+        SimilarityIndexKind::MultiIndex { substrings } => {
+            Box::new(MultiIndexHash::from_records(records, substrings))
+        }
+        SimilarityIndexKind::Lsh {
+            tables,
+            bits_per_table,
+        } => Box::new(LshIndex::from_records(records, tables, bits_per_table)),
+    }
+}
+
+/// Whether bit number `bit` is set, counting from the lowest bit of the first word
+pub(super) fn bit_at(words: &[u64], bit: u32) -> bool {
+    (words[(bit / 64) as usize] >> (bit % 64)) & 1 == 1
+}
+
+/// Turn query matches into neighbours, closest first
+pub(super) fn to_neighbours(images: &[IndexedImage], matches: Vec<(usize, u32)>) -> Vec<Neighbour> {
+    let mut neighbours: Vec<Neighbour> = matches
+        .into_iter()
+        .map(|(idx, distance)| Neighbour {
+            image: images[idx].clone(),
+            distance,
+        })
+        .collect();
+
+    neighbours.sort_by(|a, b| {
+        a.distance
+            .cmp(&b.distance)
+            .then_with(|| a.image.path.cmp(&b.image.path))
+    });
+    neighbours
+}
+
+/// Query every live image against an index and keep each matching pair once,
+/// closest pairs first
+pub(super) fn collect_pairs<F>(
+    images: &[IndexedImage],
+    is_live: impl Fn(usize) -> bool + Sync,
+    query: F,
+) -> Vec<SimilarPair>
+where
+    F: Fn(&PHash) -> Vec<(usize, u32)> + Sync,
+{
+    let mut pairs: Vec<SimilarPair> = images
+        .par_iter()
+        .enumerate()
+        .filter(|&(idx, _)| is_live(idx))
+        .flat_map_iter(|(idx, image)| {
+            query(&image.perceptual_hash)
+                .into_iter()
+                .filter(move |&(other, _)| other > idx)
+                .map(move |(other, distance)| {
+                    SimilarPair::new(image.clone(), images[other].clone(), distance)
+                })
+        })
+        .collect();
+
+    pairs.sort_by(|x, y| {
+        x.distance
+            .cmp(&y.distance)
+            .then_with(|| x.a.path.cmp(&y.a.path))
+            .then_with(|| x.b.path.cmp(&y.b.path))
+    });
+    pairs
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use tempfile::TempDir;
+
+use super::test_utils::{brute_force_pairs, clustered_images, indexed, pair_set};
+use crate::deduplication::{BkTree, IndexedImage, SimilarityIndex};
+use crate::persistence::DBImageData;
+use crate::processing::types::PHash;
+
+fn build_tree(images: &[IndexedImage]) -> BkTree {
+    let mut tree = BkTree::new();
+    for image in images {
+        tree.insert(image.clone());
+    }
+    tree
+}
+
+/// Test that range queries agree with brute-force comparison
+#[test]
+fn test_similar_pairs_match_brute_force() {
+    let images = clustered_images(20, 6);
+    let tree = build_tree(&images);
+
+    for max_distance in [0, 2, 5, 40] {
+        let found: BTreeSet<_> = tree
+            .similar_pairs(max_distance)
+            .into_iter()
+            .map(|p| (p.a.path, p.b.path, p.distance))
+            .collect();
+        assert_eq!(found, brute_force_pairs(&images, max_distance));
+    }
+}
+
+/// Test that hashes of another length are found, though they can't sit in the tree
+#[test]
+fn test_mixed_hash_lengths_match_brute_force() {
+    let images: Vec<IndexedImage> = clustered_images(20, 6)
+        .into_iter()
+        .enumerate()
+        .map(|(i, image)| {
+            if i % 3 != 1 {
+                return image;
+            }
+            let base = image.perceptual_hash.as_u64();
+            let mut words = [0u64; 16];
+            for (j, word) in words.iter_mut().enumerate() {
+                *word = base.rotate_left(j as u32 * 7);
+            }
+            IndexedImage {
+                perceptual_hash: PHash::Enhanced(words),
+                ..image
+            }
+        })
+        .collect();
+    let tree = build_tree(&images);
+
+    for max_distance in [0, 2, 5, 40] {
+        assert_eq!(
+            pair_set(tree.similar_pairs(max_distance)),
+            brute_force_pairs(&images, max_distance),
+            "max_distance={}",
+            max_distance
+        );
+    }
+    let neighbours = tree.neighbours(&images[1].perceptual_hash, 0);
+    assert!(neighbours.iter().any(|n| n.image.path == images[1].path));
+}
+
+/// Test neighbour queries return matches sorted by distance
+#[test]
+fn test_neighbours() {
+    let tree = build_tree(&[
+        indexed("/a.jpg", 0b0000),
+        indexed("/b.jpg", 0b0001),
+        indexed("/c.jpg", 0b0111),
+        indexed("/d.jpg", u64::MAX),
+    ]);
+
+    let neighbours = tree.neighbours(&PHash::Standard(0), 3);
+    let found: Vec<(&str, u32)> = neighbours
+        .iter()
+        .map(|n| (n.image.path.to_str().unwrap(), n.distance))
+        .collect();
+
+    assert_eq!(found, vec![("/a.jpg", 0), ("/b.jpg", 1), ("/c.jpg", 3)]);
+}
+
+/// Test that re-inserting a path replaces the old entry only when its hash changed
+#[test]
+fn test_insert_replace_and_remove() {
+    let mut tree = build_tree(&[indexed("/a.jpg", 0), indexed("/b.jpg", 1)]);
+
+    assert!(!tree.insert(indexed("/a.jpg", 0)));
+    assert!(tree.insert(indexed("/a.jpg", u64::MAX)));
+    assert_eq!(tree.len(), 2);
+    assert!(tree.similar_pairs(1).is_empty());
+
+    assert!(tree.remove(&PathBuf::from("/b.jpg")));
+    assert!(!tree.contains(&PathBuf::from("/b.jpg")));
+    assert!(tree.neighbours(&PHash::Standard(1), 0).is_empty());
+
+    tree.compact();
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree.neighbours(&PHash::Standard(u64::MAX), 0).len(), 1);
+}
+
+/// Test building from database records skips records without a perceptual hash
+#[test]
+fn test_from_records() {
+    let records = vec![
+        DBImageData {
+            path: PathBuf::from("/a.jpg"),
+            crypto_hash: None,
+            perceptual_hash: Some(PHash::Standard(7)),
+        },
+        DBImageData {
+            path: PathBuf::from("/b.jpg"),
+            crypto_hash: None,
+            perceptual_hash: None,
+        },
+    ];
+
+    let mut tree = BkTree::from_records(&records);
+    assert_eq!(tree.len(), 1);
+
+    // A rescan only inserts what is new
+    assert_eq!(tree.extend_from_records(&records), 0);
+}
+
+/// Test that a saved tree loads back with the same contents
+#[test]
+fn test_save_and_load() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("index.bktree");
+    let images = clustered_images(5, 4);
+    let mut tree = build_tree(&images);
+    tree.remove(&images[0].path);
+    tree.save(&file).unwrap();
+
+    let loaded = BkTree::load(&file).unwrap();
+
+    assert_eq!(loaded.len(), tree.len());
+    assert!(!loaded.contains(&images[0].path));
+    assert_eq!(loaded.similar_pairs(3), tree.similar_pairs(3));
+}
@@ -0,0 +1,114 @@
+use super::test_utils::indexed;
+use crate::config::ClusterLinkage;
+use crate::deduplication::{
+    cluster_pairs, cluster_pairs_with, BkTree, IndexedImage, SimilarityGroup, SimilarityIndex,
+};
+
+/// A chain a~b~c where a and c are too far apart: a=0, b=4 bits, c=8 bits
+fn chain() -> Vec<IndexedImage> {
+    vec![
+        indexed("/a.jpg", 0),
+        indexed("/b.jpg", 0x0F),
+        indexed("/c.jpg", 0xFF),
+    ]
+}
+
+fn cluster(
+    images: &[IndexedImage],
+    max_distance: u32,
+    linkage: ClusterLinkage,
+) -> Vec<SimilarityGroup> {
+    let mut tree = BkTree::new();
+    for image in images {
+        tree.insert(image.clone());
+    }
+    cluster_pairs(&tree.similar_pairs(max_distance), linkage)
+}
+
+fn group_paths(groups: &[SimilarityGroup]) -> Vec<Vec<&str>> {
+    groups
+        .iter()
+        .map(|g| g.paths().map(|p| p.to_str().unwrap()).collect())
+        .collect()
+}
+
+/// Test that single linkage chains transitively and reports the spread
+#[test]
+fn test_single_linkage_chains() {
+    let groups = cluster(&chain(), 4, ClusterLinkage::Single);
+
+    assert_eq!(
+        group_paths(&groups),
+        vec![vec!["/a.jpg", "/b.jpg", "/c.jpg"]]
+    );
+    assert_eq!(groups[0].max_distance, 8);
+    assert_eq!(groups[0].centre, None);
+}
+
+/// Test that complete linkage refuses to chain
+#[test]
+fn test_complete_linkage_stops_chain_drift() {
+    let groups = cluster(&chain(), 4, ClusterLinkage::Complete);
+
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].len(), 2);
+    assert!(groups[0].max_distance <= 4);
+}
+
+/// Test that complete linkage keeps fully connected groups together
+#[test]
+fn test_complete_linkage_clique() {
+    let images = vec![
+        indexed("/a.jpg", 0b000),
+        indexed("/b.jpg", 0b001),
+        indexed("/c.jpg", 0b011),
+        indexed("/far.jpg", u64::MAX),
+    ];
+
+    let groups = cluster(&images, 2, ClusterLinkage::Complete);
+
+    assert_eq!(
+        group_paths(&groups),
+        vec![vec!["/a.jpg", "/b.jpg", "/c.jpg"]]
+    );
+    assert_eq!(groups[0].max_distance, 2);
+}
+
+/// Test that star linkage groups around the medoid by default
+#[test]
+fn test_star_linkage_medoid() {
+    let groups = cluster(&chain(), 4, ClusterLinkage::Star);
+
+    assert_eq!(groups.len(), 1);
+    assert_eq!(
+        groups[0].centre.as_deref().unwrap().to_str(),
+        Some("/b.jpg")
+    );
+    assert_eq!(groups[0].len(), 3);
+}
+
+/// Test that star linkage uses the supplied elector
+#[test]
+fn test_star_linkage_custom_elector() {
+    let mut tree = BkTree::new();
+    for image in chain() {
+        tree.insert(image);
+    }
+    let pairs = tree.similar_pairs(4);
+
+    // Always elect "/a.jpg" while it is a candidate
+    let elect_a = |candidates: &[IndexedImage]| {
+        candidates
+            .iter()
+            .position(|c| c.path.to_str() == Some("/a.jpg"))
+            .unwrap_or(0)
+    };
+    let groups = cluster_pairs_with(&pairs, ClusterLinkage::Star, &elect_a);
+
+    // a only reaches b; c is left on its own and dropped
+    assert_eq!(group_paths(&groups), vec![vec!["/a.jpg", "/b.jpg"]]);
+    assert_eq!(
+        groups[0].centre.as_deref().unwrap().to_str(),
+        Some("/a.jpg")
+    );
+}
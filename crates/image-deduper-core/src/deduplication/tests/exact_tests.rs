@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use tempfile::TempDir;
+
+use crate::deduplication::find_exact_duplicates;
+use crate::persistence::DBImageData;
+use crate::test_utils::fixtures::hashed_file;
+
+/// Test that identical files are grouped and unique files are dropped
+#[test]
+fn test_groups_identical_hashes() {
+    let dir = TempDir::new().unwrap();
+    let results = vec![
+        hashed_file(dir.path(), "a.jpg", b"same contents"),
+        hashed_file(dir.path(), "b.jpg", b"same contents"),
+        hashed_file(dir.path(), "c.jpg", b"same contents"),
+        hashed_file(dir.path(), "unique.jpg", b"different"),
+    ];
+
+    let groups = find_exact_duplicates(&results);
+
+    assert_eq!(groups.len(), 1);
+    let group = &groups[0];
+    assert_eq!(group.hash, blake3::hash(b"same contents"));
+    assert_eq!(group.len(), 3);
+    assert_eq!(group.file_size, 13);
+    assert_eq!(group.total_bytes, 39);
+    assert_eq!(group.reclaimable_bytes, 26);
+}
+
+/// Test that groups are ordered by reclaimable bytes
+#[test]
+fn test_groups_sorted_by_reclaimable_bytes() {
+    let dir = TempDir::new().unwrap();
+    let results = vec![
+        hashed_file(dir.path(), "small1.jpg", b"s"),
+        hashed_file(dir.path(), "small2.jpg", b"s"),
+        hashed_file(dir.path(), "large1.jpg", b"large file"),
+        hashed_file(dir.path(), "large2.jpg", b"large file"),
+    ];
+
+    let groups = find_exact_duplicates(&results);
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].reclaimable_bytes, 10);
+    assert_eq!(groups[1].reclaimable_bytes, 1);
+}
+
+/// Test database records, skipping missing hashes and repeated paths
+#[test]
+fn test_groups_database_records() {
+    let hash = blake3::hash(b"contents");
+    let record = |path: &str, crypto_hash| DBImageData {
+        path: PathBuf::from(path),
+        crypto_hash,
+        perceptual_hash: None,
+    };
+    let records = vec![
+        record("/nonexistent/a.jpg", Some(hash)),
+        record("/nonexistent/a.jpg", Some(hash)),
+        record("/nonexistent/b.jpg", Some(hash)),
+        record("/nonexistent/c.jpg", None),
+    ];
+
+    let groups = find_exact_duplicates(&records);
+
+    assert_eq!(groups.len(), 1);
+    assert_eq!(
+        groups[0].paths,
+        vec![
+            PathBuf::from("/nonexistent/a.jpg"),
+            PathBuf::from("/nonexistent/b.jpg")
+        ]
+    );
+    // Sizes can't be read for missing files
+    assert_eq!(groups[0].total_bytes, 0);
+}
@@ -0,0 +1,151 @@
+use super::test_utils::{brute_force_pairs, clustered_images, indexed, pair_set};
+use crate::config::SimilarityIndexKind;
+use crate::deduplication::{
+    build_index, compare_indexes, BkTree, IndexedImage, LshIndex, MultiIndexHash, SimilarityIndex,
+};
+use crate::processing::types::{ImageHashResult, PHash};
+
+/// Enhanced hashes spread around a few random centres
+fn enhanced_images() -> Vec<IndexedImage> {
+    clustered_images(8, 5)
+        .into_iter()
+        .map(|image| {
+            let base = image.perceptual_hash.as_u64();
+            let mut words = [0u64; 16];
+            for (i, word) in words.iter_mut().enumerate() {
+                *word = base.rotate_left(i as u32 * 7);
+            }
+            IndexedImage {
+                perceptual_hash: PHash::Enhanced(words),
+                ..image
+            }
+        })
+        .collect()
+}
+
+/// Test that multi-index hashing is exact for standard hashes
+#[test]
+fn test_multi_index_matches_brute_force() {
+    let images = clustered_images(20, 6);
+
+    for substrings in [1, 4, 8] {
+        let index = MultiIndexHash::new(images.clone(), substrings);
+        for max_distance in [0, 3, 9, 20] {
+            assert_eq!(
+                pair_set(index.similar_pairs(max_distance)),
+                brute_force_pairs(&images, max_distance),
+                "substrings={} max_distance={}",
+                substrings,
+                max_distance
+            );
+        }
+    }
+}
+
+/// Test that multi-index hashing is exact for enhanced hashes
+#[test]
+fn test_multi_index_enhanced_hashes() {
+    let images = enhanced_images();
+    let index = MultiIndexHash::new(images.clone(), 32);
+
+    assert_eq!(index.table_count(), 32);
+    for max_distance in [0, 40, 100] {
+        assert_eq!(
+            pair_set(index.similar_pairs(max_distance)),
+            brute_force_pairs(&images, max_distance)
+        );
+    }
+}
+
+/// Test that the substring count is raised so substrings fit in 64 bits
+#[test]
+fn test_multi_index_table_count_clamped() {
+    let index = MultiIndexHash::new(enhanced_images(), 2);
+    assert_eq!(index.table_count(), 16);
+}
+
+/// Test that hashes of another length are still found
+#[test]
+fn test_multi_index_mixed_hash_lengths() {
+    let mut images = enhanced_images();
+    images.push(indexed("/standard.jpg", images[0].perceptual_hash.as_u64()));
+    let index = MultiIndexHash::new(images.clone(), 16);
+
+    let neighbours = index.neighbours(&images[0].perceptual_hash, 0);
+    let paths: Vec<_> = neighbours.iter().map(|n| n.image.path.clone()).collect();
+    assert!(paths.contains(&images[0].path));
+    assert!(paths.contains(&images.last().unwrap().path));
+}
+
+/// Test that LSH only returns true matches and finds exact copies
+#[test]
+fn test_lsh_results_are_verified() {
+    let images = clustered_images(20, 6);
+    let index = LshIndex::new(images.clone(), 8, 16);
+    let expected = brute_force_pairs(&images, 4);
+
+    let found = pair_set(index.similar_pairs(4));
+    assert!(found.is_subset(&expected));
+
+    // Identical hashes always share every bucket
+    for image in &images {
+        let neighbours = index.neighbours(&image.perceptual_hash, 0);
+        assert!(neighbours.iter().any(|n| n.image.path == image.path));
+    }
+}
+
+/// Test recall reporting against an exact reference
+#[test]
+fn test_compare_indexes() {
+    let images = clustered_images(30, 5);
+    let queries: Vec<PHash> = images.iter().map(|i| i.perceptual_hash).collect();
+    let mut reference = BkTree::new();
+    for image in &images {
+        reference.insert(image.clone());
+    }
+
+    let exact = MultiIndexHash::new(images.clone(), 4);
+    let report = compare_indexes(&exact, &reference, &queries, 4);
+    assert_eq!(report.queries, queries.len());
+    assert!(report.expected_matches >= queries.len());
+    assert_eq!(report.recall, 1.0);
+
+    let approximate = LshIndex::new(images, 2, 48);
+    let report = compare_indexes(&approximate, &reference, &queries, 4);
+    assert!(report.recall > 0.0 && report.recall <= 1.0);
+}
+
+/// Test that every index kind agrees with brute force when exact
+#[test]
+fn test_build_index_kinds() {
+    let images = clustered_images(10, 4);
+    let records: Vec<ImageHashResult> = images
+        .iter()
+        .map(|image| ImageHashResult {
+            path: image.path.clone(),
+            cryptographic: blake3::hash(image.path.to_str().unwrap().as_bytes()),
+            perceptual: image.perceptual_hash,
+        })
+        .collect();
+
+    for kind in [
+        SimilarityIndexKind::BkTree,
+        SimilarityIndexKind::MultiIndex { substrings: 4 },
+    ] {
+        let index = build_index(&records, kind);
+        assert_eq!(index.len(), images.len());
+        assert_eq!(
+            pair_set(index.similar_pairs(3)),
+            brute_force_pairs(&images, 3)
+        );
+    }
+
+    let lsh = build_index(
+        &records,
+        SimilarityIndexKind::Lsh {
+            tables: 4,
+            bits_per_table: 12,
+        },
+    );
+    assert_eq!(lsh.len(), images.len());
+}
@@ -0,0 +1,7 @@
+// Tests for the deduplication module
+mod bktree_tests;
+mod clustering_tests;
+mod exact_tests;
+mod index_tests;
+mod priority_tests;
+mod test_utils;
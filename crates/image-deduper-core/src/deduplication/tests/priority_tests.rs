@@ -0,0 +1,253 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use tempfile::TempDir;
+
+use crate::config::PriorityRule;
+use crate::deduplication::{
+    find_exact_duplicates, Candidate, ElectionReason, IndexedImage, PriorityEngine, SimilarityGroup,
+};
+use crate::processing::types::{ImageHashResult, PHash};
+use crate::types::ImageFormat;
+
+/// A candidate with no filesystem facts
+fn candidate(path: &str, format: ImageFormat) -> Candidate {
+    Candidate {
+        path: PathBuf::from(path),
+        crypto_hash: None,
+        size: None,
+        created: None,
+        modified: None,
+        format,
+        dimensions: None,
+    }
+}
+
+/// Test that later rules only break ties left by earlier rules
+#[test]
+fn test_rules_apply_in_order() {
+    let candidates = vec![
+        Candidate {
+            dimensions: Some((100, 100)),
+            size: Some(10),
+            ..candidate("/a.jpg", ImageFormat::Jpeg)
+        },
+        Candidate {
+            dimensions: Some((200, 200)),
+            size: Some(20),
+            ..candidate("/b.jpg", ImageFormat::Jpeg)
+        },
+        Candidate {
+            dimensions: Some((200, 200)),
+            size: Some(30),
+            ..candidate("/c.jpg", ImageFormat::Jpeg)
+        },
+    ];
+    let engine = PriorityEngine::new(&[
+        PriorityRule::HighestResolution,
+        PriorityRule::SmallestFileSize,
+    ]);
+
+    let election = engine.elect(&candidates);
+
+    assert_eq!(election.original, 1);
+    assert_eq!(
+        election.reason,
+        ElectionReason::Rule(PriorityRule::SmallestFileSize)
+    );
+}
+
+/// Test ordered format and directory preferences
+#[test]
+fn test_preferred_format_and_directory() {
+    let candidates = vec![
+        candidate("/incoming/a.jpg", ImageFormat::Jpeg),
+        candidate("/archive/a.heic", ImageFormat::Heic),
+        candidate("/archive/a.png", ImageFormat::Png),
+    ];
+
+    let by_format = PriorityEngine::new(&[PriorityRule::PreferredFormat(vec![
+        ImageFormat::Png,
+        ImageFormat::Heic,
+    ])]);
+    assert_eq!(by_format.elect(&candidates).original, 2);
+
+    let by_directory = PriorityEngine::new(&[
+        PriorityRule::PreferredDirectory(vec![PathBuf::from("/archive")]),
+        PriorityRule::PreferredFormat(vec![ImageFormat::Heic]),
+    ]);
+    let election = by_directory.elect(&candidates);
+    assert_eq!(election.original, 1);
+    assert_eq!(
+        election.reason,
+        ElectionReason::Rule(PriorityRule::PreferredFormat(vec![ImageFormat::Heic]))
+    );
+}
+
+/// Test that a preferred directory matches other spellings and symlinks of it
+#[test]
+fn test_preferred_directory_spellings() {
+    let candidates = vec![
+        candidate("/incoming/a.jpg", ImageFormat::Jpeg),
+        candidate("/caf\u{e9}/a.jpg", ImageFormat::Jpeg),
+    ];
+    let decomposed = PriorityEngine::new(&[PriorityRule::PreferredDirectory(vec![PathBuf::from(
+        "/cafe\u{301}",
+    )])]);
+    assert_eq!(decomposed.elect(&candidates).original, 1);
+
+    #[cfg(unix)]
+    {
+        let dir = TempDir::new().unwrap();
+        let keep = dir.path().join("keep");
+        fs::create_dir(&keep).unwrap();
+        fs::write(keep.join("a.jpg"), b"image").unwrap();
+        let linked = dir.path().join("linked");
+        std::os::unix::fs::symlink(&keep, &linked).unwrap();
+
+        let candidates = vec![
+            candidate(
+                dir.path().join("a.jpg").to_str().unwrap(),
+                ImageFormat::Jpeg,
+            ),
+            candidate(keep.join("a.jpg").to_str().unwrap(), ImageFormat::Jpeg),
+        ];
+        let symlinked = PriorityEngine::new(&[PriorityRule::PreferredDirectory(vec![linked])]);
+        assert_eq!(symlinked.elect(&candidates).original, 1);
+    }
+}
+
+/// Test that candidates missing a fact rank after those that have it
+#[test]
+fn test_missing_information_ranks_last() {
+    let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    let candidates = vec![
+        candidate("/a.jpg", ImageFormat::Jpeg),
+        Candidate {
+            created: Some(old),
+            ..candidate("/b.jpg", ImageFormat::Jpeg)
+        },
+    ];
+
+    let by_date = PriorityEngine::new(&[PriorityRule::OldestCreationDate]);
+    assert_eq!(by_date.elect(&candidates).original, 1);
+
+    // Nobody has a size: the rule ties and path order decides
+    let by_size = PriorityEngine::new(&[PriorityRule::LargestFileSize]);
+    let election = by_size.elect(&candidates);
+    assert_eq!(election.original, 0);
+    assert_eq!(election.reason, ElectionReason::PathOrder);
+}
+
+/// Test that protected files are elected ahead of every rule
+#[test]
+fn test_protected_files_elected_first() {
+    let candidates = vec![
+        Candidate {
+            dimensions: Some((400, 400)),
+            ..candidate("/incoming/a.jpg", ImageFormat::Jpeg)
+        },
+        Candidate {
+            dimensions: Some((100, 100)),
+            ..candidate("/archive/2019/a.jpg", ImageFormat::Jpeg)
+        },
+        Candidate {
+            dimensions: Some((200, 200)),
+            ..candidate("/archive/2020/a.jpg", ImageFormat::Jpeg)
+        },
+    ];
+    let engine = PriorityEngine::new(&[PriorityRule::HighestResolution]);
+
+    let only_2019 = engine
+        .clone()
+        .with_protected_roots(&[PathBuf::from("/archive/2019")]);
+    let election = only_2019.elect(&candidates);
+    assert_eq!(election.original, 1);
+    assert_eq!(election.reason, ElectionReason::Protected);
+
+    // Among several protected files the rules still decide
+    let whole_archive = engine.with_protected_roots(&[PathBuf::from("/archive")]);
+    let election = whole_archive.elect(&candidates);
+    assert_eq!(election.original, 2);
+    assert_eq!(
+        election.reason,
+        ElectionReason::Rule(PriorityRule::HighestResolution)
+    );
+}
+
+/// Test electing originals for an exact group read from disk
+#[test]
+fn test_elect_duplicate_group() {
+    let dir = TempDir::new().unwrap();
+    let keep_dir = dir.path().join("keep");
+    fs::create_dir_all(&keep_dir).unwrap();
+
+    let results: Vec<ImageHashResult> = [dir.path().join("x.jpg"), keep_dir.join("x.jpg")]
+        .into_iter()
+        .map(|path| {
+            fs::write(&path, b"contents").unwrap();
+            ImageHashResult {
+                path,
+                cryptographic: blake3::hash(b"contents"),
+                perceptual: PHash::Standard(0),
+            }
+        })
+        .collect();
+    let groups = find_exact_duplicates(&results);
+    let engine = PriorityEngine::new(&[PriorityRule::PreferredDirectory(vec![keep_dir.clone()])]);
+
+    let elected = engine.elect_duplicate_groups(&groups);
+
+    assert_eq!(elected.len(), 1);
+    assert_eq!(elected[0].original.path, keep_dir.join("x.jpg"));
+    assert_eq!(elected[0].original.size, Some(8));
+    assert_eq!(elected[0].duplicates.len(), 1);
+    assert_eq!(elected[0].max_distance, 0);
+}
+
+/// Test that a file in an exact group is not also acted on in a similarity group
+#[test]
+fn test_exact_duplicates_leave_similarity_groups() {
+    let dir = TempDir::new().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    let results: Vec<ImageHashResult> = ["a.jpg", "b.jpg"]
+        .into_iter()
+        .map(|name| {
+            fs::write(path(name), b"contents").unwrap();
+            ImageHashResult {
+                path: path(name),
+                cryptographic: blake3::hash(b"contents"),
+                perceptual: PHash::Standard(0),
+            }
+        })
+        .collect();
+    let exact = find_exact_duplicates(&results);
+    let member = |name: &str, hash: u64| IndexedImage {
+        path: path(name),
+        crypto_hash: None,
+        perceptual_hash: PHash::Standard(hash),
+    };
+    let similar = vec![
+        SimilarityGroup::new(
+            vec![member("a.jpg", 0), member("b.jpg", 0), member("c.jpg", 1)],
+            Some(path("b.jpg")),
+        ),
+        // Nothing is left to compare with once b.jpg is taken out
+        SimilarityGroup::new(vec![member("b.jpg", 0), member("d.jpg", 3)], None),
+    ];
+
+    let elected = PriorityEngine::new(&[]).elect_groups(&exact, &similar);
+
+    assert_eq!(elected.len(), 2);
+    assert_eq!(elected[0].original.path, path("a.jpg"));
+    assert_eq!(elected[0].duplicates[0].path, path("b.jpg"));
+    assert_eq!(elected[1].original.path, path("a.jpg"));
+    let similar_duplicates: Vec<PathBuf> = elected[1]
+        .duplicates
+        .iter()
+        .map(|duplicate| duplicate.path.clone())
+        .collect();
+    assert_eq!(similar_duplicates, vec![path("c.jpg")]);
+    assert_eq!(elected[1].max_distance, 1);
+}
@@ -0,0 +1,70 @@
+#![allow(dead_code)]
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::deduplication::{IndexedImage, SimilarPair};
+use crate::processing::types::PHash;
+
+/// Create an indexed image with a standard perceptual hash
+pub fn indexed(path: &str, hash: u64) -> IndexedImage {
+    IndexedImage {
+        path: PathBuf::from(path),
+        crypto_hash: None,
+        perceptual_hash: PHash::Standard(hash),
+    }
+}
+
+/// Deterministic pseudo-random 64-bit hashes (xorshift)
+pub fn pseudo_random_hashes(count: usize, seed: u64) -> Vec<u64> {
+    let mut state = seed.max(1);
+    (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        })
+        .collect()
+}
+
+/// Create indexed images with clusters of close hashes around random centres
+pub fn clustered_images(clusters: usize, per_cluster: usize) -> Vec<IndexedImage> {
+    pseudo_random_hashes(clusters, 42)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(c, centre)| {
+            (0..per_cluster).map(move |i| {
+                // Flip i low bits to get members 0..per_cluster bits away from the centre
+                let hash = centre ^ ((1u64 << i) - 1);
+                indexed(&format!("/images/c{}_{}.jpg", c, i), hash)
+            })
+        })
+        .collect()
+}
+
+/// Brute-force all pairs within a distance, as (path, path, distance)
+pub fn brute_force_pairs(
+    images: &[IndexedImage],
+    max_distance: u32,
+) -> BTreeSet<(PathBuf, PathBuf, u32)> {
+    let mut pairs = BTreeSet::new();
+    for (i, a) in images.iter().enumerate() {
+        for b in &images[i + 1..] {
+            let distance = a.perceptual_hash.distance(&b.perceptual_hash);
+            if distance <= max_distance {
+                let (x, y) = if a.path <= b.path { (a, b) } else { (b, a) };
+                pairs.insert((x.path.clone(), y.path.clone(), distance));
+            }
+        }
+    }
+    pairs
+}
+
+/// Reduce pairs to comparable (path, path, distance) tuples
+pub fn pair_set(pairs: Vec<SimilarPair>) -> BTreeSet<(PathBuf, PathBuf, u32)> {
+    pairs
+        .into_iter()
+        .map(|p| (p.a.path, p.b.path, p.distance))
+        .collect()
+}
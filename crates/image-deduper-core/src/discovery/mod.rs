@@ -1,3 +1,4 @@
+use log::warn;
 use rayon::prelude::*;
 use std::fs;
 use std::io;
@@ -6,35 +7,65 @@ use walkdir::WalkDir;
 
 use crate::config::Config;
 use crate::error::{Error, Result};
+use crate::path_identity;
 use crate::types::{ImageFile, ImageFormat};
 
 /// Test module for discovery functionality
 #[cfg(test)]
 pub mod tests;
 
-/// Discover images in the provided directories
+/// Discover images in the provided directories and `Config::protected_roots`
 pub fn discover_images<P: AsRef<Path>>(
     directories: &[P],
     config: &Config,
 ) -> Result<Vec<ImageFile>> {
     // Convert to a collection of PathBufs first
-    let paths: Vec<PathBuf> = directories
+    let mut paths: Vec<PathBuf> = directories
         .iter()
         .map(|dir| dir.as_ref().to_path_buf())
         .collect();
 
+    // Protected roots are always scanned, so their files can count as originals
+    for root in &config.protected_roots {
+        if !paths
+            .iter()
+            .any(|dir| path_identity::starts_with(root, dir))
+        {
+            paths.retain(|dir| !path_identity::starts_with(dir, root));
+            paths.push(root.clone());
+        }
+    }
+
     // Now we can use par_iter on a concrete type
-    let image_files: Result<Vec<_>> = paths
+    let image_files: Vec<ImageFile> = paths
         .par_iter()
         .map(|dir| discover_images_in_directory(dir, config))
         .collect::<Vec<Result<Vec<ImageFile>>>>()
         .into_iter()
-        .try_fold(Vec::new(), |mut acc, result| {
+        .try_fold(Vec::new(), |mut acc, result| -> Result<Vec<ImageFile>> {
             acc.extend(result?);
             Ok(acc)
-        });
+        })?;
+
+    // Names that differ only by Unicode normalization share one database entry
+    for spellings in normalization_collisions(&image_files) {
+        warn!(
+            "Names differ only by Unicode normalization: {}",
+            spellings
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(image_files)
+}
 
-    image_files
+/// Discovered files whose names differ only by Unicode normalization, such as
+/// a copy from macOS next to the same name written on Linux
+pub fn normalization_collisions(image_files: &[ImageFile]) -> Vec<Vec<PathBuf>> {
+    path_identity::normalization_collisions(image_files.iter().map(|file| file.path.as_path()))
 }
 
 /// Discover images in a single directory
@@ -61,15 +92,14 @@ pub fn discover_images_in_directory(directory: &Path, config: &Config) -> Result
             // Check if it's a hidden file (starts with a dot)
             let is_hidden = current_path
                 .file_name()
-                .and_then(|name| name.to_str())
-                .map(|name| name.starts_with('.'))
+                .map(|name| name.as_encoded_bytes().starts_with(b"."))
                 .unwrap_or(false);
 
             let is_excluded = is_hidden
                 || config
                     .excluded_directories
                     .iter()
-                    .any(|excluded| current_path.starts_with(excluded));
+                    .any(|excluded| path_identity::starts_with(current_path, excluded));
 
             // Keep entry only if it's not excluded and is a file
             !is_excluded && e.file_type().is_file()
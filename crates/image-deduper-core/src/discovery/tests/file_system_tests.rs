@@ -1,4 +1,6 @@
-use crate::discovery::{discover_images, discover_images_in_directory, has_image_extension};
+use crate::discovery::{
+    discover_images, discover_images_in_directory, has_image_extension, normalization_collisions,
+};
 use crate::{config, Config};
 use std::path::{Path, PathBuf};
 
@@ -100,3 +102,55 @@ fn test_discover_images_nonexistent_directory() {
     let result = discover_images_in_directory(nonexistent_dir, &config);
     assert!(result.is_err());
 }
+
+/// Test that protected roots are scanned even when not listed, and only once
+#[test]
+fn test_discover_images_includes_protected_roots() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let incoming = dir.path().join("incoming");
+    let archive = dir.path().join("archive");
+    for path in [incoming.join("a.jpg"), archive.join("2020/b.jpg")] {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"image").unwrap();
+    }
+    let config = Config {
+        protected_roots: vec![archive.clone()],
+        ..Default::default()
+    };
+
+    let results = discover_images(&[&incoming, &archive.join("2020")], &config).unwrap();
+
+    let mut paths: Vec<PathBuf> = results.into_iter().map(|image| image.path).collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![archive.join("2020/b.jpg"), incoming.join("a.jpg")]
+    );
+}
+
+/// Test that names differing only by Unicode normalization are reported,
+/// and that excluded directories match either spelling
+#[test]
+fn test_normalization_collisions() {
+    let dir = tempfile::TempDir::new().unwrap();
+    // "é" composed (NFC, as written on Linux) and decomposed (NFD, from macOS)
+    let composed = dir.path().join("caf\u{e9}.jpg");
+    let decomposed = dir.path().join("cafe\u{301}.jpg");
+    let excluded = dir.path().join("re\u{301}sume\u{301}");
+    for path in [&composed, &decomposed, &excluded.join("c.jpg")] {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"image").unwrap();
+    }
+    let config = Config {
+        excluded_directories: vec![dir.path().join("r\u{e9}sum\u{e9}")],
+        ..Default::default()
+    };
+
+    let results = discover_images(&[dir.path()], &config).unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        normalization_collisions(&results),
+        vec![vec![decomposed, composed]]
+    );
+}
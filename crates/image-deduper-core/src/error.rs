@@ -12,7 +12,11 @@ pub enum Error {
 
     /// Database error
     #[error("Database error: {0}")]
-    Database(#[from] rocksdb::Error),
+    Database(DatabaseError),
+
+    /// The database is held open by another process
+    #[error("Database {} is in use by {owner}", path.display())]
+    DatabaseLocked { path: PathBuf, owner: String },
 
     /// Image processing error
     #[error("Image processing error: {0}")]
@@ -33,6 +37,10 @@ pub enum Error {
     #[error("Safety check failed: {0}")]
     SafetyCheck(String),
 
+    /// A file under a protected root would have been changed
+    #[error("Protected path: {0} is under a protected root and is never changed")]
+    ProtectedPath(PathBuf),
+
     /// Unsupported image format
     #[error("Unsupported image format: {0}")]
     UnsupportedFormat(String),
@@ -41,7 +49,39 @@ pub enum Error {
     #[error("Format handling error: {0}")]
     FormatHandling(String),
 
+    /// A database record that cannot be read
+    #[error("Corrupt database record {key}: {reason}")]
+    CorruptRecord { key: String, reason: String },
+
+    /// Serialization or deserialization error
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+
     /// Unknown error
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
+
+/// An error from a storage backend
+#[derive(Error, Debug)]
+pub enum DatabaseError {
+    /// RocksDB error
+    #[error(transparent)]
+    RocksDb(#[from] rocksdb::Error),
+
+    /// SQLite error
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+impl From<rocksdb::Error> for Error {
+    fn from(err: rocksdb::Error) -> Self {
+        Error::Database(err.into())
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Database(err.into())
+    }
+}
@@ -9,9 +9,10 @@
 // -- External Dependencies --
 
 use log::{info, warn};
-use persistence::ImageHashDB;
+use persistence::{DBImageData, FileStamp, HashRecord, HashStore};
 
 // -- Standard Library --
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::{
     path::Path,
@@ -23,7 +24,7 @@ mod error;
 
 // -- Public Re-exports --
 pub use config::*;
-pub use error::{Error, Result};
+pub use error::{DatabaseError, Error, Result};
 pub use types::*;
 
 // -- Public Modules --
@@ -32,6 +33,7 @@ pub mod config;
 pub mod deduplication;
 pub mod discovery;
 pub mod logging;
+pub mod path_identity;
 pub mod persistence;
 pub mod processing;
 pub mod safety;
@@ -58,23 +60,29 @@ pub fn get_default_db_path() -> PathBuf {
 /// Main entry point for the deduplication process
 pub struct ImageDeduper {
     config: Config,
-    db: ImageHashDB,
-    _safety_manager: safety::SafetyManager,
+    db: Box<dyn HashStore>,
+    safety_manager: Arc<safety::SafetyManager>,
     _shutdown_requested: Arc<AtomicBool>,
     memory_tracker: Arc<MemoryTracker>,
 }
 
 impl ImageDeduper {
     /// Create a new ImageDeduper with the provided configuration
-    pub fn new(config: &Config) -> Self {
+    ///
+    /// Fails if the database cannot be opened, for instance while another
+    /// process has it open for writing; see `persistence::ImageHashDB::new`.
+    pub fn new(config: &Config) -> Result<Self> {
         let cpu_count = num_cpus::get();
         // Cap at 8 threads to prevent too many file handles
         let thread_count = std::cmp::min(cpu_count, 8);
 
-        rayon::ThreadPoolBuilder::new()
+        // Only the first deduper in a process sets up the global pool
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
             .num_threads(thread_count)
             .build_global()
-            .unwrap();
+        {
+            log::debug!("Keeping the existing thread pool: {}", e);
+        }
 
         // Attempt to increase file descriptor limit on Unix platforms
         #[cfg(unix)]
@@ -92,18 +100,18 @@ impl ImageDeduper {
             }
         }
 
-        let db = ImageHashDB::new(&config);
+        let db = persistence::open_store(config)?;
         let memory_tracker = Arc::new(MemoryTracker::new());
-        let _safety_manager = safety::SafetyManager::new(&config);
+        let safety_manager = Arc::new(safety::SafetyManager::new(config));
         let _shutdown_requested = Arc::new(AtomicBool::new(false));
 
-        Self {
+        Ok(Self {
             config: config.clone(),
             db,
             memory_tracker,
-            _safety_manager,
+            safety_manager,
             _shutdown_requested,
-        }
+        })
     }
 
     /// Run the full deduplication pipeline
@@ -124,19 +132,153 @@ impl ImageDeduper {
         discovery::discover_images(directories, &self.config)
     }
 
+    /// Find groups of exact duplicates among the images stored in the database
+    pub fn find_exact_duplicates(&self) -> Result<Vec<deduplication::DuplicateGroup>> {
+        let records = self.stored_images()?;
+        Ok(deduplication::find_exact_duplicates(&records))
+    }
+
+    /// Find groups of perceptually similar images among those stored in the database,
+    /// using the configured similarity index, threshold and cluster linkage
+    pub fn find_similar_groups(&self) -> Result<Vec<deduplication::SimilarityGroup>> {
+        let records = self.stored_images()?;
+        let bits = match records.iter().find_map(|record| record.perceptual_hash) {
+            Some(hash) => hash.bits(),
+            None => return Ok(Vec::new()),
+        };
+        let max_distance =
+            deduplication::max_distance_for_threshold(self.config.phash_threshold, bits);
+
+        let index = deduplication::build_index(&records, self.config.similarity_index);
+        let pairs = index.similar_pairs(max_distance);
+
+        // Star clusters are built around the image the priority rules would keep
+        let engine = deduplication::PriorityEngine::from_config(&self.config);
+        Ok(deduplication::cluster_pairs_with(
+            &pairs,
+            self.config.cluster_linkage,
+            &engine,
+        ))
+    }
+
+    /// Elect the original in each group using the configured priority rules
+    ///
+    /// A file in an exact group is left out of the similarity groups, except
+    /// for that group's original; see `PriorityEngine::elect_groups`.
+    pub fn elect_originals(
+        &self,
+        exact: &[deduplication::DuplicateGroup],
+        similar: &[deduplication::SimilarityGroup],
+    ) -> Vec<deduplication::ElectedGroup> {
+        deduplication::PriorityEngine::from_config(&self.config).elect_groups(exact, similar)
+    }
+
+    /// Apply the configured action to the duplicates in each elected group
+    ///
+    /// Honours `Config::dry_run` and returns one result per duplicate, with the
+    /// journal run id to pass to `undo`.
+    pub fn execute_actions(
+        &self,
+        groups: &[deduplication::ElectedGroup],
+    ) -> Result<action::ActionRun> {
+        self.action_executor()?.execute(groups)
+    }
+
+    /// Plan the configured action for the duplicates in each elected group,
+    /// for review before anything is changed
+    pub fn plan_actions(
+        &self,
+        groups: &[deduplication::ElectedGroup],
+    ) -> Result<action::ActionPlan> {
+        let executor = action::ActionExecutor::new(&self.config)?;
+        Ok(action::ActionPlan::from_groups(
+            groups,
+            executor.action_type(),
+        ))
+    }
+
+    /// Apply a reviewed plan, skipping any file that changed since it was made
+    ///
+    /// Honours `Config::dry_run`.
+    pub fn apply_plan(&self, plan: &action::ActionPlan) -> Result<action::ActionRun> {
+        self.action_executor()?.apply_plan(plan)
+    }
+
+    /// An executor for the configured action, gated by this deduper's safety manager
+    fn action_executor(&self) -> Result<action::ActionExecutor> {
+        Ok(action::ActionExecutor::new(&self.config)?
+            .with_safety_manager(Arc::clone(&self.safety_manager)))
+    }
+
+    /// Roll back a run of actions recorded in the journal
+    pub fn undo(&self, run_id: &str) -> Result<Vec<ActionResult>> {
+        action::undo(&self.config.journal_dir, run_id)
+    }
+
+    /// Restore the contents with Blake3 hash `hash` from `Config::backup_dir` to `to`
+    pub fn restore_from_backup(&self, hash: &blake3::Hash, to: &Path) -> Result<()> {
+        self.safety_manager.restore_from_backup(hash, to)
+    }
+
+    /// Every image stored in the database with its hashes
+    fn stored_images(&self) -> Result<Vec<DBImageData>> {
+        Ok(self
+            .db
+            .records()?
+            .into_iter()
+            .map(DBImageData::from)
+            .collect())
+    }
+
+    /// Remove safety backups past `Config::backup_retention`
+    pub fn prune_backups(&self) -> Result<safety::BackupPruneReport> {
+        self.safety_manager.prune_backups()
+    }
+
+    /// The stored paths with the same contents as the file at `path`
+    ///
+    /// An empty result means the file is not yet in the library.
+    pub fn find_in_library(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let hash = processing::compute_cryptographic(path)?;
+        self.db.paths_with_hash(&hash)
+    }
+
+    /// Remove database entries for files that no longer exist, only under
+    /// `root` if given
+    ///
+    /// Honours `Config::dry_run`: a dry run only reports the missing files.
+    pub fn prune_database(&self, root: Option<&Path>) -> Result<persistence::DatabasePruneReport> {
+        self.db.prune_missing(root, self.config.dry_run)
+    }
+
     /// Hash and persist all images in the provided directories
+    ///
+    /// Each batch is stored in one atomic write as soon as it is hashed, so an
+    /// interrupted run keeps every batch it completed and the next run skips them.
+    /// Files moved or renamed since they were hashed are recognised by content
+    /// and have their entries moved instead of being hashed again.
     pub fn hash_and_persist(
         &self,
         image_files: &[ImageFile],
         config: &Config,
-    ) -> Result<(usize, usize)> {
+    ) -> Result<PersistSummary> {
         // Get image paths from ImageFile objects
         let image_paths: Vec<PathBuf> = image_files.iter().map(|img| img.path.clone()).collect();
-        let images_to_process = self.get_images_to_process(config, image_paths)?;
+        let new_paths = self.get_images_to_process(config, image_paths)?;
+        let images_to_process = if config.force_rescan {
+            new_paths.clone()
+        } else {
+            self.db.relocate_moved(&new_paths)?
+        };
+        let mut summary = PersistSummary {
+            moved: new_paths.len() - images_to_process.len(),
+            skipped: image_files.len().saturating_sub(new_paths.len()),
+            ..Default::default()
+        };
 
         if images_to_process.is_empty() {
             info!("No new images to process");
-            return Ok(self.db.get_db_stats()?);
+            return Ok(summary);
         }
 
         // Process images in smaller batches to manage memory usage
@@ -150,6 +292,13 @@ impl ImageDeduper {
                 pre_mem / 1024 / 1024
             );
 
+            // Stamp the files before hashing, so an edit made while hashing
+            // leaves the stored entry stale rather than wrongly current
+            let stamps: HashMap<PathBuf, FileStamp> = image_batch
+                .iter()
+                .filter_map(|path| Some((path.clone(), FileStamp::of(path).ok()?)))
+                .collect();
+
             // Process them
             let batch_results = processing::process_image_batch(image_batch);
 
@@ -162,6 +311,16 @@ impl ImageDeduper {
                 diff / 1024 / 1024
             );
 
+            // Store the batch before moving on; images that failed to hash
+            // are missing from the results
+            let records: Vec<HashRecord> = batch_results
+                .iter()
+                .map(|result| HashRecord::new(result, stamps.get(&result.path).copied()))
+                .collect();
+            self.db.insert(&records)?;
+            summary.new += batch_results.len();
+            summary.failed += image_batch.len() - batch_results.len();
+
             // Force cleanup of batch results
             drop(batch_results);
 
@@ -201,7 +360,7 @@ impl ImageDeduper {
                 info!("Performing full database maintenance...");
 
                 // Compact the database to reclaim space
-                self.db.compact_range();
+                self.db.compact();
                 info!("Database compaction complete");
 
                 // Check memory after compaction
@@ -225,7 +384,7 @@ impl ImageDeduper {
         // Final database maintenance
         match self.db.flush() {
             Ok(_) => {
-                self.db.compact_range();
+                self.db.compact();
                 info!("Final database maintenance completed successfully");
             }
             Err(e) => warn!("Final database maintenance error: {}. Continuing...", e),
@@ -239,7 +398,11 @@ impl ImageDeduper {
             self.memory_tracker.peak_mb()
         );
 
-        Ok(self.db.get_db_stats()?)
+        info!(
+            "Hashed {} new images, moved {}, skipped {} already stored, {} failed",
+            summary.new, summary.moved, summary.skipped, summary.failed
+        );
+        Ok(summary)
     }
 
     // Helper function to determine which images need processing
@@ -255,9 +418,9 @@ impl ImageDeduper {
             );
             image_paths
         } else {
-            // Filter out images already in database
+            // Filter out images already in database and unchanged since
             let new_paths = self.db.find_new_images(&image_paths)?;
-            info!("Found {} new images to process", new_paths.len());
+            info!("Found {} new or changed images to process", new_paths.len());
             new_paths
         };
         Ok(paths_to_process)
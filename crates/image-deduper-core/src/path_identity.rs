@@ -0,0 +1,98 @@
+/// Path identity under Unicode normalization
+///
+/// macOS writes file names decomposed (NFD) while Linux keeps the bytes it is
+/// given, usually composed (NFC), so one logical file can arrive under two
+/// spellings. Paths are compared by their identity, the path normalized to
+/// NFC; the real on-disk path is kept for filesystem operations. Names that
+/// are not valid UTF-8 are compared exactly. A filesystem that does not
+/// normalize names can hold both spellings as different files, which
+/// `same_file` tells apart.
+///
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// The identity of `path`: the path normalized to NFC
+pub fn identity_path(path: &Path) -> Cow<'_, Path> {
+    match path.to_str() {
+        Some(text) if !is_nfc(text) => Cow::Owned(PathBuf::from(text.nfc().collect::<String>())),
+        _ => Cow::Borrowed(path),
+    }
+}
+
+/// Whether two paths name the same logical file, ignoring normalization
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    identity_path(a) == identity_path(b)
+}
+
+/// Whether `a` and `b` are the same file on disk, such as two spellings of a
+/// name on a filesystem that normalizes it
+///
+/// Paths that cannot be read are not the same file.
+#[cfg(unix)]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Whether `a` and `b` are the same file on disk, such as two spellings of a
+/// name on a filesystem that normalizes it
+///
+/// Paths that cannot be read are not the same file.
+#[cfg(not(unix))]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether `path` is `base` or under it, ignoring normalization
+pub fn starts_with(path: &Path, base: &Path) -> bool {
+    identity_path(path).starts_with(identity_path(base))
+}
+
+/// Whether `path` is `base` or under it, ignoring normalization, as given or,
+/// failing that, canonicalized, so relative paths and symlinked directories
+/// are recognised
+pub fn is_within(path: &Path, base: &Path) -> bool {
+    if starts_with(path, base) {
+        return true;
+    }
+    match (fs::canonicalize(path), fs::canonicalize(base)) {
+        (Ok(path), Ok(base)) => starts_with(&path, &base),
+        _ => false,
+    }
+}
+
+/// Groups of distinct paths whose names differ only by normalization
+///
+/// Each group is sorted, and the groups are ordered by identity.
+pub fn normalization_collisions<'a>(
+    paths: impl IntoIterator<Item = &'a Path>,
+) -> Vec<Vec<PathBuf>> {
+    let mut by_identity: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for path in paths {
+        let spellings = by_identity
+            .entry(identity_path(path).into_owned())
+            .or_default();
+        if !spellings.iter().any(|spelling| spelling == path) {
+            spellings.push(path.to_path_buf());
+        }
+    }
+    by_identity
+        .into_values()
+        .filter(|spellings| spellings.len() > 1)
+        .map(|mut spellings| {
+            spellings.sort();
+            spellings
+        })
+        .collect()
+}
@@ -1,11 +1,20 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use blake3::Hash as Blake3Hash;
-use directories::ProjectDirs;
 use log::{info, warn};
-use rocksdb::{IteratorMode, Options as RdbOptions, WriteBatch, DB};
-
-use crate::error::Result;
+use rocksdb::{Options as RdbOptions, WriteBatch, DB};
+
+use super::lock::{directory_owner_file, locked, LockOwner};
+use super::models::FileStamp;
+use super::schema::{
+    self, decode, decode_blake3, encode, hash_index_key, identity_index_key, identity_index_prefix,
+    path_from_bytes, path_key, path_to_bytes, prefix_iter, write_synced, PATH_PREFIXES,
+    SCHEMA_VERSION,
+};
+use super::store::{database_path, supersedes, HashRecord, HashStore, StoreStats};
+use crate::error::{Error, Result};
+use crate::path_identity::same_file;
 
 use crate::processing::types::ImageHashResult;
 use crate::processing::types::PHash;
@@ -18,117 +27,175 @@ pub struct DBImageData {
     pub perceptual_hash: Option<PHash>,
 }
 
+/// Summary of a pass removing entries for files that no longer exist
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabasePruneReport {
+    /// Number of stored paths checked
+    pub checked: usize,
+
+    /// Stored paths whose files no longer exist
+    pub missing: Vec<PathBuf>,
+
+    /// Number of entries removed; 0 for a dry run
+    pub removed: usize,
+}
+
 pub struct ImageHashDB {
     db: DB,
+
+    /// Where the database is kept, when it is open for writing and this
+    /// process is recorded as its owner
+    owned: Option<PathBuf>,
 }
 
 impl ImageHashDB {
-    /// Create a new ImageGashDB
-    pub fn new(config: &Config) -> Self {
-        // Configure RocksDB options for better concurrent write performance
-        let mut options = RdbOptions::default();
-        options.create_if_missing(true);
-        options.increase_parallelism(num_cpus::get() as i32);
-        options.set_max_background_jobs(4);
-        options.set_write_buffer_size(64 * 1024 * 1024);
-        options.set_max_write_buffer_number(4);
-        // Use level-based compaction for better performance
-        options.set_level_compaction_dynamic_level_bytes(true);
-
-        // Create the db in tghe system's  config dir
-        let mut store_path = ProjectDirs::from("com", "lyonef", "image_deduper")
-            .map(|proj_dirs| proj_dirs.config_dir().to_path_buf())
-            .expect("Failed to get config directory");
-
-        store_path.push(PathBuf::from(
-            config
-                .database_name
-                .as_ref()
-                .unwrap_or(&String::from("image_hash_db")),
-        ));
-
-        // Delete the data base if config.reinitialise_database is true
+    /// Open the store configured by `config` for writing, creating it if it
+    /// does not exist and migrating it to the current layout
+    ///
+    /// Fails with `Error::DatabaseLocked`, naming the holder, when another
+    /// process has the store open, and with `Error::Database` when it cannot be
+    /// opened otherwise, for instance under a read-only home directory.
+    pub fn new(config: &Config) -> Result<Self> {
+        // Create the db in the system's config dir
+        let store_path = database_path(config)?;
+
+        // Delete the data base if config.reinitialise_database is true, unless
+        // another process is using it
         if config.reinitialise_database {
+            if let Some(owner) = LockOwner::read(&directory_owner_file(&store_path)) {
+                return Err(locked(store_path, owner));
+            }
             std::fs::remove_dir_all(&store_path).unwrap_or_default();
             info!("Deleted existing database at: {}", store_path.display());
         }
 
         info!("Opening RocksDB database at: {}", store_path.display());
 
-        return Self {
-            db: DB::open(&options, &store_path).expect("failed to open store"),
+        let db = match DB::open(&Self::options(), &store_path) {
+            Ok(db) => db,
+            Err(e) => {
+                return Err(match LockOwner::read(&directory_owner_file(&store_path)) {
+                    Some(owner) => locked(store_path, owner),
+                    None => Error::from(e),
+                })
+            }
         };
+        schema::migrate(&db)?;
+        LockOwner::current().record(&directory_owner_file(&store_path));
+        Ok(Self {
+            db,
+            owned: Some(store_path),
+        })
     }
 
-    /// Insert multiple hash results efficiently in a single batch operation
-    pub fn batch_insert_hashes(&self, results: &[ImageHashResult]) -> Result<()> {
-        if results.is_empty() {
-            return Ok(());
-        }
+    /// Open the store configured by `config` for reading only
+    ///
+    /// This does not take the lock, so reporting tools can read while another
+    /// process writes; they see the store as it was when opened. Writes fail
+    /// with `Error::Database`. The store must already exist with the current
+    /// layout, since it cannot be migrated.
+    pub fn open_read_only(config: &Config) -> Result<Self> {
+        let store_path = database_path(config)?;
+        info!(
+            "Opening RocksDB database read-only at: {}",
+            store_path.display()
+        );
 
-        // Create a batch operation
-        let mut batch = WriteBatch::default();
+        let db = DB::open_for_read_only(&Self::options(), &store_path, false)?;
+        let version = schema::version(&db)?;
+        if version != SCHEMA_VERSION {
+            return Err(Error::Configuration(format!(
+                "the database has schema version {}, but reading it needs version {}; \
+                 open it for writing once to migrate it",
+                version, SCHEMA_VERSION
+            )));
+        }
+        Ok(Self { db, owned: None })
+    }
 
-        // Add all items to batch
-        for result in results {
-            let path_str = result.path.to_string_lossy().into_owned();
-            let c_hash_bytes = blake3_to_vec(result.cryptographic);
-            let p_hash_bytes = phash_to_vec(&result.perceptual);
+    /// RocksDB options tuned for concurrent writes
+    fn options() -> RdbOptions {
+        let mut options = RdbOptions::default();
+        options.create_if_missing(true);
+        options.increase_parallelism(num_cpus::get() as i32);
+        options.set_max_background_jobs(4);
+        options.set_write_buffer_size(64 * 1024 * 1024);
+        options.set_max_write_buffer_number(4);
+        // Use level-based compaction for better performance
+        options.set_level_compaction_dynamic_level_bytes(true);
+        options
+    }
 
-            // Create keys for path->hash mappings
-            let path_c_key = [b"pc:".to_vec(), path_str.as_bytes().to_vec()].concat();
-            let path_p_key = [b"pp:".to_vec(), path_str.as_bytes().to_vec()].concat();
+    /// Version of the store's layout; see `persistence::SCHEMA_VERSION`
+    pub fn schema_version(&self) -> Result<u32> {
+        schema::version(&self.db)
+    }
 
-            // Add to batch
-            batch.put(&path_c_key, &c_hash_bytes);
-            batch.put(&path_p_key, &p_hash_bytes);
+    /// Insert multiple hash results efficiently in a single batch operation
+    ///
+    /// `stamps` holds each file's stamp from before it was hashed; a result
+    /// without one is stored unstamped, so it is treated as changed and hashed
+    /// again next time. The batch is written atomically and synced to disk
+    /// before returning, so either all of `results` survive a crash or none of
+    /// them do.
+    pub fn batch_insert_hashes(
+        &self,
+        results: &[ImageHashResult],
+        stamps: &HashMap<PathBuf, FileStamp>,
+    ) -> Result<()> {
+        if results.is_empty() {
+            return Ok(());
         }
 
-        // Write batch to database
-        self.db.write(batch)?;
+        let records: Vec<HashRecord> = results
+            .iter()
+            .map(|result| HashRecord::new(result, stamps.get(&result.path).copied()))
+            .collect();
+        self.insert(&records)?;
 
         info!("Inserted {} hash records into database", results.len());
         Ok(())
     }
 
+    /// All stored entries
+    ///
+    /// Entries with an unreadable hash are skipped with a warning, and an
+    /// unreadable perceptual hash is left out; rehashing replaces them.
     pub fn get_all_hashes(&self) -> Result<Vec<DBImageData>> {
-        let mut images = Vec::new();
+        Ok(self.records()?.into_iter().map(DBImageData::from).collect())
+    }
 
-        // Iterate over all keys in the database
-        let iter = self.db.iterator(IteratorMode::Start);
-        for result in iter {
-            match result {
-                Ok((key, value)) => {
-                    let key_str = String::from_utf8(key.to_vec()).expect("Invalid UTF-8 sequence");
-                    if key_str.starts_with("pc:") {
-                        let path_str = &key_str[3..];
-                        let path = PathBuf::from(path_str);
-
-                        // Retrieve the perceptual hash
-                        let path_p_key = [b"pp:".to_vec(), path_str.as_bytes().to_vec()].concat();
-                        let p_hash_bytes = self.db.get(path_p_key)?;
-
-                        // Convert byte vectors back to hashes
-                        let c_hash = vec_to_blake3(&value);
-                        let p_hash = p_hash_bytes.map(|bytes| vec_to_phash(&bytes));
-
-                        images.push(DBImageData {
-                            path,
-                            crypto_hash: Some(c_hash),
-                            perceptual_hash: p_hash,
-                        });
-                    }
-                }
-                Err(e) => {
-                    warn!("Error iterating over database: {}", e);
-                }
-            }
+    /// All stored paths whose contents have Blake3 hash `hash`
+    ///
+    /// Served from the `cp:` index, without scanning the database.
+    pub fn paths_with_hash(&self, hash: &Blake3Hash) -> Result<Vec<PathBuf>> {
+        let prefix = hash_index_key(hash.as_bytes(), b"");
+        prefix_iter(&self.db, &prefix)
+            .map(|result| Ok(path_from_bytes(&result?.0[prefix.len()..])))
+            .collect()
+    }
+
+    /// Whether any stored file has Blake3 hash `hash`
+    pub fn contains_hash(&self, hash: &Blake3Hash) -> Result<bool> {
+        let prefix = hash_index_key(hash.as_bytes(), b"");
+        let first = prefix_iter(&self.db, &prefix).next();
+        match first {
+            Some(result) => result.map(|_| true),
+            None => Ok(false),
         }
+    }
 
-        Ok(images)
+    /// Rebuild the `cp:` hash index from the stored path hashes
+    pub fn rebuild_hash_index(&self) -> Result<()> {
+        schema::rebuild_hash_index(&self.db)
     }
 
-    /// Find images that are not already in the database
+    /// Find images that are not already in the database, or that changed
+    /// since they were hashed
+    ///
+    /// A stored entry is only current while the file's size, modified time and
+    /// inode match the stamp recorded with it. Entries stored without a stamp,
+    /// or that can't be read, are treated as changed.
     pub fn find_new_images(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
         use rayon::prelude::*;
         use std::time::Instant;
@@ -145,8 +212,16 @@ impl ImageHashDB {
             let chunk_new_paths: Vec<PathBuf> = chunk
                 .par_iter()
                 .filter_map(|path| match self.check_hashes(path) {
-                    Ok(exists) if !exists => Some(path.clone()),
-                    _ => None,
+                    Ok(true) => None,
+                    Ok(false) => Some(path.clone()),
+                    Err(e) => {
+                        warn!(
+                            "Hashing {} again, its stored entry can't be read: {}",
+                            path.display(),
+                            e
+                        );
+                        Some(path.clone())
+                    }
                 })
                 .collect();
 
@@ -163,18 +238,83 @@ impl ImageHashDB {
         Ok(new_paths)
     }
 
-    /// Check if hashes exist for a given path
-    fn check_hashes(&self, path: &PathBuf) -> Result<bool> {
-        let path_str = path.to_string_lossy().into_owned();
-
+    /// Check if current hashes exist for a given path
+    fn check_hashes(&self, path: &Path) -> Result<bool> {
         // Check only the cryptographic hash for faster lookups
         // We know both hashes are inserted together
-        let path_c_key = [b"pc:".to_vec(), path_str.as_bytes().to_vec()].concat();
+        let Some((path_bytes, _)) = self.entry(path)? else {
+            return Ok(false);
+        };
 
-        // One database read is faster than two
-        let exists = self.db.get(&path_c_key)?.is_some();
+        // The hashes are stale if the file changed since they were stored
+        // An unreadable stamp is stale too, and rehashing replaces it
+        let path_m_key = path_key(b"pm:", &path_bytes);
+        let stored = self
+            .db
+            .get(&path_m_key)?
+            .and_then(|bytes| decode::<FileStamp>(&path_m_key, &bytes).ok());
+        Ok(stored.is_some() && stored == FileStamp::of(path).ok())
+    }
+
+    /// The stored spellings of `path`'s name, from the identity index, as raw
+    /// bytes
+    fn spellings(&self, path: &Path) -> Result<Vec<Vec<u8>>> {
+        let prefix = identity_index_prefix(path);
+        prefix_iter(&self.db, &prefix)
+            .map(|result| Ok(result?.0[prefix.len()..].to_vec()))
+            .collect()
+    }
 
-        Ok(exists)
+    /// The key and Blake3 hash of the entry for `path`: the one stored under
+    /// its exact bytes, or else under another spelling of its name that is the
+    /// same file on disk
+    fn entry(&self, path: &Path) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let path_bytes = path_to_bytes(path);
+        if let Some(c_hash_bytes) = self.db.get(path_key(b"pc:", path_bytes))? {
+            return Ok(Some((path_bytes.to_vec(), c_hash_bytes)));
+        }
+        for spelling in self.spellings(path)? {
+            if !same_file(path, &path_from_bytes(&spelling)) {
+                continue;
+            }
+            if let Some(c_hash_bytes) = self.db.get(path_key(b"pc:", &spelling))? {
+                return Ok(Some((spelling, c_hash_bytes)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The record of the entry keyed by `path_bytes`, whose hash is stored
+    /// under `path_c_key`
+    ///
+    /// An unreadable perceptual hash or stamp is left out with a warning;
+    /// without its stamp the file is hashed again.
+    fn record(
+        &self,
+        path_bytes: &[u8],
+        path_c_key: &[u8],
+        c_hash_bytes: &[u8],
+    ) -> Result<HashRecord> {
+        let path = path_from_bytes(path_bytes);
+        let cryptographic = decode_blake3(path_c_key, c_hash_bytes)?;
+        let path_p_key = path_key(b"pp:", path_bytes);
+        let perceptual = self.db.get(&path_p_key)?.and_then(|bytes| {
+            decode::<PHash>(&path_p_key, &bytes)
+                .inspect_err(|e| warn!("Ignoring {}: {}", path.display(), e))
+                .ok()
+        });
+        let path_m_key = path_key(b"pm:", path_bytes);
+        let stamp = self.db.get(&path_m_key)?.and_then(|bytes| {
+            decode::<FileStamp>(&path_m_key, &bytes)
+                .inspect_err(|e| warn!("Ignoring {}: {}", path.display(), e))
+                .ok()
+        });
+        Ok(HashRecord {
+            path,
+            cryptographic,
+            perceptual,
+            stamp,
+        })
     }
 
     /// Flush memtable to disk
@@ -196,12 +336,10 @@ impl ImageHashDB {
         let iter = self.db.iterator(rocksdb::IteratorMode::Start);
         for result in iter {
             if let Ok((key, _)) = result {
-                if let Ok(key_str) = std::str::from_utf8(&key) {
-                    if key_str.starts_with("pc:") {
-                        pc_count += 1;
-                    } else if key_str.starts_with("pp:") {
-                        pp_count += 1;
-                    }
+                if key.starts_with(b"pc:") {
+                    pc_count += 1;
+                } else if key.starts_with(b"pp:") {
+                    pp_count += 1;
                 }
             }
         }
@@ -222,22 +360,20 @@ impl ImageHashDB {
         let iter = self.db.iterator(rocksdb::IteratorMode::Start);
         for result in iter {
             if let Ok((key, _value)) = result {
-                if let Ok(key_str) = std::str::from_utf8(&key) {
-                    if key_str.starts_with("pc:") {
-                        pc_keys += 1;
-                        let path = key_str[3..].to_string();
-                        path_to_hashes
-                            .entry(path.clone())
-                            .and_modify(|(c, _)| *c = true)
-                            .or_insert((true, false));
-                    } else if key_str.starts_with("pp:") {
-                        pp_keys += 1;
-                        let path = key_str[3..].to_string();
-                        path_to_hashes
-                            .entry(path.clone())
-                            .and_modify(|(_, p)| *p = true)
-                            .or_insert((false, true));
-                    }
+                if key.starts_with(b"pc:") {
+                    pc_keys += 1;
+                    let path = path_from_bytes(&key[3..]);
+                    path_to_hashes
+                        .entry(path)
+                        .and_modify(|(c, _)| *c = true)
+                        .or_insert((true, false));
+                } else if key.starts_with(b"pp:") {
+                    pp_keys += 1;
+                    let path = path_from_bytes(&key[3..]);
+                    path_to_hashes
+                        .entry(path)
+                        .and_modify(|(_, p)| *p = true)
+                        .or_insert((false, true));
                 }
             }
         }
@@ -261,7 +397,12 @@ impl ImageHashDB {
             for (path, has_c, has_p) in
                 &inconsistent_paths[0..std::cmp::min(5, inconsistent_paths.len())]
             {
-                info!("  - Path: {}, Has C: {}, Has P: {}", path, has_c, has_p);
+                info!(
+                    "  - Path: {}, Has C: {}, Has P: {}",
+                    path.display(),
+                    has_c,
+                    has_p
+                );
             }
             if inconsistent_paths.len() > 5 {
                 info!("  - ... and {} more", inconsistent_paths.len() - 5);
@@ -278,56 +419,160 @@ impl ImageHashDB {
     }
 }
 
-/// Convert a Blake3 hash to a byte vector
-fn blake3_to_vec(hash: blake3::Hash) -> Vec<u8> {
-    hash.as_bytes().to_vec()
+impl Drop for ImageHashDB {
+    fn drop(&mut self) {
+        if let Some(store_path) = &self.owned {
+            LockOwner::release(&directory_owner_file(store_path));
+        }
+    }
 }
 
-/// Convert a PHash to a byte vector
-fn phash_to_vec(phash: &PHash) -> Vec<u8> {
-    match phash {
-        PHash::Standard(hash_value) => {
-            // Convert u64 to 8 bytes
-            hash_value.to_be_bytes().to_vec()
-        }
-        PHash::Enhanced(hash_array) => {
-            // Convert [u64; 16] to 128 bytes
-            let mut bytes = Vec::with_capacity(128);
-            for &value in hash_array.iter() {
-                bytes.extend_from_slice(&value.to_be_bytes());
+/// Remove the entry keyed by `path_bytes`, whose Blake3 hash is `c_hash_bytes`,
+/// and its index entries
+fn delete_entry(batch: &mut WriteBatch, path_bytes: &[u8], c_hash_bytes: &[u8]) {
+    for prefix in PATH_PREFIXES {
+        batch.delete(path_key(prefix, path_bytes));
+    }
+    batch.delete(hash_index_key(c_hash_bytes, path_bytes));
+    batch.delete(identity_index_key(path_bytes));
+}
+
+impl ImageHashDB {
+    /// Add the writes storing `records` to `batch`
+    fn insert_into(&self, batch: &mut WriteBatch, records: &[HashRecord]) -> Result<()> {
+        for record in records {
+            let path_bytes = path_to_bytes(&record.path);
+            let c_hash_bytes = record.cryptographic.as_bytes().to_vec();
+
+            // Create keys for path->hash mappings
+            let path_c_key = path_key(b"pc:", path_bytes);
+            let path_p_key = path_key(b"pp:", path_bytes);
+            let path_m_key = path_key(b"pm:", path_bytes);
+
+            // Keep the hash index in step when a path's contents changed
+            if let Some(old_hash) = self.db.get(&path_c_key)? {
+                if old_hash != c_hash_bytes {
+                    batch.delete(hash_index_key(&old_hash, path_bytes));
+                }
+            }
+
+            batch.put(&path_c_key, &c_hash_bytes);
+            batch.put(hash_index_key(&c_hash_bytes, path_bytes), b"");
+            match &record.perceptual {
+                Some(p_hash) => batch.put(&path_p_key, encode(p_hash)?),
+                None => batch.delete(&path_p_key),
+            }
+            batch.put(identity_index_key(path_bytes), b"");
+            match &record.stamp {
+                Some(stamp) => batch.put(&path_m_key, encode(stamp)?),
+                None => batch.delete(&path_m_key),
+            }
+
+            // The file renamed to another spelling, or a filesystem that
+            // normalizes names reported it under one
+            for spelling in self.spellings(&record.path)? {
+                if !supersedes(&record.path, &path_from_bytes(&spelling)) {
+                    continue;
+                }
+                if let Some(old_hash) = self.db.get(path_key(b"pc:", &spelling))? {
+                    delete_entry(batch, &spelling, &old_hash);
+                }
             }
-            bytes
         }
+        Ok(())
     }
-}
 
-// Helper function to convert byte vector to blake3::Hash
-fn vec_to_blake3(bytes: &[u8]) -> Blake3Hash {
-    let mut hash_bytes = [0u8; 32];
-    hash_bytes.copy_from_slice(bytes);
-    Blake3Hash::from(hash_bytes)
+    /// Add the writes removing the entries of `paths` to `batch`, returning
+    /// how many were stored
+    fn delete_into(&self, batch: &mut WriteBatch, paths: &[PathBuf]) -> Result<usize> {
+        let mut deleted = 0;
+        for path in paths {
+            let Some((path_bytes, c_hash_bytes)) = self.entry(path)? else {
+                continue;
+            };
+            delete_entry(batch, &path_bytes, &c_hash_bytes);
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
 }
 
-// Helper function to convert byte vector to PHash
-fn vec_to_phash(bytes: &[u8]) -> PHash {
-    match bytes.len() {
-        8 => {
-            // Deserialize as Standard PHash (64-bit)
-            let mut array = [0u8; 8];
-            array.copy_from_slice(bytes);
-            let value = u64::from_be_bytes(array);
-            PHash::Standard(value)
+impl HashStore for ImageHashDB {
+    fn insert(&self, records: &[HashRecord]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        self.insert_into(&mut batch, records)?;
+        write_synced(&self.db, batch)
+    }
+
+    fn get(&self, path: &Path) -> Result<Option<HashRecord>> {
+        match self.entry(path)? {
+            Some((path_bytes, c_hash_bytes)) => self
+                .record(&path_bytes, &path_key(b"pc:", &path_bytes), &c_hash_bytes)
+                .map(Some),
+            None => Ok(None),
         }
-        128 => {
-            // Deserialize as Enhanced PHash (1024-bit)
-            let mut array = [0u64; 16];
-            for (i, chunk) in bytes.chunks_exact(8).enumerate() {
-                let mut buf = [0u8; 8];
-                buf.copy_from_slice(chunk);
-                array[i] = u64::from_be_bytes(buf);
+    }
+
+    /// Every stored entry
+    ///
+    /// Entries with an unreadable hash are skipped with a warning; see
+    /// `get_all_hashes`.
+    fn records(&self) -> Result<Vec<HashRecord>> {
+        let mut records = Vec::new();
+        for result in prefix_iter(&self.db, b"pc:") {
+            let (key, value) = match result {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Error iterating over database: {}", e);
+                    continue;
+                }
+            };
+            match self.record(&key[3..], &key, &value) {
+                Ok(record) => records.push(record),
+                Err(e @ Error::CorruptRecord { .. }) => {
+                    warn!("Skipping {}: {}", path_from_bytes(&key[3..]).display(), e)
+                }
+                Err(e) => return Err(e),
             }
-            PHash::Enhanced(array)
         }
-        _ => panic!("Invalid byte length for PHash"),
+        Ok(records)
+    }
+
+    fn delete(&self, paths: &[PathBuf]) -> Result<usize> {
+        let mut batch = WriteBatch::default();
+        let deleted = self.delete_into(&mut batch, paths)?;
+        write_synced(&self.db, batch)?;
+        Ok(deleted)
+    }
+
+    fn replace(&self, old: &[PathBuf], records: &[HashRecord]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        self.delete_into(&mut batch, old)?;
+        self.insert_into(&mut batch, records)?;
+        write_synced(&self.db, batch)
+    }
+
+    fn paths_with_hash(&self, hash: &Blake3Hash) -> Result<Vec<PathBuf>> {
+        ImageHashDB::paths_with_hash(self, hash)
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        let (records, perceptual_hashes) = self.get_db_stats()?;
+        Ok(StoreStats {
+            records,
+            perceptual_hashes,
+        })
+    }
+
+    fn find_new_images(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        ImageHashDB::find_new_images(self, paths)
+    }
+
+    fn flush(&self) -> Result<()> {
+        ImageHashDB::flush(self)
+    }
+
+    fn compact(&self) {
+        self.compact_range()
     }
 }
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use tempfile::TempDir;
+
+use crate::error::Error;
+use crate::path_identity::same_file;
+use crate::persistence::{schema, FileStamp, HashStore, ImageHashDB};
+use crate::processing::types::{ImageHashResult, PHash};
+use crate::test_utils::fixtures::{db_config, hashed_file, open_db};
+use crate::Config;
+
+fn stamps_of(results: &[ImageHashResult]) -> HashMap<PathBuf, FileStamp> {
+    results
+        .iter()
+        .map(|result| (result.path.clone(), FileStamp::of(&result.path).unwrap()))
+        .collect()
+}
+
+/// Test that a stored batch is found again and its images are no longer new
+#[test]
+fn test_batch_insert_persists_hashes() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+    let results = [
+        hashed_file(dir.path(), "a.jpg", b"a.jpg"),
+        hashed_file(dir.path(), "b.jpg", b"b.jpg"),
+    ];
+    db.batch_insert_hashes(&results, &stamps_of(&results))
+        .unwrap();
+
+    let new_file = dir.path().join("c.jpg");
+    fs::write(&new_file, b"c").unwrap();
+    let paths = vec![
+        results[0].path.clone(),
+        results[1].path.clone(),
+        new_file.clone(),
+    ];
+    assert_eq!(db.find_new_images(&paths).unwrap(), vec![new_file]);
+    assert_eq!(db.get_db_stats().unwrap(), (2, 2));
+}
+
+/// Test that files edited or touched since they were hashed are found again
+#[test]
+fn test_changed_files_are_stale() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+    let results = [
+        hashed_file(dir.path(), "edited.jpg", b"edited.jpg"),
+        hashed_file(dir.path(), "touched.jpg", b"touched.jpg"),
+        hashed_file(dir.path(), "unstamped.jpg", b"unstamped.jpg"),
+        hashed_file(dir.path(), "same.jpg", b"same.jpg"),
+    ];
+    let mut stamps = stamps_of(&results);
+    stamps.remove(&results[2].path);
+    db.batch_insert_hashes(&results, &stamps).unwrap();
+
+    fs::write(&results[0].path, b"edited in place").unwrap();
+    File::options()
+        .write(true)
+        .open(&results[1].path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+
+    let paths: Vec<PathBuf> = results.iter().map(|result| result.path.clone()).collect();
+    assert_eq!(db.find_new_images(&paths).unwrap(), paths[..3].to_vec());
+}
+
+/// Test that stamps survive encoding
+#[test]
+fn test_file_stamp_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let result = hashed_file(dir.path(), "a.jpg", b"a.jpg");
+    let stamp = FileStamp::of(&result.path).unwrap();
+
+    assert_eq!(stamp.size, 5);
+    let bytes = schema::encode(&stamp).unwrap();
+    assert_eq!(
+        schema::decode::<FileStamp>(b"pm:a.jpg", &bytes).unwrap(),
+        stamp
+    );
+    assert!(matches!(
+        schema::decode::<FileStamp>(b"pm:a.jpg", b"short"),
+        Err(Error::CorruptRecord { .. })
+    ));
+}
+
+/// Test pruning entries of deleted files, scoped to a root and as a dry run
+#[test]
+fn test_prune_missing() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    let results = [
+        hashed_file(dir.path(), "kept.jpg", b"kept.jpg"),
+        hashed_file(dir.path(), "gone.jpg", b"gone.jpg"),
+        hashed_file(dir.path(), "sub/gone.jpg", b"sub/gone.jpg"),
+    ];
+    db.batch_insert_hashes(&results, &stamps_of(&results))
+        .unwrap();
+    fs::remove_file(&results[1].path).unwrap();
+    fs::remove_file(&results[2].path).unwrap();
+
+    let sub = dir.path().join("sub");
+    let report = db.prune_missing(Some(&sub), true).unwrap();
+    assert_eq!(report.checked, 1);
+    assert_eq!(report.missing, vec![results[2].path.clone()]);
+    assert_eq!(report.removed, 0);
+    assert_eq!(db.get_db_stats().unwrap(), (3, 3));
+
+    let report = db.prune_missing(Some(&sub), false).unwrap();
+    assert_eq!(report.removed, 1);
+    assert_eq!(db.get_db_stats().unwrap(), (2, 2));
+
+    let report = db.prune_missing(None, false).unwrap();
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.missing, vec![results[1].path.clone()]);
+    assert_eq!(db.get_db_stats().unwrap(), (1, 1));
+
+    assert!(db
+        .prune_missing(Some(&dir.path().join("unmounted")), true)
+        .is_err());
+}
+
+/// Test looking up paths by hash, as contents change and files are pruned
+#[test]
+fn test_hash_index() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+    let mut results = [
+        hashed_file(dir.path(), "a.jpg", b"a.jpg"),
+        hashed_file(dir.path(), "b.jpg", b"b.jpg"),
+    ];
+    results[1].cryptographic = results[0].cryptographic;
+    db.batch_insert_hashes(&results, &stamps_of(&results))
+        .unwrap();
+
+    let hash = results[0].cryptographic;
+    let mut paths = db.paths_with_hash(&hash).unwrap();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![results[0].path.clone(), results[1].path.clone()]
+    );
+    assert!(!db.contains_hash(&blake3::hash(b"other")).unwrap());
+
+    // b.jpg is re-hashed with new contents, then a.jpg is deleted
+    results[1].cryptographic = blake3::hash(b"edited");
+    db.batch_insert_hashes(&results[1..], &stamps_of(&results[1..]))
+        .unwrap();
+    assert_eq!(
+        db.paths_with_hash(&hash).unwrap(),
+        vec![results[0].path.clone()]
+    );
+    fs::remove_file(&results[0].path).unwrap();
+    db.prune_missing(None, false).unwrap();
+    assert!(!db.contains_hash(&hash).unwrap());
+    assert!(db.contains_hash(&blake3::hash(b"edited")).unwrap());
+
+    // The index is rebuilt to the same contents
+    db.rebuild_hash_index().unwrap();
+    assert_eq!(
+        db.paths_with_hash(&blake3::hash(b"edited")).unwrap(),
+        vec![results[1].path.clone()]
+    );
+    assert_eq!(db.get_all_hashes().unwrap().len(), 1);
+}
+
+/// Test that moved files get their entries moved instead of being hashed again
+#[test]
+fn test_relocate_moved() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+    let results = [
+        hashed_file(dir.path(), "a.jpg", b"a.jpg"),
+        hashed_file(dir.path(), "copied.jpg", b"copied.jpg"),
+    ];
+    db.batch_insert_hashes(&results, &stamps_of(&results))
+        .unwrap();
+
+    fs::create_dir(dir.path().join("sorted")).unwrap();
+    let moved = dir.path().join("sorted/renamed.jpg");
+    fs::rename(&results[0].path, &moved).unwrap();
+    let copy = dir.path().join("sorted/copy.jpg");
+    fs::copy(&results[1].path, &copy).unwrap();
+    let new_file = dir.path().join("sorted/new.jpg");
+    fs::write(&new_file, b"new").unwrap();
+
+    let paths = vec![moved.clone(), copy.clone(), new_file.clone()];
+    let unseen = db.find_new_images(&paths).unwrap();
+    assert_eq!(unseen, paths);
+    assert_eq!(db.relocate_moved(&unseen).unwrap(), vec![copy, new_file]);
+
+    assert!(db.find_new_images(&paths[..1]).unwrap().is_empty());
+    assert_eq!(
+        db.paths_with_hash(&results[0].cryptographic).unwrap(),
+        vec![moved.clone()]
+    );
+    let stored = db.get_all_hashes().unwrap();
+    assert_eq!(stored.len(), 2);
+    let entry = stored.iter().find(|entry| entry.path == moved).unwrap();
+    assert_eq!(entry.perceptual_hash, Some(PHash::Standard(42)));
+}
+
+/// Test that paths which are not valid UTF-8 are stored and read back exactly
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_paths_round_trip() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+    // Two names a lossy conversion would both turn into "caf\u{fffd}.jpg"
+    let results: Vec<ImageHashResult> = [&b"caf\xe9.jpg"[..], &b"caf\xe8.jpg"[..]]
+        .iter()
+        .map(|name| {
+            let path = dir.path().join(OsStr::from_bytes(name));
+            fs::write(&path, name).unwrap();
+            ImageHashResult {
+                cryptographic: blake3::hash(name),
+                perceptual: PHash::Standard(42),
+                path,
+            }
+        })
+        .collect();
+    db.batch_insert_hashes(&results, &stamps_of(&results))
+        .unwrap();
+
+    let paths: Vec<PathBuf> = results.iter().map(|result| result.path.clone()).collect();
+    assert!(db.find_new_images(&paths).unwrap().is_empty());
+    let mut stored: Vec<PathBuf> = db
+        .get_all_hashes()
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+    stored.sort();
+    let mut expected = paths.clone();
+    expected.sort();
+    assert_eq!(stored, expected);
+    assert_eq!(
+        db.paths_with_hash(&results[0].cryptographic).unwrap(),
+        vec![paths[0].clone()]
+    );
+}
+
+/// Test that a file renamed to another spelling of its name keeps one entry,
+/// under the spelling on disk
+#[test]
+fn test_normalization_shares_entries() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+    // Written on macOS, decomposed
+    let results = [hashed_file(
+        dir.path(),
+        "cafe\u{301}.jpg",
+        "cafe\u{301}.jpg".as_bytes(),
+    )];
+    db.batch_insert_hashes(&results, &stamps_of(&results))
+        .unwrap();
+
+    let stored = db.get_all_hashes().unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].path, results[0].path);
+    assert_eq!(
+        db.paths_with_hash(&results[0].cryptographic).unwrap(),
+        vec![results[0].path.clone()]
+    );
+    assert!(db
+        .find_new_images(&[results[0].path.clone()])
+        .unwrap()
+        .is_empty());
+
+    // Seen again under the composed spelling, it is the same entry
+    let composed = hashed_file(dir.path(), "caf\u{e9}.jpg", "caf\u{e9}.jpg".as_bytes());
+    fs::remove_file(&results[0].path).unwrap();
+    let mut composed_result = results[0].clone();
+    composed_result.path = composed.path.clone();
+    db.batch_insert_hashes(&[composed_result], &stamps_of(&[composed]))
+        .unwrap();
+    let stored = db.get_all_hashes().unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].path, dir.path().join("caf\u{e9}.jpg"));
+    assert_eq!(
+        db.prune_missing(None, true).unwrap().missing,
+        Vec::<PathBuf>::new()
+    );
+}
+
+/// Test that two files whose names differ only by normalization, both on
+/// disk, keep separate entries
+#[test]
+fn test_normalization_collisions_keep_separate_entries() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+    let results = [
+        hashed_file(dir.path(), "caf\u{e9}.jpg", "caf\u{e9}.jpg".as_bytes()),
+        hashed_file(dir.path(), "cafe\u{301}.jpg", "cafe\u{301}.jpg".as_bytes()),
+    ];
+    // A filesystem that normalizes names holds only one of them
+    if same_file(&results[0].path, &results[1].path) {
+        return;
+    }
+    db.batch_insert_hashes(&results[..1], &stamps_of(&results[..1]))
+        .unwrap();
+    db.batch_insert_hashes(&results[1..], &stamps_of(&results[1..]))
+        .unwrap();
+
+    let mut stored: Vec<_> = db
+        .get_all_hashes()
+        .unwrap()
+        .into_iter()
+        .map(|data| (data.path, data.crypto_hash.unwrap()))
+        .collect();
+    stored.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut expected: Vec<_> = results
+        .iter()
+        .map(|result| (result.path.clone(), result.cryptographic))
+        .collect();
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(stored, expected);
+
+    for result in &results {
+        assert_eq!(
+            db.paths_with_hash(&result.cryptographic).unwrap(),
+            vec![result.path.clone()]
+        );
+    }
+    let paths: Vec<_> = results.iter().map(|result| result.path.clone()).collect();
+    assert!(db.find_new_images(&paths).unwrap().is_empty());
+}
+
+/// Test that a second writer is refused with the holder named, and that the
+/// database can be opened again once the first closes
+#[test]
+fn test_locked_database_names_its_owner() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(dir.path());
+
+    match ImageHashDB::new(&db_config(dir.path())) {
+        Err(Error::DatabaseLocked { path, owner }) => {
+            assert_eq!(path, dir.path().join("db"));
+            assert!(owner.contains(&std::process::id().to_string()), "{}", owner);
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("opened a locked database"),
+    }
+    // Nor may it be wiped while in use
+    let reinitialise = Config {
+        reinitialise_database: true,
+        ..db_config(dir.path())
+    };
+    assert!(matches!(
+        ImageHashDB::new(&reinitialise),
+        Err(Error::DatabaseLocked { .. })
+    ));
+
+    drop(db);
+    assert!(!dir.path().join("db/OWNER").exists());
+    open_db(dir.path());
+}
+
+/// Test that a read-only database can be read alongside a writer but not
+/// written
+#[test]
+fn test_read_only_database() {
+    let dir = TempDir::new().unwrap();
+    assert!(matches!(
+        ImageHashDB::open_read_only(&db_config(dir.path())),
+        Err(Error::Database(_))
+    ));
+
+    let db = open_db(dir.path());
+    let results = [hashed_file(dir.path(), "a.jpg", b"a.jpg")];
+    db.batch_insert_hashes(&results, &stamps_of(&results))
+        .unwrap();
+
+    let reader = ImageHashDB::open_read_only(&db_config(dir.path())).unwrap();
+    assert_eq!(
+        reader.paths_with_hash(&results[0].cryptographic).unwrap(),
+        vec![results[0].path.clone()]
+    );
+    assert!(matches!(
+        reader.batch_insert_hashes(&results, &stamps_of(&results)),
+        Err(Error::Database(_))
+    ));
+}
@@ -1,4 +1,3 @@
-use rusqlite;
 use std::path::PathBuf;
 
 /// Result type for persistence operations
@@ -58,11 +57,10 @@ impl std::error::Error for PersistenceError {
 impl From<PersistenceError> for crate::Error {
     fn from(err: PersistenceError) -> Self {
         match err {
-            PersistenceError::Database(e) => {
-                crate::Error::Unknown(format!("Database error: {}", e))
-            }
+            PersistenceError::Database(e) => crate::Error::from(e),
             PersistenceError::Path(path, _msg) => crate::Error::FileNotFound(path),
             PersistenceError::NotFound(_) => crate::Error::Unknown("Record not found".to_string()),
+            PersistenceError::Initialization(msg) => crate::Error::Configuration(msg),
             _ => crate::Error::Unknown(format!("Persistence error: {}", err)),
         }
     }
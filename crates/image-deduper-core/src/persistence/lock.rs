@@ -0,0 +1,154 @@
+/// Naming the process that has a database open
+///
+/// RocksDB locks an open database against other writers but does not record
+/// who holds the lock. A writer therefore records itself in an `OWNER` file in
+/// the database directory, so a process refused the lock can say which one
+/// holds it. SQLite has no such lock, so the owner file beside a SQLite
+/// database, `<name>-owner`, is the lock: it is created atomically and a
+/// writer finding another live owner is refused. The file is removed on
+/// close; one left by a process that died is ignored and replaced.
+///
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+const OWNER_FILE: &str = "OWNER";
+
+/// The process holding a database open for writing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockOwner {
+    /// Process id
+    pub pid: u32,
+
+    /// Command line the process was started with
+    pub command: String,
+
+    /// When it opened the database, RFC 3339
+    pub since: String,
+}
+
+impl LockOwner {
+    /// This process, opening a database now
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            since: chrono::Local::now().to_rfc3339(),
+        }
+    }
+
+    /// The live process recorded in `owner_file` as holding a database
+    pub(super) fn read(owner_file: &Path) -> Option<Self> {
+        let contents = fs::read(owner_file).ok()?;
+        let owner: Self = serde_json::from_slice(&contents).ok()?;
+        owner.is_alive().then_some(owner)
+    }
+
+    /// Record this owner in `owner_file`, replacing any other
+    ///
+    /// Failing to write the record does not stop the database being used.
+    pub(super) fn record(&self, owner_file: &Path) {
+        let written = serde_json::to_vec(self)
+            .map_err(std::io::Error::other)
+            .and_then(|contents| fs::write(owner_file, contents));
+        if let Err(e) = written {
+            warn!("Could not record {}: {}", owner_file.display(), e);
+        }
+    }
+
+    /// Record this owner in `owner_file` unless a live process already is,
+    /// returning that process
+    ///
+    /// The file is created atomically, so of two processes racing for it only
+    /// one succeeds. Failing to write the record does not stop the database
+    /// being used.
+    pub(super) fn acquire(&self, owner_file: &Path) -> Result<(), Self> {
+        // A second attempt follows removing a record left by a dead process
+        for _ in 0..2 {
+            let created = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(owner_file);
+            let e = match created {
+                Ok(mut file) => {
+                    let written = serde_json::to_vec(self)
+                        .map_err(std::io::Error::other)
+                        .and_then(|contents| file.write_all(&contents));
+                    if let Err(e) = written {
+                        warn!("Could not record {}: {}", owner_file.display(), e);
+                    }
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+            if e.kind() != ErrorKind::AlreadyExists {
+                warn!("Could not record {}: {}", owner_file.display(), e);
+                return Ok(());
+            }
+            if let Some(owner) = Self::read(owner_file) {
+                return Err(owner);
+            }
+            let _ = fs::remove_file(owner_file);
+        }
+        self.record(owner_file);
+        Ok(())
+    }
+
+    /// Remove `owner_file` if it names this process
+    pub(super) fn release(owner_file: &Path) {
+        if Self::read(owner_file).is_some_and(|owner| owner.pid == std::process::id()) {
+            let _ = fs::remove_file(owner_file);
+        }
+    }
+
+    #[cfg(unix)]
+    fn is_alive(&self) -> bool {
+        let Ok(pid) = libc::pid_t::try_from(self.pid) else {
+            return false;
+        };
+        // SAFETY: signal 0 only checks that the process exists
+        let result = unsafe { libc::kill(pid, 0) };
+        result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    #[cfg(not(unix))]
+    fn is_alive(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "process {} ({}), open since {}",
+            self.pid, self.command, self.since
+        )
+    }
+}
+
+/// The owner file of a store kept in the directory `store_path`
+pub(super) fn directory_owner_file(store_path: &Path) -> PathBuf {
+    store_path.join(OWNER_FILE)
+}
+
+/// The owner file of a store kept in the single file `store_path`
+pub(super) fn file_owner_file(store_path: &Path) -> PathBuf {
+    let mut name = store_path.as_os_str().to_owned();
+    name.push("-owner");
+    PathBuf::from(name)
+}
+
+/// The error refusing the database at `path` held by `owner`
+pub(super) fn locked(path: PathBuf, owner: LockOwner) -> Error {
+    Error::DatabaseLocked {
+        path,
+        owner: owner.to_string(),
+    }
+}
@@ -0,0 +1,106 @@
+/// An in-memory hash store
+///
+/// Nothing is written to disk, so tests using it are hermetic and a run using
+/// it starts from nothing.
+///
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use blake3::Hash as Blake3Hash;
+
+use super::store::{supersedes, HashRecord, HashStore, StoreStats};
+use crate::error::Result;
+use crate::path_identity::{same_file, same_path};
+
+/// Hash records in a map keyed by path
+#[derive(Debug, Default)]
+pub struct MemoryHashDB {
+    records: RwLock<HashMap<PathBuf, HashRecord>>,
+}
+
+impl MemoryHashDB {
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<PathBuf, HashRecord>> {
+        // A panic while holding the lock leaves the map whole
+        self.records.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<PathBuf, HashRecord>> {
+        self.records.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The key of the record for `path` in `stored`: `path` itself, or another
+/// spelling of its name that is the same file on disk
+fn entry_key(stored: &HashMap<PathBuf, HashRecord>, path: &Path) -> Option<PathBuf> {
+    if stored.contains_key(path) {
+        return Some(path.to_path_buf());
+    }
+    stored
+        .keys()
+        .find(|key| same_path(key, path) && same_file(key, path))
+        .cloned()
+}
+
+/// Store `records` in `stored`, replacing those of the same files
+fn insert_into(stored: &mut HashMap<PathBuf, HashRecord>, records: &[HashRecord]) {
+    for record in records {
+        stored.retain(|key, _| !(same_path(key, &record.path) && supersedes(&record.path, key)));
+        stored.insert(record.path.clone(), record.clone());
+    }
+}
+
+/// Remove the records of `paths` from `stored`, returning how many there were
+fn delete_from(stored: &mut HashMap<PathBuf, HashRecord>, paths: &[PathBuf]) -> usize {
+    paths
+        .iter()
+        .filter(|path| entry_key(stored, path).is_some_and(|key| stored.remove(&key).is_some()))
+        .count()
+}
+
+impl HashStore for MemoryHashDB {
+    fn insert(&self, records: &[HashRecord]) -> Result<()> {
+        insert_into(&mut self.write(), records);
+        Ok(())
+    }
+
+    fn get(&self, path: &Path) -> Result<Option<HashRecord>> {
+        let stored = self.read();
+        Ok(entry_key(&stored, path).and_then(|key| stored.get(&key).cloned()))
+    }
+
+    fn records(&self) -> Result<Vec<HashRecord>> {
+        Ok(self.read().values().cloned().collect())
+    }
+
+    fn delete(&self, paths: &[PathBuf]) -> Result<usize> {
+        Ok(delete_from(&mut self.write(), paths))
+    }
+
+    fn replace(&self, old: &[PathBuf], records: &[HashRecord]) -> Result<()> {
+        let mut stored = self.write();
+        delete_from(&mut stored, old);
+        insert_into(&mut stored, records);
+        Ok(())
+    }
+
+    fn paths_with_hash(&self, hash: &Blake3Hash) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .read()
+            .values()
+            .filter(|record| record.cryptographic == *hash)
+            .map(|record| record.path.clone())
+            .collect())
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        let stored = self.read();
+        Ok(StoreStats {
+            records: stored.len(),
+            perceptual_hashes: stored
+                .values()
+                .filter(|record| record.perceptual.is_some())
+                .count(),
+        })
+    }
+}
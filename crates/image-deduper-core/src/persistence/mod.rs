@@ -1,5 +1,24 @@
 mod db;
+mod error;
+mod lock;
+mod memory;
 mod models;
+mod schema;
+mod sqlite;
+mod store;
 
-pub use db::ImageHashDB;
-pub use models::StoredImage;
+pub use db::{DBImageData, DatabasePruneReport, ImageHashDB};
+pub use error::{PersistenceError, PersistenceResult};
+pub use lock::LockOwner;
+pub use memory::MemoryHashDB;
+pub use models::{FileStamp, StoredImage};
+pub use schema::SCHEMA_VERSION;
+pub use sqlite::SqliteHashDB;
+pub use store::{database_path, open_store, HashRecord, HashStore, StoreStats};
+
+#[cfg(test)]
+mod db_tests;
+#[cfg(test)]
+mod schema_tests;
+#[cfg(test)]
+mod store_tests;
@@ -1,11 +1,18 @@
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
 
+use super::schema::{decode_blake3, path_to_bytes};
+use super::store::HashRecord;
+use crate::error::Result;
 use crate::{processing::types::PHash, ImageFile, ImageFormat};
 
 /// Representation of a stored image with its hashes
+///
+/// This is the row model of the SQLite store.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoredImage {
     /// ID in the database
@@ -20,6 +27,12 @@ pub struct StoredImage {
     /// Last modified timestamp (stored as unix timestamp)
     pub last_modified: i64,
 
+    /// Nanoseconds of the last modified time, when the file was stamped
+    pub modified_nanos: Option<u32>,
+
+    /// Inode number, when the file was stamped
+    pub inode: Option<u64>,
+
     /// Image format
     pub format: ImageFormat,
 
@@ -30,29 +43,73 @@ pub struct StoredImage {
     pub cryptographic_hash: Vec<u8>,
 
     /// Perceptual hash for similarity detection
-    pub perceptual_hash: u64,
+    pub perceptual_hash: Option<PHash>,
 }
 
 impl StoredImage {
     /// Create a new stored image from an image file and its hashes
     pub fn new(image: &ImageFile, cryptographic_hash: Vec<u8>, perceptual_hash: PHash) -> Self {
-        // Extract the u64 value for storage
-        let hash_value = match perceptual_hash {
-            PHash::Standard(hash) => hash,
-            PHash::Enhanced(array) => array[0], // Store only first 64 bits from enhanced hash
-        };
         Self {
             id: None,
             path: image.path.clone(),
             size: image.size,
             last_modified: system_time_to_unix_timestamp(&image.last_modified),
+            modified_nanos: None,
+            inode: None,
             format: image.format.clone(),
             created: image.created.as_ref().map(system_time_to_unix_timestamp),
             cryptographic_hash,
-            perceptual_hash: hash_value,
+            perceptual_hash: Some(perceptual_hash),
+        }
+    }
+
+    /// The row of a hash record
+    ///
+    /// An unstamped record has size and modified time 0.
+    pub fn from_record(record: &HashRecord) -> Self {
+        let format = record
+            .path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(ImageFormat::from_extension)
+            .unwrap_or_else(|| ImageFormat::Other(String::new()));
+        Self {
+            id: None,
+            path: record.path.clone(),
+            size: record.stamp.map_or(0, |stamp| stamp.size),
+            last_modified: record.stamp.map_or(0, |stamp| stamp.modified_secs),
+            modified_nanos: record.stamp.map(|stamp| stamp.modified_nanos),
+            inode: record.stamp.map(|stamp| stamp.inode),
+            format,
+            created: None,
+            cryptographic_hash: record.cryptographic.as_bytes().to_vec(),
+            perceptual_hash: record.perceptual,
         }
     }
 
+    /// The hash record of this row
+    ///
+    /// Fails with `Error::CorruptRecord` if the cryptographic hash is not a
+    /// Blake3 hash.
+    pub fn to_record(&self) -> Result<HashRecord> {
+        Ok(HashRecord {
+            path: self.path.clone(),
+            cryptographic: decode_blake3(path_to_bytes(&self.path), &self.cryptographic_hash)?,
+            perceptual: self.perceptual_hash,
+            stamp: self.stamp(),
+        })
+    }
+
+    /// The file's stamp when it was hashed, if it was stamped
+    pub fn stamp(&self) -> Option<FileStamp> {
+        Some(FileStamp {
+            size: self.size,
+            modified_secs: self.last_modified,
+            modified_nanos: self.modified_nanos?,
+            inode: self.inode?,
+        })
+    }
+
     /// Convert to an ImageFile
     pub fn to_image_file(&self) -> ImageFile {
         ImageFile {
@@ -70,6 +127,51 @@ impl StoredImage {
     }
 }
 
+/// What a file looked like when it was hashed
+///
+/// A stored hash is only trusted while the file's stamp is unchanged, so files
+/// edited in place are hashed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    /// File size in bytes
+    pub size: u64,
+
+    /// Seconds of the last modified time since the Unix epoch
+    pub modified_secs: i64,
+
+    /// Nanoseconds of the last modified time
+    pub modified_nanos: u32,
+
+    /// Inode number, or 0 where the platform has none
+    pub inode: u64,
+}
+
+impl FileStamp {
+    /// Read the stamp of the file at `path`
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let (modified_secs, modified_nanos) =
+            match metadata.modified()?.duration_since(SystemTime::UNIX_EPOCH) {
+                Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+                Err(e) => (
+                    -(e.duration().as_secs() as i64),
+                    e.duration().subsec_nanos(),
+                ),
+            };
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+
+        Ok(Self {
+            size: metadata.len(),
+            modified_secs,
+            modified_nanos,
+            inode,
+        })
+    }
+}
+
 // Helper function to convert SystemTime to Unix timestamp
 fn system_time_to_unix_timestamp(time: &SystemTime) -> i64 {
     match time.duration_since(SystemTime::UNIX_EPOCH) {
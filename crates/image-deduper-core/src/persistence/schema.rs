@@ -0,0 +1,290 @@
+/// Layout, record encoding and migrations of the RocksDB store
+///
+/// Every stored file is keyed by the exact bytes of its path under these
+/// prefixes:
+/// - `pc:` its Blake3 hash, as the 32 hash bytes
+/// - `pp:` its perceptual hash, a `PHash` record
+/// - `pm:` its stamp when it was hashed, a `FileStamp` record
+/// - `cp:<hash><path>` an empty value indexing paths by Blake3 hash
+/// - `pi:<identity>\0<path>` an empty value indexing paths by their identity
+///   (see `path_identity`), so another spelling of a stored name is found
+///
+/// Records are encoded with bincode. The layout version is kept under
+/// `meta:schema_version`, and older databases are upgraded in place when
+/// opened, one migration at a time.
+///
+use std::path::{Path, PathBuf};
+
+use blake3::Hash as Blake3Hash;
+use log::{info, warn};
+use rocksdb::{Direction, IteratorMode, WriteBatch, WriteOptions, DB};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::path_identity::identity_path;
+use crate::processing::types::PHash;
+
+/// Version of the layout written by this build
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Key of the layout version record
+const VERSION_KEY: &[u8] = b"meta:schema_version";
+
+/// Prefixes of the records kept for each stored path
+pub(super) const PATH_PREFIXES: [&[u8]; 3] = [b"pc:", b"pp:", b"pm:"];
+
+/// A step upgrading the store from the version before `to`
+struct Migration {
+    to: u32,
+    description: &'static str,
+    run: fn(&DB) -> Result<()>,
+}
+
+/// Every migration, in order
+const MIGRATIONS: &[Migration] = &[Migration {
+    to: 2,
+    description: "typed records, the hash index and the identity index",
+    run: migrate_to_v2,
+}];
+
+/// Bring the store up to `SCHEMA_VERSION`
+///
+/// A new store is stamped with the current version. A store without a version
+/// record predates versioning and is version 1. A store from a newer build is
+/// refused rather than risk misreading it.
+pub(super) fn migrate(db: &DB) -> Result<()> {
+    let version = match db.get(VERSION_KEY)? {
+        Some(bytes) => decode::<u32>(VERSION_KEY, &bytes)?,
+        None if db.iterator(IteratorMode::Start).next().is_none() => SCHEMA_VERSION,
+        None => 1,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(Error::Configuration(format!(
+            "the database has schema version {}, newer than the supported version {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.to > version) {
+        info!(
+            "Migrating database to schema version {}: {}",
+            migration.to, migration.description
+        );
+        (migration.run)(db)?;
+        write_version(db, migration.to)?;
+    }
+    if db.get(VERSION_KEY)?.is_none() {
+        write_version(db, version)?;
+    }
+    Ok(())
+}
+
+/// The layout version of an open store
+pub(super) fn version(db: &DB) -> Result<u32> {
+    match db.get(VERSION_KEY)? {
+        Some(bytes) => decode(VERSION_KEY, &bytes),
+        None => Ok(1),
+    }
+}
+
+fn write_version(db: &DB, version: u32) -> Result<()> {
+    let mut batch = WriteBatch::default();
+    batch.put(VERSION_KEY, encode(&version)?);
+    write_synced(db, batch)
+}
+
+/// Version 2: perceptual hashes and stamps become bincode records, and the
+/// `cp:` hash index and `pi:` identity index are built
+///
+/// Entries whose hash cannot be read are dropped. The rest are left
+/// unstamped, so, like entries without a readable perceptual hash, their files
+/// count as changed and are hashed again on the next run. Everything, the version record included, is written
+/// at once, so an interrupted migration leaves the store as it was and simply
+/// runs again.
+fn migrate_to_v2(db: &DB) -> Result<()> {
+    let mut batch = WriteBatch::default();
+    let mut migrated = 0;
+    let mut dropped = 0;
+    for result in prefix_iter(db, b"pc:") {
+        let (key, value) = result?;
+        let path_bytes = &key[3..];
+        let phash = db.get(path_key(b"pp:", path_bytes))?;
+
+        for prefix in PATH_PREFIXES {
+            batch.delete(path_key(prefix, path_bytes));
+        }
+        if value.len() != blake3::OUT_LEN {
+            warn!(
+                "Dropping the entry for {}: its hash has {} bytes",
+                path_from_bytes(path_bytes).display(),
+                value.len()
+            );
+            dropped += 1;
+            continue;
+        }
+
+        batch.put(&key, &value);
+        batch.put(hash_index_key(&value, path_bytes), b"");
+        batch.put(identity_index_key(path_bytes), b"");
+        if let Some(phash) = phash.as_deref().and_then(legacy_phash) {
+            batch.put(path_key(b"pp:", path_bytes), encode(&phash)?);
+        }
+        migrated += 1;
+    }
+    batch.put(VERSION_KEY, encode(&2u32)?);
+    write_synced(db, batch)?;
+
+    info!(
+        "Migrated {} entries, dropped {} unreadable ones",
+        migrated, dropped
+    );
+    Ok(())
+}
+
+/// Rebuild the `cp:` hash index from the stored path hashes
+pub(super) fn rebuild_hash_index(db: &DB) -> Result<()> {
+    let mut batch = WriteBatch::default();
+    for result in prefix_iter(db, b"cp:") {
+        batch.delete(result?.0);
+    }
+    let mut indexed = 0;
+    for result in prefix_iter(db, b"pc:") {
+        let (key, value) = result?;
+        batch.put(hash_index_key(&value, &key[3..]), b"");
+        indexed += 1;
+    }
+    write_synced(db, batch)?;
+
+    info!("Indexed {} stored paths by hash", indexed);
+    Ok(())
+}
+
+/// A stored key and its value
+pub(super) type Entry = (Box<[u8]>, Box<[u8]>);
+
+/// Entries whose keys start with `prefix`, in key order
+pub(super) fn prefix_iter<'a>(
+    db: &'a DB,
+    prefix: &'a [u8],
+) -> impl Iterator<Item = Result<Entry>> + 'a {
+    db.iterator(IteratorMode::From(prefix, Direction::Forward))
+        .map(|result| result.map_err(Error::from))
+        .take_while(move |result| {
+            result
+                .as_ref()
+                .map_or(true, |(key, _)| key.starts_with(prefix))
+        })
+}
+
+/// Write a batch atomically and sync it to disk
+pub(super) fn write_synced(db: &DB, batch: WriteBatch) -> Result<()> {
+    let mut write_options = WriteOptions::default();
+    write_options.set_sync(true);
+    Ok(db.write_opt(batch, &write_options)?)
+}
+
+/// Encode a record
+pub(super) fn encode<T: Serialize>(record: &T) -> Result<Vec<u8>> {
+    bincode::serde::encode_to_vec(record, bincode::config::standard())
+        .map_err(|e| Error::Serialization(format!("Failed to encode record: {}", e)))
+}
+
+/// Decode the record stored under `key`
+pub(super) fn decode<T: DeserializeOwned>(key: &[u8], bytes: &[u8]) -> Result<T> {
+    match bincode::serde::decode_from_slice(bytes, bincode::config::standard()) {
+        Ok((record, read)) if read == bytes.len() => Ok(record),
+        Ok((_, read)) => Err(corrupt(
+            key,
+            format!("{} trailing bytes", bytes.len() - read),
+        )),
+        Err(e) => Err(corrupt(key, e.to_string())),
+    }
+}
+
+/// Decode a Blake3 hash stored under `key`
+pub(super) fn decode_blake3(key: &[u8], bytes: &[u8]) -> Result<Blake3Hash> {
+    let bytes: [u8; blake3::OUT_LEN] = bytes
+        .try_into()
+        .map_err(|_| corrupt(key, format!("a Blake3 hash of {} bytes", bytes.len())))?;
+    Ok(Blake3Hash::from(bytes))
+}
+
+fn corrupt(key: &[u8], reason: String) -> Error {
+    Error::CorruptRecord {
+        key: String::from_utf8_lossy(key).into_owned(),
+        reason,
+    }
+}
+
+/// A perceptual hash in the version 1 layout: big-endian `u64`s, 8 bytes for
+/// `Standard` and 128 for `Enhanced`
+fn legacy_phash(bytes: &[u8]) -> Option<PHash> {
+    let mut values = bytes
+        .chunks_exact(8)
+        .map(|chunk| u64::from_be_bytes(chunk.try_into().unwrap()));
+    match bytes.len() {
+        8 => values.next().map(PHash::Standard),
+        128 => {
+            let mut array = [0u64; 16];
+            for (slot, value) in array.iter_mut().zip(values) {
+                *slot = value;
+            }
+            Some(PHash::Enhanced(array))
+        }
+        _ => None,
+    }
+}
+
+/// The raw bytes of a path
+///
+/// Paths are stored exactly, so names that are not valid UTF-8 keep their own
+/// keys instead of colliding after a lossy conversion.
+pub(super) fn path_to_bytes(path: &Path) -> &[u8] {
+    path.as_os_str().as_encoded_bytes()
+}
+
+/// The bytes of a path's identity, shared by its NFC and NFD spellings; see
+/// `path_identity`
+pub(super) fn identity_bytes(path: &Path) -> Vec<u8> {
+    path_to_bytes(&identity_path(path)).to_vec()
+}
+
+/// The path stored in a key as raw bytes
+pub(super) fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        PathBuf::from(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Key of a path's record with `prefix`
+pub(super) fn path_key(prefix: &[u8], path_bytes: &[u8]) -> Vec<u8> {
+    [prefix, path_bytes].concat()
+}
+
+/// Key of a path in the hash index: `cp:`, the 32 hash bytes, then the path
+pub(super) fn hash_index_key(hash: &[u8], path_bytes: &[u8]) -> Vec<u8> {
+    [b"cp:", hash, path_bytes].concat()
+}
+
+/// Start of the identity index keys of every spelling of `path`
+///
+/// Paths cannot contain a NUL byte, so the separator keeps one identity from
+/// matching another that it is a prefix of.
+pub(super) fn identity_index_prefix(path: &Path) -> Vec<u8> {
+    [b"pi:", identity_bytes(path).as_slice(), b"\0"].concat()
+}
+
+/// Key of a stored path in the identity index
+pub(super) fn identity_index_key(path_bytes: &[u8]) -> Vec<u8> {
+    [
+        identity_index_prefix(&path_from_bytes(path_bytes)).as_slice(),
+        path_bytes,
+    ]
+    .concat()
+}
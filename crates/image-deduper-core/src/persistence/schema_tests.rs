@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rocksdb::{Options, DB};
+use tempfile::TempDir;
+
+use crate::error::Error;
+use crate::persistence::{schema, SCHEMA_VERSION};
+use crate::processing::types::PHash;
+use crate::test_utils::fixtures::open_db;
+
+/// Write entries in the unversioned layout straight into a new store
+fn write_legacy(dir: &Path, entries: &[(&[u8], &[u8])]) {
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    let db = DB::open(&options, dir.join("db")).unwrap();
+    for (key, value) in entries {
+        db.put(key, value).unwrap();
+    }
+}
+
+/// Test that a new store starts at the current version
+#[test]
+fn test_new_store_is_current() {
+    let dir = TempDir::new().unwrap();
+    assert_eq!(
+        open_db(dir.path()).schema_version().unwrap(),
+        SCHEMA_VERSION
+    );
+}
+
+/// Test upgrading an unversioned store in place
+#[test]
+fn test_migrate_unversioned_store() {
+    let dir = TempDir::new().unwrap();
+    let good = dir.path().join("good.jpg");
+    let decomposed = dir.path().join("cafe\u{301}.jpg");
+    let no_phash = dir.path().join("no_phash.jpg");
+    for path in [&good, &decomposed, &no_phash] {
+        fs::write(path, b"image").unwrap();
+    }
+    let hash = blake3::hash(b"image");
+    let key = |prefix: &str, path: &PathBuf| {
+        [prefix.as_bytes(), path.as_os_str().as_encoded_bytes()].concat()
+    };
+    let phash = 42u64.to_be_bytes();
+    let entries = [
+        (key("pc:", &good), hash.as_bytes().to_vec()),
+        (key("pp:", &good), phash.to_vec()),
+        (key("pc:", &decomposed), hash.as_bytes().to_vec()),
+        (key("pp:", &decomposed), phash.to_vec()),
+        (key("pc:", &no_phash), hash.as_bytes().to_vec()),
+        (key("pp:", &no_phash), b"odd".to_vec()),
+        (key("pc:", &dir.path().join("bad.jpg")), b"short".to_vec()),
+    ];
+    let entries: Vec<(&[u8], &[u8])> = entries
+        .iter()
+        .map(|(key, value)| (key.as_slice(), value.as_slice()))
+        .collect();
+    write_legacy(dir.path(), &entries);
+
+    let db = open_db(dir.path());
+    assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+
+    let mut stored = db.get_all_hashes().unwrap();
+    stored.sort_by(|a, b| a.path.cmp(&b.path));
+    let paths: Vec<&Path> = stored.iter().map(|entry| entry.path.as_path()).collect();
+    assert_eq!(paths, vec![decomposed.as_path(), &good, &no_phash]);
+    assert_eq!(stored[1].perceptual_hash, Some(PHash::Standard(42)));
+    assert_eq!(stored[2].perceptual_hash, None);
+    assert_eq!(db.paths_with_hash(&hash).unwrap().len(), 3);
+
+    // Nothing was stamped before, so every file is hashed again
+    assert_eq!(
+        db.find_new_images(&[good.clone(), no_phash.clone()])
+            .unwrap(),
+        vec![good, no_phash]
+    );
+}
+
+/// Test that a store from a newer build is refused instead of misread
+#[test]
+fn test_newer_store_is_refused() {
+    let dir = TempDir::new().unwrap();
+    let version = schema::encode(&(SCHEMA_VERSION + 1)).unwrap();
+    write_legacy(dir.path(), &[(b"meta:schema_version", &version)]);
+
+    let db = DB::open(&Options::default(), dir.path().join("db")).unwrap();
+    assert!(matches!(schema::migrate(&db), Err(Error::Configuration(_))));
+}
+
+/// Test that corrupted records are reported or skipped rather than panicking
+#[test]
+fn test_corrupt_records_are_recoverable() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("a.jpg");
+    fs::write(&path, b"image").unwrap();
+    let version = schema::encode(&SCHEMA_VERSION).unwrap();
+    let pc_key = [b"pc:", path.as_os_str().as_encoded_bytes()].concat();
+    let pp_key = [b"pp:", path.as_os_str().as_encoded_bytes()].concat();
+    let pm_key = [b"pm:", path.as_os_str().as_encoded_bytes()].concat();
+    write_legacy(
+        dir.path(),
+        &[
+            (b"meta:schema_version", &version),
+            (&pc_key, blake3::hash(b"image").as_bytes()),
+            (&pp_key, &[0xff; 3]),
+            (&pm_key, b"?"),
+        ],
+    );
+
+    let db = open_db(dir.path());
+    let stored = db.get_all_hashes().unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].perceptual_hash, None);
+    assert_eq!(
+        db.find_new_images(std::slice::from_ref(&path)).unwrap(),
+        vec![path]
+    );
+    assert!(matches!(
+        schema::decode::<PHash>(&pp_key, &[0xff; 3]),
+        Err(Error::CorruptRecord { .. })
+    ));
+}
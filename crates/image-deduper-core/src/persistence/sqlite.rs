@@ -0,0 +1,405 @@
+/// A SQLite hash store
+///
+/// Each stored file is a row of the `images` table, modelled on `StoredImage`,
+/// so results can be queried with plain SQL, for example the exact duplicates:
+///
+/// ```sql
+/// SELECT hex(cryptographic_hash), count(*) FROM images
+/// GROUP BY cryptographic_hash HAVING count(*) > 1;
+/// ```
+///
+/// Rows are keyed by path, and indexed by path identity (see `path_identity`)
+/// to find other spellings of a name. A path is stored as text, or as a blob
+/// when it is not valid UTF-8. Perceptual hashes are bincode
+/// `PHash` records. The table layout version is kept in `PRAGMA user_version`.
+///
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use blake3::Hash as Blake3Hash;
+use log::{info, warn};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params, Connection, OpenFlags, Params, Row};
+
+use super::error::{PersistenceError, PersistenceResult};
+use super::lock::{file_owner_file, locked, LockOwner};
+use super::models::StoredImage;
+use super::schema::{decode, encode, identity_bytes, path_from_bytes, path_to_bytes};
+use super::store::{database_path, supersedes, HashRecord, HashStore, StoreStats};
+use crate::error::Result;
+use crate::path_identity::same_file;
+use crate::processing::types::PHash;
+use crate::{Config, ImageFormat};
+
+/// Version of the table layout written by this build
+const TABLE_VERSION: i64 = 1;
+
+/// How long a write waits for another connection's write to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+const CREATE_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS images (
+        id INTEGER PRIMARY KEY,
+        identity BLOB NOT NULL,
+        path NOT NULL UNIQUE,
+        size INTEGER NOT NULL,
+        last_modified INTEGER NOT NULL,
+        modified_nanos INTEGER,
+        inode INTEGER,
+        format TEXT NOT NULL,
+        created INTEGER,
+        cryptographic_hash BLOB NOT NULL,
+        perceptual_hash BLOB
+    );
+    CREATE INDEX IF NOT EXISTS images_by_hash ON images (cryptographic_hash);
+    CREATE INDEX IF NOT EXISTS images_by_identity ON images (identity);
+";
+
+const COLUMNS: &str = "id, path, size, last_modified, modified_nanos, inode, format, created, \
+                       cryptographic_hash, perceptual_hash";
+
+const UPSERT: &str = "
+    INSERT INTO images (identity, path, size, last_modified, modified_nanos, inode, format,
+                        created, cryptographic_hash, perceptual_hash)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+    ON CONFLICT (path) DO UPDATE SET
+        identity = excluded.identity,
+        size = excluded.size,
+        last_modified = excluded.last_modified,
+        modified_nanos = excluded.modified_nanos,
+        inode = excluded.inode,
+        format = excluded.format,
+        created = excluded.created,
+        cryptographic_hash = excluded.cryptographic_hash,
+        perceptual_hash = excluded.perceptual_hash
+";
+
+/// Hash records in a SQLite database
+pub struct SqliteHashDB {
+    conn: Mutex<Connection>,
+
+    /// The file recording this process as the writer, when the database is
+    /// open for writing
+    owner_file: Option<PathBuf>,
+}
+
+impl SqliteHashDB {
+    /// Open the SQLite store configured by `config`, `database_path` with a
+    /// `.sqlite` extension
+    pub fn new(config: &Config) -> Result<Self> {
+        let store_path = database_path(config)?.with_extension("sqlite");
+
+        // Delete the data base if config.reinitialise_database is true, unless
+        // another process is using it
+        if config.reinitialise_database {
+            if let Some(owner) = LockOwner::read(&file_owner_file(&store_path)) {
+                return Err(locked(store_path, owner));
+            }
+            std::fs::remove_file(&store_path).unwrap_or_default();
+            info!("Deleted existing database at: {}", store_path.display());
+        }
+
+        info!("Opening SQLite database at: {}", store_path.display());
+        Self::open(&store_path)
+    }
+
+    /// Open the database at `path` for writing, creating it if it does not
+    /// exist
+    ///
+    /// Fails with `Error::DatabaseLocked`, naming the holder, when another
+    /// writer has it open, and with `Error::Database` when it cannot be opened
+    /// otherwise. Readers may have it open alongside; a write waits up to
+    /// `BUSY_TIMEOUT` for them.
+    pub fn open(path: &Path) -> Result<Self> {
+        let owner_file = file_owner_file(path);
+        if let Err(owner) = LockOwner::current().acquire(&owner_file) {
+            return Err(locked(path.to_path_buf(), owner));
+        }
+        match Connection::open(path)
+            .map_err(PersistenceError::from)
+            .and_then(Self::init)
+        {
+            Ok(mut store) => {
+                store.owner_file = Some(owner_file);
+                Ok(store)
+            }
+            Err(e) => {
+                LockOwner::release(&owner_file);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Open the existing database at `path` for reading only
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        info!("Opening SQLite database read-only at: {}", path.display());
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        Ok(Connection::open_with_flags(path, flags)
+            .map_err(PersistenceError::from)
+            .and_then(Self::init_read_only)?)
+    }
+
+    fn init(conn: Connection) -> PersistenceResult<Self> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        let version = table_version(&conn)?;
+        if version > TABLE_VERSION {
+            return Err(PersistenceError::Initialization(format!(
+                "the database has table version {}, newer than the supported version {}",
+                version, TABLE_VERSION
+            )));
+        }
+        conn.execute_batch(CREATE_TABLE)?;
+        conn.pragma_update(None, "user_version", TABLE_VERSION)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            owner_file: None,
+        })
+    }
+
+    fn init_read_only(conn: Connection) -> PersistenceResult<Self> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        let version = table_version(&conn)?;
+        if version != TABLE_VERSION {
+            return Err(PersistenceError::Initialization(format!(
+                "the database has table version {}, but reading it needs version {}",
+                version, TABLE_VERSION
+            )));
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+            owner_file: None,
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run `write` in one transaction
+    fn transaction<T>(
+        &self,
+        write: impl FnOnce(&Connection) -> PersistenceResult<T>,
+    ) -> PersistenceResult<T> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let result = write(&tx)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    fn query_rows(&self, filter: &str, params: impl Params) -> PersistenceResult<Vec<EncodedRow>> {
+        let conn = self.conn();
+        let mut select =
+            conn.prepare_cached(&format!("SELECT {} FROM images {}", COLUMNS, filter))?;
+        let rows = select.query_map(params, read_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+impl Drop for SqliteHashDB {
+    fn drop(&mut self) {
+        if let Some(owner_file) = &self.owner_file {
+            LockOwner::release(owner_file);
+        }
+    }
+}
+
+impl HashStore for SqliteHashDB {
+    fn insert(&self, records: &[HashRecord]) -> Result<()> {
+        Ok(self.transaction(|conn| insert_rows(conn, records))?)
+    }
+
+    fn get(&self, path: &Path) -> Result<Option<HashRecord>> {
+        let id = entry_id(&self.conn(), path).map_err(PersistenceError::from)?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        let rows = self.query_rows("WHERE id = ?1", [id])?;
+        rows.into_iter().next().map(to_record).transpose()
+    }
+
+    /// Every stored record
+    ///
+    /// Rows with an unreadable hash are skipped with a warning, as in
+    /// `ImageHashDB::get_all_hashes`.
+    fn records(&self) -> Result<Vec<HashRecord>> {
+        Ok(self
+            .query_rows("", [])?
+            .into_iter()
+            .filter_map(|row| {
+                let path = row.0.path.clone();
+                to_record(row)
+                    .inspect_err(|e| warn!("Skipping {}: {}", path.display(), e))
+                    .ok()
+            })
+            .collect())
+    }
+
+    fn delete(&self, paths: &[PathBuf]) -> Result<usize> {
+        Ok(self.transaction(|conn| delete_rows(conn, paths))?)
+    }
+
+    fn replace(&self, old: &[PathBuf], records: &[HashRecord]) -> Result<()> {
+        Ok(self.transaction(|conn| {
+            delete_rows(conn, old)?;
+            insert_rows(conn, records)
+        })?)
+    }
+
+    fn paths_with_hash(&self, hash: &Blake3Hash) -> Result<Vec<PathBuf>> {
+        let rows = self.query_rows("WHERE cryptographic_hash = ?1", [hash.as_bytes()])?;
+        Ok(rows.into_iter().map(|row| row.0.path).collect())
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        let counts = self.conn().query_row(
+            "SELECT count(*), count(perceptual_hash) FROM images",
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        );
+        let (records, perceptual_hashes) = counts.map_err(PersistenceError::from)?;
+        Ok(StoreStats {
+            records: records as usize,
+            perceptual_hashes: perceptual_hashes as usize,
+        })
+    }
+}
+
+/// Store `records`, replacing the rows of the same files
+fn insert_rows(conn: &Connection, records: &[HashRecord]) -> PersistenceResult<()> {
+    let mut upsert = conn.prepare_cached(UPSERT)?;
+    let mut delete = conn.prepare_cached("DELETE FROM images WHERE id = ?1")?;
+    for record in records {
+        // The file renamed to another spelling, or a filesystem that
+        // normalizes names reported it under one
+        for (id, spelling) in spellings(conn, &record.path)? {
+            if supersedes(&record.path, &spelling) {
+                delete.execute([id])?;
+            }
+        }
+
+        let image = StoredImage::from_record(record);
+        let perceptual_hash = image
+            .perceptual_hash
+            .map(|phash| encode(&phash))
+            .transpose()
+            .map_err(|e| PersistenceError::Other(e.to_string()))?;
+        upsert.execute(params![
+            identity_bytes(&image.path),
+            path_value(&image.path),
+            image.size as i64,
+            image.last_modified,
+            image.modified_nanos,
+            image.inode.map(|inode| inode as i64),
+            format_name(&image.format),
+            image.created,
+            image.cryptographic_hash,
+            perceptual_hash,
+        ])?;
+    }
+    Ok(())
+}
+
+/// Remove the rows of `paths`, returning how many there were
+fn delete_rows(conn: &Connection, paths: &[PathBuf]) -> PersistenceResult<usize> {
+    let mut delete = conn.prepare_cached("DELETE FROM images WHERE id = ?1")?;
+    let mut deleted = 0;
+    for path in paths {
+        if let Some(id) = entry_id(conn, path)? {
+            deleted += delete.execute([id])?;
+        }
+    }
+    Ok(deleted)
+}
+
+fn table_version(conn: &Connection) -> PersistenceResult<i64> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// The ids and paths of the rows stored under any spelling of `path`'s name
+fn spellings(conn: &Connection, path: &Path) -> rusqlite::Result<Vec<(i64, PathBuf)>> {
+    let mut select = conn.prepare_cached("SELECT id, path FROM images WHERE identity = ?1")?;
+    let rows = select.query_map([identity_bytes(path)], |row| {
+        Ok((row.get(0)?, path_column(row, 1)?))
+    })?;
+    rows.collect()
+}
+
+/// The id of the row for `path`: the one stored under `path` itself, or else
+/// under another spelling of its name that is the same file on disk
+fn entry_id(conn: &Connection, path: &Path) -> rusqlite::Result<Option<i64>> {
+    let spellings = spellings(conn, path)?;
+    Ok(spellings
+        .iter()
+        .find(|(_, spelling)| spelling == path)
+        .or_else(|| {
+            spellings
+                .iter()
+                .find(|(_, spelling)| same_file(path, spelling))
+        })
+        .map(|(id, _)| *id))
+}
+
+/// A row, with its perceptual hash still encoded
+type EncodedRow = (StoredImage, Option<Vec<u8>>);
+
+/// The path in column `index`, stored as text or a blob
+fn path_column(row: &Row, index: usize) -> rusqlite::Result<PathBuf> {
+    match row.get_ref(index)? {
+        ValueRef::Text(bytes) | ValueRef::Blob(bytes) => Ok(path_from_bytes(bytes)),
+        other => Err(rusqlite::Error::InvalidColumnType(
+            index,
+            String::from("path"),
+            other.data_type(),
+        )),
+    }
+}
+
+fn read_row(row: &Row) -> rusqlite::Result<EncodedRow> {
+    let image = StoredImage {
+        id: row.get(0)?,
+        path: path_column(row, 1)?,
+        size: row.get::<_, i64>(2)? as u64,
+        last_modified: row.get(3)?,
+        modified_nanos: row.get(4)?,
+        inode: row.get::<_, Option<i64>>(5)?.map(|inode| inode as u64),
+        format: ImageFormat::from_extension(&row.get::<_, String>(6)?),
+        created: row.get(7)?,
+        cryptographic_hash: row.get(8)?,
+        perceptual_hash: None,
+    };
+    Ok((image, row.get(9)?))
+}
+
+/// The record of a row; an unreadable perceptual hash is left out with a
+/// warning
+fn to_record((mut image, perceptual_hash): EncodedRow) -> Result<HashRecord> {
+    image.perceptual_hash = perceptual_hash.and_then(|bytes| {
+        decode::<PHash>(path_to_bytes(&image.path), &bytes)
+            .inspect_err(|e| warn!("Ignoring {}: {}", image.path.display(), e))
+            .ok()
+    });
+    image.to_record()
+}
+
+/// A path as text, or as a blob when it is not valid UTF-8
+fn path_value(path: &Path) -> Value {
+    match path.to_str() {
+        Some(text) => Value::Text(text.to_owned()),
+        None => Value::Blob(path_to_bytes(path).to_vec()),
+    }
+}
+
+/// The name a format is stored under; `ImageFormat::from_extension` reads it
+/// back
+fn format_name(format: &ImageFormat) -> &str {
+    match format {
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::Png => "png",
+        ImageFormat::Tiff => "tiff",
+        ImageFormat::Heic => "heic",
+        ImageFormat::Raw => "raw",
+        ImageFormat::Other(ext) => ext,
+    }
+}
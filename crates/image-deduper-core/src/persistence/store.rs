@@ -0,0 +1,275 @@
+/// Storage backends for image hashes
+///
+/// `HashStore` is what the deduper needs from a backend: insert, lookup,
+/// iterate, delete and stats. The maintenance passes built on top of it have
+/// default implementations that a backend may replace with faster ones.
+/// Three backends are provided: RocksDB (`ImageHashDB`), SQLite
+/// (`SqliteHashDB`), whose table can be queried with plain SQL, and an
+/// in-memory map (`MemoryHashDB`) for hermetic tests. `Config::storage_backend`
+/// chooses one.
+///
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use blake3::Hash as Blake3Hash;
+use directories::ProjectDirs;
+use log::{debug, info};
+
+use super::db::{DBImageData, DatabasePruneReport, ImageHashDB};
+use super::memory::MemoryHashDB;
+use super::models::FileStamp;
+use super::sqlite::SqliteHashDB;
+use crate::config::{Config, StorageBackend};
+use crate::error::{Error, Result};
+use crate::path_identity::same_file;
+use crate::processing::compute_cryptographic;
+use crate::processing::types::{ImageHashResult, PHash};
+
+/// A stored file and its hashes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashRecord {
+    /// The file's real path
+    pub path: PathBuf,
+
+    /// Blake3 hash of the file contents
+    pub cryptographic: Blake3Hash,
+
+    /// Perceptual hash, if it could be read
+    pub perceptual: Option<PHash>,
+
+    /// The file's stamp when it was hashed; unstamped records are stale
+    pub stamp: Option<FileStamp>,
+}
+
+impl HashRecord {
+    /// The record of a hashed file, stamped before it was hashed
+    pub fn new(result: &ImageHashResult, stamp: Option<FileStamp>) -> Self {
+        Self {
+            path: result.path.clone(),
+            cryptographic: result.cryptographic,
+            perceptual: Some(result.perceptual),
+            stamp,
+        }
+    }
+}
+
+impl From<HashRecord> for DBImageData {
+    fn from(record: HashRecord) -> Self {
+        Self {
+            path: record.path,
+            crypto_hash: Some(record.cryptographic),
+            perceptual_hash: record.perceptual,
+        }
+    }
+}
+
+/// Counts of what a store holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Number of stored files
+    pub records: usize,
+
+    /// Number of them with a perceptual hash
+    pub perceptual_hashes: usize,
+}
+
+/// A backend storing image hashes by path
+///
+/// Records are keyed by the exact path. A path not stored under its own
+/// spelling still finds the record of another spelling of its name that is
+/// the same file on disk, and storing a record replaces those of other
+/// spellings that are no longer separate files; see `path_identity`. Two
+/// files whose names differ only by normalization keep separate records.
+pub trait HashStore: Send + Sync {
+    /// Store `records`, replacing those of the same paths
+    ///
+    /// The records are written atomically: after a crash either all of them
+    /// are stored or none are.
+    fn insert(&self, records: &[HashRecord]) -> Result<()>;
+
+    /// The record of `path`, if stored
+    fn get(&self, path: &Path) -> Result<Option<HashRecord>>;
+
+    /// Every stored record
+    fn records(&self) -> Result<Vec<HashRecord>>;
+
+    /// Remove the records of `paths`, returning how many were stored
+    fn delete(&self, paths: &[PathBuf]) -> Result<usize>;
+
+    /// Remove the records of `old` and store `records` in their place
+    ///
+    /// Both are done in one write: after a crash either the old records are
+    /// stored or the new ones are.
+    fn replace(&self, old: &[PathBuf], records: &[HashRecord]) -> Result<()>;
+
+    /// Paths of the stored files whose contents have Blake3 hash `hash`
+    fn paths_with_hash(&self, hash: &Blake3Hash) -> Result<Vec<PathBuf>>;
+
+    /// Counts of the stored records
+    fn stats(&self) -> Result<StoreStats>;
+
+    /// Whether the record of `path` is stored and stamped as the file is now
+    fn is_current(&self, path: &Path) -> Result<bool> {
+        let stamp = self.get(path)?.and_then(|record| record.stamp);
+        Ok(stamp.is_some() && stamp == FileStamp::of(path).ok())
+    }
+
+    /// Those of `paths` that are not stored, or changed since they were hashed
+    fn find_new_images(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let mut new_paths = Vec::new();
+        for path in paths {
+            if !self.is_current(path)? {
+                new_paths.push(path.clone());
+            }
+        }
+        info!(
+            "Found {} new images out of {} total",
+            new_paths.len(),
+            paths.len()
+        );
+        Ok(new_paths)
+    }
+
+    /// Remove the records of stored files that no longer exist
+    ///
+    /// Only paths under `root` are checked when it is given, and a root that
+    /// does not exist is refused. With `dry_run` nothing is removed.
+    fn prune_missing(&self, root: Option<&Path>, dry_run: bool) -> Result<DatabasePruneReport> {
+        if let Some(root) = root.filter(|root| !root.exists()) {
+            return Err(Error::FileNotFound(root.to_path_buf()));
+        }
+
+        let mut report = DatabasePruneReport::default();
+        for record in self.records()? {
+            if root.is_some_and(|root| !record.path.starts_with(root)) {
+                continue;
+            }
+            report.checked += 1;
+            if !record.path.exists() {
+                report.missing.push(record.path);
+            }
+        }
+        info!(
+            "Found {} of {} stored paths missing",
+            report.missing.len(),
+            report.checked
+        );
+        if !dry_run {
+            report.removed = self.delete(&report.missing)?;
+            info!("Removed {} entries for missing files", report.removed);
+        }
+        Ok(report)
+    }
+
+    /// Find which of `paths` are stored files that moved, and move their
+    /// records, returning the paths left unmatched
+    ///
+    /// A moved file has the size and modified time of a stored file that no
+    /// longer exists, and the same Blake3 hash. Its record keeps its
+    /// perceptual hash.
+    fn relocate_moved(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut by_stamp: HashMap<(u64, i64, u32), Vec<HashRecord>> = HashMap::new();
+        for record in self.records()? {
+            if let Some(stamp) = record.stamp {
+                by_stamp
+                    .entry((stamp.size, stamp.modified_secs, stamp.modified_nanos))
+                    .or_default()
+                    .push(record);
+            }
+        }
+
+        let mut unmatched = Vec::new();
+        let mut moved = Vec::new();
+        let mut old_paths = Vec::new();
+        for path in paths {
+            let Ok(stamp) = FileStamp::of(path) else {
+                unmatched.push(path.clone());
+                continue;
+            };
+            let found =
+                match by_stamp.get_mut(&(stamp.size, stamp.modified_secs, stamp.modified_nanos)) {
+                    Some(candidates) => {
+                        // A file still at its stored path was copied, not moved
+                        candidates.retain(|candidate| !candidate.path.exists());
+                        if candidates.is_empty() {
+                            None
+                        } else {
+                            compute_cryptographic(path)
+                                .ok()
+                                .and_then(|hash| {
+                                    candidates
+                                        .iter()
+                                        .position(|candidate| candidate.cryptographic == hash)
+                                })
+                                .map(|index| candidates.swap_remove(index))
+                        }
+                    }
+                    None => None,
+                };
+            let Some(record) = found else {
+                unmatched.push(path.clone());
+                continue;
+            };
+
+            debug!("{} moved to {}", record.path.display(), path.display());
+            old_paths.push(record.path);
+            moved.push(HashRecord {
+                path: path.clone(),
+                stamp: Some(stamp),
+                ..record
+            });
+        }
+
+        self.replace(&old_paths, &moved)?;
+        info!(
+            "Matched {} of {} unseen paths to stored files that moved",
+            moved.len(),
+            paths.len()
+        );
+        Ok(unmatched)
+    }
+
+    /// Write buffered changes to disk
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Reclaim space left by replaced and deleted records
+    fn compact(&self) {}
+}
+
+/// Whether a record of `path` replaces the stored record of `other`, another
+/// spelling of its name: it does unless `other` is still a different file
+pub(super) fn supersedes(path: &Path, other: &Path) -> bool {
+    path != other && (!other.exists() || same_file(path, other))
+}
+
+/// Where the store for `config` is kept: `database_name` under the system's
+/// config directory
+pub fn database_path(config: &Config) -> Result<PathBuf> {
+    let config_dir = ProjectDirs::from("com", "lyonef", "image_deduper")
+        .map(|proj_dirs| proj_dirs.config_dir().to_path_buf())
+        .ok_or_else(|| {
+            Error::Configuration(String::from("could not find the system's config directory"))
+        })?;
+
+    Ok(config_dir.join(config.database_name.as_deref().unwrap_or("image_hash_db")))
+}
+
+/// Open the backend `config` chooses, for reading only with
+/// `Config::read_only_database`
+pub fn open_store(config: &Config) -> Result<Box<dyn HashStore>> {
+    Ok(match (config.storage_backend, config.read_only_database) {
+        (StorageBackend::RocksDb, false) => Box::new(ImageHashDB::new(config)?),
+        (StorageBackend::RocksDb, true) => Box::new(ImageHashDB::open_read_only(config)?),
+        (StorageBackend::Sqlite, false) => Box::new(SqliteHashDB::new(config)?),
+        (StorageBackend::Sqlite, true) => Box::new(SqliteHashDB::open_read_only(
+            &database_path(config)?.with_extension("sqlite"),
+        )?),
+        (StorageBackend::Memory, _) => Box::new(MemoryHashDB::default()),
+    })
+}
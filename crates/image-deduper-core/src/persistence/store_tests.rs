@@ -0,0 +1,312 @@
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::error::{DatabaseError, Error};
+use crate::path_identity::same_file;
+use crate::persistence::{
+    open_store, FileStamp, HashRecord, HashStore, ImageHashDB, MemoryHashDB, SqliteHashDB,
+    StoreStats,
+};
+use crate::processing::types::PHash;
+use crate::{Config, StorageBackend};
+
+/// Write a file and a stamped record of it
+fn stored_file(dir: &Path, name: &str) -> HashRecord {
+    let path = dir.join(name);
+    fs::write(&path, name).unwrap();
+    HashRecord {
+        cryptographic: blake3::hash(name.as_bytes()),
+        perceptual: Some(PHash::Standard(42)),
+        stamp: Some(FileStamp::of(&path).unwrap()),
+        path,
+    }
+}
+
+/// The backends under test, each kept inside `dir`
+fn stores(dir: &Path) -> Vec<(&'static str, Box<dyn HashStore>)> {
+    vec![
+        (
+            "rocksdb",
+            Box::new(
+                ImageHashDB::new(&Config {
+                    database_name: Some(dir.join("db").to_string_lossy().into_owned()),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ),
+        ),
+        (
+            "sqlite",
+            Box::new(SqliteHashDB::open(&dir.join("db.sqlite")).unwrap()),
+        ),
+        ("memory", Box::new(MemoryHashDB::default())),
+    ]
+}
+
+/// Test that every backend inserts, looks up, iterates and deletes alike
+#[test]
+fn test_backends_store_records() {
+    let dir = TempDir::new().unwrap();
+    for (name, store) in stores(dir.path()) {
+        let files = TempDir::new().unwrap();
+        let mut records = vec![
+            stored_file(files.path(), "a.jpg"),
+            stored_file(files.path(), "b.jpg"),
+            stored_file(files.path(), "c.png"),
+        ];
+        records[1].perceptual = Some(PHash::Enhanced([7; 16]));
+        records[2].perceptual = None;
+        records[2].stamp = None;
+        store.insert(&records).unwrap();
+
+        for record in &records {
+            assert_eq!(
+                store.get(&record.path).unwrap().as_ref(),
+                Some(record),
+                "{}",
+                name
+            );
+        }
+        let mut stored = store.records().unwrap();
+        stored.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(stored, records, "{}", name);
+        assert_eq!(
+            store.stats().unwrap(),
+            StoreStats {
+                records: 3,
+                perceptual_hashes: 2
+            },
+            "{}",
+            name
+        );
+        assert!(store.is_current(&records[0].path).unwrap(), "{}", name);
+        assert!(!store.is_current(&records[2].path).unwrap(), "{}", name);
+
+        // Replacing a record moves it in the hash index
+        let replaced = HashRecord {
+            cryptographic: records[1].cryptographic,
+            ..records[0].clone()
+        };
+        store.insert(std::slice::from_ref(&replaced)).unwrap();
+        let mut same = store.paths_with_hash(&records[1].cryptographic).unwrap();
+        same.sort();
+        assert_eq!(
+            same,
+            vec![records[0].path.clone(), records[1].path.clone()],
+            "{}",
+            name
+        );
+        assert!(store
+            .paths_with_hash(&records[0].cryptographic)
+            .unwrap()
+            .is_empty());
+
+        let missing = files.path().join("missing.jpg");
+        assert_eq!(
+            store.delete(&[records[0].path.clone(), missing]).unwrap(),
+            1,
+            "{}",
+            name
+        );
+        assert_eq!(store.get(&records[0].path).unwrap(), None, "{}", name);
+        assert_eq!(store.stats().unwrap().records, 2, "{}", name);
+    }
+}
+
+/// Test that every backend replaces records in one write
+#[test]
+fn test_backends_replace_records() {
+    let dir = TempDir::new().unwrap();
+    for (name, store) in stores(dir.path()) {
+        let files = TempDir::new().unwrap();
+        let old = stored_file(files.path(), "old.jpg");
+        let kept = stored_file(files.path(), "kept.jpg");
+        store.insert(&[old.clone(), kept.clone()]).unwrap();
+
+        let new = HashRecord {
+            path: files.path().join("new.jpg"),
+            ..old.clone()
+        };
+        store
+            .replace(std::slice::from_ref(&old.path), std::slice::from_ref(&new))
+            .unwrap();
+        let mut stored = store.records().unwrap();
+        stored.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(stored, vec![kept, new.clone()], "{}", name);
+        assert_eq!(
+            store.paths_with_hash(&old.cryptographic).unwrap(),
+            vec![new.path],
+            "{}",
+            name
+        );
+    }
+}
+
+/// Test that the default maintenance passes work on the in-memory backend
+#[test]
+fn test_default_maintenance_passes() {
+    let dir = TempDir::new().unwrap();
+    let store = MemoryHashDB::default();
+    let records = [
+        stored_file(dir.path(), "a.jpg"),
+        stored_file(dir.path(), "b.jpg"),
+        stored_file(dir.path(), "gone.jpg"),
+    ];
+    store.insert(&records).unwrap();
+
+    fs::create_dir(dir.path().join("sorted")).unwrap();
+    let moved = dir.path().join("sorted/renamed.jpg");
+    fs::rename(&records[0].path, &moved).unwrap();
+    fs::remove_file(&records[2].path).unwrap();
+    let new_file = dir.path().join("new.jpg");
+    fs::write(&new_file, b"new").unwrap();
+
+    let paths = vec![moved.clone(), records[1].path.clone(), new_file.clone()];
+    let unseen = store.find_new_images(&paths).unwrap();
+    assert_eq!(unseen, vec![moved.clone(), new_file.clone()]);
+    assert_eq!(store.relocate_moved(&unseen).unwrap(), vec![new_file]);
+    let relocated = store.get(&moved).unwrap().unwrap();
+    assert_eq!(relocated.perceptual, Some(PHash::Standard(42)));
+    assert!(store.is_current(&moved).unwrap());
+
+    let report = store.prune_missing(None, false).unwrap();
+    assert_eq!(report.missing, vec![records[2].path.clone()]);
+    assert_eq!(report.removed, 1);
+    assert_eq!(store.stats().unwrap().records, 2);
+}
+
+/// Test that SQLite rows can be read with plain SQL
+#[test]
+fn test_sqlite_rows_are_queryable() {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("db.sqlite");
+    let store = SqliteHashDB::open(&db_path).unwrap();
+    let record = stored_file(dir.path(), "a.jpg");
+    store.insert(std::slice::from_ref(&record)).unwrap();
+    drop(store);
+
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    let (path, format, hash): (String, String, String) = conn
+        .query_row(
+            "SELECT path, format, hex(cryptographic_hash) FROM images",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(path, record.path.to_string_lossy());
+    assert_eq!(format, "jpeg");
+    assert_eq!(hash, record.cryptographic.to_hex().to_uppercase());
+}
+
+/// Test that a SQLite store from a newer build is refused
+#[test]
+fn test_sqlite_newer_table_is_refused() {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("db.sqlite");
+    rusqlite::Connection::open(&db_path)
+        .unwrap()
+        .pragma_update(None, "user_version", 99)
+        .unwrap();
+
+    assert!(matches!(
+        SqliteHashDB::open(&db_path),
+        Err(Error::Configuration(_))
+    ));
+}
+
+/// Test that every backend keeps separate records for two files whose names
+/// differ only by normalization, and one record for a file renamed between
+/// the spellings
+#[test]
+fn test_backends_keep_spellings_apart() {
+    let dir = TempDir::new().unwrap();
+    for (name, store) in stores(dir.path()) {
+        let files = TempDir::new().unwrap();
+        let composed = stored_file(files.path(), "caf\u{e9}.jpg");
+        let decomposed = stored_file(files.path(), "cafe\u{301}.jpg");
+        if same_file(&composed.path, &decomposed.path) {
+            return;
+        }
+        store.insert(std::slice::from_ref(&composed)).unwrap();
+        store.insert(std::slice::from_ref(&decomposed)).unwrap();
+        assert_eq!(store.stats().unwrap().records, 2, "{}", name);
+        assert_eq!(
+            store.get(&composed.path).unwrap().as_ref(),
+            Some(&composed),
+            "{}",
+            name
+        );
+        assert_eq!(
+            store.get(&decomposed.path).unwrap().as_ref(),
+            Some(&decomposed),
+            "{}",
+            name
+        );
+
+        // Once the decomposed file is gone, the composed one replaces it
+        fs::remove_file(&decomposed.path).unwrap();
+        store.insert(std::slice::from_ref(&composed)).unwrap();
+        assert_eq!(store.records().unwrap(), vec![composed], "{}", name);
+    }
+}
+
+/// Test that a SQLite store that cannot be opened is an error, not a panic
+#[test]
+fn test_sqlite_unopenable_store_is_an_error() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("file");
+    fs::write(&file, b"not a directory").unwrap();
+
+    assert!(matches!(
+        SqliteHashDB::open(&file.join("db.sqlite")),
+        Err(Error::Database(DatabaseError::Sqlite(_)))
+    ));
+    assert!(matches!(
+        SqliteHashDB::open_read_only(&dir.path().join("missing.sqlite")),
+        Err(Error::Database(DatabaseError::Sqlite(_)))
+    ));
+    let config = Config {
+        storage_backend: StorageBackend::Sqlite,
+        database_name: Some(file.join("db").to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    assert!(SqliteHashDB::new(&config).is_err());
+    assert!(open_store(&config).is_err());
+}
+
+/// Test that a second SQLite writer is refused with the holder named, that
+/// the database is not deleted under it, and that readers are let in
+#[test]
+fn test_sqlite_locked_database_names_its_owner() {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("db.sqlite");
+    let store = SqliteHashDB::open(&db_path).unwrap();
+    let record = stored_file(dir.path(), "a.jpg");
+    store.insert(std::slice::from_ref(&record)).unwrap();
+
+    match SqliteHashDB::open(&db_path) {
+        Err(Error::DatabaseLocked { path, owner }) => {
+            assert_eq!(path, db_path);
+            assert!(owner.contains(&std::process::id().to_string()), "{}", owner);
+        }
+        other => panic!("expected a locked database, got {:?}", other.err()),
+    }
+    let config = Config {
+        storage_backend: StorageBackend::Sqlite,
+        database_name: Some(dir.path().join("db").to_string_lossy().into_owned()),
+        reinitialise_database: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        SqliteHashDB::new(&config),
+        Err(Error::DatabaseLocked { .. })
+    ));
+    let reader = SqliteHashDB::open_read_only(&db_path).unwrap();
+    assert_eq!(reader.get(&record.path).unwrap(), Some(record));
+
+    drop(store);
+    assert!(!dir.path().join("db.sqlite-owner").exists());
+    SqliteHashDB::open(&db_path).unwrap();
+}
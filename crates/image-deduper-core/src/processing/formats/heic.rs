@@ -36,13 +36,17 @@ pub fn process_heic_image<P: AsRef<Path>>(path: P) -> Result<PHash> {
         }
     }
 
-    // Use libheif to read the file
-    let path_str = path_ref
-        .to_str()
-        .ok_or_else(|| heic_error("Invalid path for HEIC file"))?;
-
-    let ctx = libheif_rs::HeifContext::read_from_file(path_str)
-        .map_err(|e| heic_error(&format!("Failed to read HEIC: {}", e)))?;
+    // Use libheif to read the file. It only opens paths that are valid UTF-8,
+    // so any other file is read here and handed over as bytes
+    let data;
+    let ctx = match path_ref.to_str() {
+        Some(path_str) => libheif_rs::HeifContext::read_from_file(path_str),
+        None => {
+            data = std::fs::read(path_ref)?;
+            libheif_rs::HeifContext::read_from_bytes(&data)
+        }
+    }
+    .map_err(|e| heic_error(&format!("Failed to read HEIC: {}", e)))?;
 
     // Get primary image handle
     let handle = ctx
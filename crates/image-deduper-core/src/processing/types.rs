@@ -1,10 +1,11 @@
 /// PHash enum and core methods
 ///
 use blake3::Hash as Blake3Hash;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 /// A perceptual hash that can be either a 64-bit value (8x8) or a 1024-bit value (32x32)
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PHash {
     /// Standard 64-bit perceptual hash (8x8 grid)
     Standard(u64),
@@ -65,6 +66,22 @@ impl PHash {
         }
     }
 
+    /// Number of bits in the hash
+    pub fn bits(&self) -> u32 {
+        match self {
+            PHash::Standard(_) => 64,
+            PHash::Enhanced(_) => 1024,
+        }
+    }
+
+    /// View the hash bits as 64-bit words, lowest bits first
+    pub fn as_words(&self) -> &[u64] {
+        match self {
+            PHash::Standard(hash) => std::slice::from_ref(hash),
+            PHash::Enhanced(hash_array) => hash_array,
+        }
+    }
+
     /// Get the underlying 64-bit hash value (for compatibility)
     pub fn as_u64(&self) -> u64 {
         match self {
@@ -77,7 +94,7 @@ impl PHash {
 /// For cached image loading and processing
 pub struct ImageCache {
     buffer_size: usize,
-    cache: std::collections::HashMap<String, PHash>,
+    cache: std::collections::HashMap<std::path::PathBuf, PHash>,
 }
 
 impl ImageCache {
@@ -93,9 +110,9 @@ impl ImageCache {
         path: P,
         hash_fn: impl Fn(&P) -> Result<PHash, image::ImageError>,
     ) -> Result<PHash, image::ImageError> {
-        let path_str = path.as_ref().to_string_lossy().to_string();
+        let path_buf = path.as_ref().to_path_buf();
 
-        if let Some(hash) = self.cache.get(&path_str) {
+        if let Some(hash) = self.cache.get(&path_buf) {
             return Ok(*hash);
         }
 
@@ -107,7 +124,7 @@ impl ImageCache {
             self.cache.clear();
         }
 
-        self.cache.insert(path_str, hash);
+        self.cache.insert(path_buf, hash);
         Ok(hash)
     }
 }
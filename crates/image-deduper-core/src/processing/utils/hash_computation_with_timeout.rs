@@ -6,7 +6,7 @@ use blake3::Hash as Blake3Hash;
 use log::info;
 use once_cell::sync::Lazy;
 use std::collections::HashSet;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 use super::file_validation::get_file_extension;
@@ -16,13 +16,12 @@ use crate::error::Result;
 
 // Global skip list for problematic files that consistently cause timeouts
 // This list persists across multiple function calls to prevent repeated timeouts
-static PROBLEMATIC_FILES: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+static PROBLEMATIC_FILES: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
 
 /// Check if a file is known to be problematic
 pub fn is_problematic(path: &Path) -> bool {
-    let path_str = path.to_string_lossy().to_string();
     if let Ok(skip_list) = PROBLEMATIC_FILES.lock() {
-        skip_list.contains(&path_str)
+        skip_list.contains(path)
     } else {
         false
     }
@@ -30,12 +29,11 @@ pub fn is_problematic(path: &Path) -> bool {
 
 /// Mark a file as problematic for future reference
 pub fn mark_as_problematic(path: &Path) {
-    let path_str = path.to_string_lossy().to_string();
     if let Ok(mut skip_list) = PROBLEMATIC_FILES.lock() {
-        skip_list.insert(path_str.clone());
+        skip_list.insert(path.to_path_buf());
         info!(
             "Added {} to problematic files skip list (now {} entries)",
-            path_str,
+            path.display(),
             skip_list.len()
         );
     }
@@ -0,0 +1,180 @@
+/// Content-addressed safety backups
+///
+/// Each backup is stored once per Blake3 hash, as `<backup_dir>/<ab>/<hash>`
+/// where `ab` is the first two hex digits of the hash. A copy is fsynced and
+/// re-hashed before it counts as a backup, so a destructive action never
+/// proceeds on an unverified copy.
+///
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use blake3::Hash as Blake3Hash;
+use log::{info, warn};
+
+use crate::action::file_ops::{replace_with_copy, verify_hash};
+use crate::config::BackupRetention;
+use crate::error::{Error, Result};
+
+/// Summary of a backup pruning pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackupPruneReport {
+    /// Number of backups removed
+    pub removed: usize,
+
+    /// Bytes freed
+    pub freed_bytes: u64,
+
+    /// Number of backups kept
+    pub kept: usize,
+}
+
+/// A directory of content-addressed backups
+#[derive(Debug, Clone)]
+pub struct BackupStore {
+    root: PathBuf,
+}
+
+impl BackupStore {
+    /// Use `root` as the backup directory, creating it when needed
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// Where the backup of `hash` is (or would be) stored
+    pub fn path_for(&self, hash: &Blake3Hash) -> PathBuf {
+        let hex = hash.to_hex();
+        self.root.join(&hex[..2]).join(hex.as_str())
+    }
+
+    /// Whether a verified backup of `hash` exists
+    pub fn contains(&self, hash: &Blake3Hash) -> bool {
+        verify_hash(&self.path_for(hash), hash).is_ok()
+    }
+
+    /// Back up `path`, whose contents must hash to `hash`
+    ///
+    /// An existing verified backup of the same contents is reused, and its
+    /// age reset so retention counts from the latest use.
+    pub fn store(&self, path: &Path, hash: &Blake3Hash) -> Result<PathBuf> {
+        let backup = self.path_for(hash);
+        if self.contains(hash) {
+            File::options()
+                .write(true)
+                .open(&backup)?
+                .set_modified(SystemTime::now())?;
+            return Ok(backup);
+        }
+
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        replace_with_copy(path, &backup, hash)?;
+        info!("Backed up {} to {}", path.display(), backup.display());
+        Ok(backup)
+    }
+
+    /// Restore the backup of `hash` to `to`
+    ///
+    /// Never overwrites a file with different contents; restoring over an
+    /// identical file does nothing.
+    pub fn restore(&self, hash: &Blake3Hash, to: &Path) -> Result<()> {
+        let backup = self.path_for(hash);
+        verify_hash(&backup, hash).map_err(|_| {
+            Error::SafetyCheck(format!(
+                "no intact backup of {} in {}",
+                hash.to_hex(),
+                self.root.display()
+            ))
+        })?;
+
+        if fs::symlink_metadata(to).is_ok() {
+            if verify_hash(to, hash).is_ok() {
+                return Ok(());
+            }
+            return Err(Error::SafetyCheck(format!(
+                "refusing to restore over {}: it holds different contents",
+                to.display()
+            )));
+        }
+
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        replace_with_copy(&backup, to, hash)?;
+        info!("Restored {} from {}", to.display(), backup.display());
+        Ok(())
+    }
+
+    /// Remove backups the retention policy no longer keeps
+    ///
+    /// Backups older than `max_age` go first, then the oldest remaining ones
+    /// until the total is within `max_bytes`.
+    pub fn prune(&self, retention: &BackupRetention) -> Result<BackupPruneReport> {
+        let mut backups = self.list()?;
+        // Oldest first
+        backups.sort_by_key(|(_, modified, _)| *modified);
+
+        let now = SystemTime::now();
+        let mut total: u64 = backups.iter().map(|(_, _, size)| size).sum();
+        let mut report = BackupPruneReport::default();
+
+        for (path, modified, size) in backups {
+            let expired = retention
+                .max_age
+                .is_some_and(|max_age| now.duration_since(modified).is_ok_and(|age| age > max_age));
+            let over_budget = retention
+                .max_bytes
+                .is_some_and(|max_bytes| total > max_bytes);
+
+            if !(expired || over_budget) {
+                report.kept += 1;
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    total -= size;
+                    report.removed += 1;
+                    report.freed_bytes += size;
+                }
+                Err(e) => {
+                    warn!("Failed to remove backup {}: {}", path.display(), e);
+                    report.kept += 1;
+                }
+            }
+        }
+
+        info!(
+            "Pruned {} backups ({} bytes), {} kept",
+            report.removed, report.freed_bytes, report.kept
+        );
+        Ok(report)
+    }
+
+    /// Every backup with its modification time and size
+    fn list(&self) -> Result<Vec<(PathBuf, SystemTime, u64)>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut backups = Vec::new();
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&shard)? {
+                let entry = entry?;
+                // Skip copies still in progress
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let metadata = entry.metadata()?;
+                backups.push((entry.path(), metadata.modified()?, metadata.len()));
+            }
+        }
+        Ok(backups)
+    }
+}
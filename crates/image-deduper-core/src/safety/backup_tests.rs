@@ -0,0 +1,151 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use tempfile::TempDir;
+
+use crate::config::{BackupRetention, Config};
+use crate::error::Error;
+use crate::safety::{BackupStore, SafetyManager};
+use crate::types::ActionType;
+
+/// Store `contents` from a file under `dir`, returning the backup's path
+fn store(backups: &BackupStore, dir: &Path, contents: &[u8]) -> std::path::PathBuf {
+    let path = dir.join("file.jpg");
+    fs::write(&path, contents).unwrap();
+    backups.store(&path, &blake3::hash(contents)).unwrap()
+}
+
+/// Set a backup's modification time to `age` ago
+fn age(path: &Path, age: Duration) {
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() - age)
+        .unwrap();
+}
+
+/// Test that backups are content-addressed and verified
+#[test]
+fn test_store_is_content_addressed() {
+    let dir = TempDir::new().unwrap();
+    let backups = BackupStore::new(&dir.path().join("backup"));
+    let hash = blake3::hash(b"image");
+
+    let backup = store(&backups, dir.path(), b"image");
+
+    let hex = hash.to_hex();
+    assert_eq!(
+        backup,
+        dir.path().join("backup").join(&hex[..2]).join(hex.as_str())
+    );
+    assert_eq!(fs::read(&backup).unwrap(), b"image");
+    assert!(backups.contains(&hash));
+
+    // The same contents are stored once
+    assert_eq!(store(&backups, dir.path(), b"image"), backup);
+}
+
+/// Test that a file not matching its expected hash is not backed up
+#[test]
+fn test_store_refuses_wrong_hash() {
+    let dir = TempDir::new().unwrap();
+    let backups = BackupStore::new(&dir.path().join("backup"));
+    let path = dir.path().join("file.jpg");
+    fs::write(&path, b"edited image").unwrap();
+    let hash = blake3::hash(b"image");
+
+    assert!(backups.store(&path, &hash).is_err());
+    assert!(!backups.contains(&hash));
+}
+
+/// Test restoring a backup, which never overwrites different contents
+#[test]
+fn test_restore_from_backup() {
+    let dir = TempDir::new().unwrap();
+    let config = Config {
+        backup_dir: Some(dir.path().join("backup")),
+        ..Default::default()
+    };
+    let safety = SafetyManager::new(&config);
+    let path = dir.path().join("file.jpg");
+    fs::write(&path, b"image").unwrap();
+    let hash = blake3::hash(b"image");
+
+    let backup = safety
+        .backup_before(ActionType::Delete, &path, &hash)
+        .unwrap();
+    assert!(backup.is_some());
+    fs::remove_file(&path).unwrap();
+
+    safety.restore_from_backup(&hash, &path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"image");
+
+    fs::write(&path, b"edited image").unwrap();
+    let result = safety.restore_from_backup(&hash, &path);
+    assert!(matches!(result, Err(Error::SafetyCheck(_))), "{:?}", result);
+    assert_eq!(fs::read(&path).unwrap(), b"edited image");
+}
+
+/// Test that only actions removing a file back it up, and only when configured
+#[test]
+fn test_backup_only_when_needed() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("file.jpg");
+    fs::write(&path, b"image").unwrap();
+    let hash = blake3::hash(b"image");
+
+    let safety = SafetyManager::new(&Config {
+        backup_dir: Some(dir.path().join("backup")),
+        ..Default::default()
+    });
+    assert!(safety
+        .backup_before(ActionType::Move, &path, &hash)
+        .unwrap()
+        .is_none());
+
+    let safety = SafetyManager::new(&Config {
+        backup_dir: None,
+        ..Default::default()
+    });
+    assert!(safety
+        .backup_before(ActionType::Delete, &path, &hash)
+        .unwrap()
+        .is_none());
+    assert!(matches!(
+        safety.restore_from_backup(&hash, &path),
+        Err(Error::Configuration(_))
+    ));
+}
+
+/// Test pruning by age, then oldest first down to the size budget
+#[test]
+fn test_prune() {
+    let dir = TempDir::new().unwrap();
+    let backups = BackupStore::new(&dir.path().join("backup"));
+    let expired = store(&backups, dir.path(), b"expired");
+    let oldest = store(&backups, dir.path(), b"oldest");
+    let newest = store(&backups, dir.path(), b"newest");
+    age(&expired, Duration::from_secs(3 * 86400));
+    age(&oldest, Duration::from_secs(3600));
+
+    let report = backups
+        .prune(&BackupRetention {
+            max_age: Some(Duration::from_secs(86400)),
+            max_bytes: Some(6),
+        })
+        .unwrap();
+
+    assert_eq!(report.removed, 2);
+    assert_eq!(report.freed_bytes, 13);
+    assert_eq!(report.kept, 1);
+    assert!(!expired.exists());
+    assert!(!oldest.exists());
+    assert!(newest.exists());
+
+    // The default retention keeps everything
+    let report = backups.prune(&BackupRetention::default()).unwrap();
+    assert_eq!(report.removed, 0);
+    assert_eq!(report.kept, 1);
+}
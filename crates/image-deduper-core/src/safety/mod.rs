@@ -0,0 +1,308 @@
+//! Safety checks before any file is changed
+//!
+//! Every action passes through the `SafetyManager` right before it is applied.
+//! It refuses, with `Error::SafetyCheck` and a reason naming the file, when:
+//! - the duplicate or its original changed since the scan (Blake3 re-hash)
+//! - the original is missing, unreadable or not a regular file
+//! - the original is the duplicate itself, under another name or through a
+//!   link
+//! - the action would remove an exact duplicate while no other copy of its
+//!   contents is known; a similar image's duplicate may go, as its original
+//!   keeps the image
+//!
+//! Files under `Config::protected_roots` are refused outright with
+//! `Error::ProtectedPath`.
+//!
+//! Before a run starts, `preflight` checks that it fits in the free space of
+//! the volumes it writes to and stays within the configured run limits.
+//!
+//! With `Config::backup_dir` set, a file is also copied into the backup store,
+//! and the copy verified, before any action that removes it.
+//!
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use blake3::Hash as Blake3Hash;
+use log::{debug, warn};
+
+use crate::config::{BackupRetention, Config};
+use crate::error::{Error, Result};
+use crate::path_identity::{is_within, same_file};
+use crate::processing::compute_cryptographic;
+use crate::types::ActionType;
+
+mod backup;
+mod preflight;
+
+pub use backup::{BackupPruneReport, BackupStore};
+pub use preflight::PendingAction;
+
+/// Test module for safety functionality
+#[cfg(test)]
+mod safety_tests;
+
+#[cfg(test)]
+mod backup_tests;
+
+#[cfg(test)]
+mod preflight_tests;
+
+/// Hashes of a duplicate and its original, verified just before acting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifiedPair {
+    /// Current Blake3 hash of the duplicate
+    pub duplicate: Blake3Hash,
+
+    /// Current Blake3 hash of the original
+    pub original: Blake3Hash,
+}
+
+#[derive(Debug)]
+pub struct SafetyManager {
+    config: Config,
+
+    /// Paths known to hold each hash, as verified during this session
+    copies: Mutex<HashMap<Blake3Hash, HashSet<PathBuf>>>,
+
+    /// Where files are backed up before being removed, if anywhere
+    backups: Option<BackupStore>,
+
+    /// How long backups are kept
+    retention: BackupRetention,
+}
+
+impl SafetyManager {
+    /// Create a new SafetyManager with the provided configuration
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            copies: Mutex::new(HashMap::new()),
+            backups: config.backup_dir.as_deref().map(BackupStore::new),
+            retention: config.backup_retention,
+        }
+    }
+
+    /// Check that a run of `actions` may start
+    ///
+    /// Fails if what the run writes to `duplicates_dir` and the backup
+    /// directory won't fit in their free space, or if the run exceeds
+    /// `max_files_per_run` or `max_directory_percent`. The limits, but not
+    /// free space, can be overridden with `override_run_limits`.
+    pub fn preflight(&self, actions: &[PendingAction], duplicates_dir: &Path) -> Result<()> {
+        preflight::check_free_space(
+            actions,
+            duplicates_dir,
+            self.config.backup_dir.as_deref(),
+            &preflight::mounted_volumes(),
+        )?;
+
+        let limits = preflight::check_limits(
+            actions,
+            self.config.max_files_per_run,
+            self.config.max_directory_percent,
+        );
+        match limits {
+            Err(Error::SafetyCheck(reason)) if self.config.override_run_limits => {
+                warn!("Run limit overridden: {}", reason);
+                Ok(())
+            }
+            Err(Error::SafetyCheck(reason)) => Err(Error::SafetyCheck(format!(
+                "{} (set override_run_limits to proceed anyway)",
+                reason
+            ))),
+            other => other,
+        }
+    }
+
+    /// Check that `action_type` may be applied to `duplicate` of `original`
+    ///
+    /// Both files are re-hashed and compared with the hashes recorded at scan
+    /// time; a file without a recorded hash can't be verified and is refused.
+    pub fn check_action(
+        &self,
+        action_type: ActionType,
+        duplicate: &Path,
+        scanned_duplicate: Option<Blake3Hash>,
+        original: &Path,
+        scanned_original: Option<Blake3Hash>,
+    ) -> Result<VerifiedPair> {
+        let refuse = |reason: String| {
+            Error::SafetyCheck(format!(
+                "refusing to {:?} {}: {}",
+                action_type,
+                duplicate.display(),
+                reason
+            ))
+        };
+
+        if protected_root(&self.config.protected_roots, duplicate).is_some() {
+            return Err(Error::ProtectedPath(duplicate.to_path_buf()));
+        }
+        match fs::symlink_metadata(duplicate) {
+            Ok(metadata) if metadata.is_file() => {}
+            Ok(_) => return Err(refuse("it is not a regular file".to_string())),
+            Err(e) => return Err(refuse(format!("it can't be read ({})", e))),
+        }
+        // A symlink original would be hashed through its target, which may be
+        // the duplicate itself
+        if fs::symlink_metadata(original).is_ok_and(|metadata| !metadata.is_file()) {
+            return Err(refuse(format!(
+                "original {} is not a regular file",
+                original.display()
+            )));
+        }
+        if is_same_file(duplicate, original) {
+            return Err(refuse("it is its own original".to_string()));
+        }
+
+        let original_hash = compute_cryptographic(original).map_err(|e| {
+            refuse(format!(
+                "original {} is missing or unreadable ({})",
+                original.display(),
+                e
+            ))
+        })?;
+        let duplicate_hash = compute_cryptographic(duplicate)
+            .map_err(|e| refuse(format!("it can't be read ({})", e)))?;
+
+        for (path, scanned, current) in [
+            (original, scanned_original, original_hash),
+            (duplicate, scanned_duplicate, duplicate_hash),
+        ] {
+            match scanned {
+                None => {
+                    return Err(refuse(format!(
+                        "no Blake3 hash was recorded for {} at scan time",
+                        path.display()
+                    )))
+                }
+                Some(scanned) if scanned != current => {
+                    return Err(refuse(format!("{} changed since the scan", path.display())))
+                }
+                Some(_) => {}
+            }
+        }
+
+        let mut copies = self.copies.lock().unwrap();
+        copies
+            .entry(original_hash)
+            .or_default()
+            .insert(original.to_path_buf());
+        let known = copies.entry(duplicate_hash).or_default();
+        known.insert(duplicate.to_path_buf());
+
+        // The original of a similar image was verified above and keeps the
+        // image, though not these exact bytes
+        let exact = duplicate_hash == original_hash;
+        if removes_copy(action_type) && exact && !known.iter().any(|path| path != duplicate) {
+            return Err(refuse(format!(
+                "it is the last remaining copy of {}",
+                duplicate_hash.to_hex()
+            )));
+        }
+
+        debug!("Safety checks passed for {}", duplicate.display());
+        Ok(VerifiedPair {
+            duplicate: duplicate_hash,
+            original: original_hash,
+        })
+    }
+
+    /// Record that an action was applied, so later checks see the copy as gone
+    pub fn record_action(&self, action_type: ActionType, duplicate: &Path, hash: &Blake3Hash) {
+        if !removes_copy(action_type) {
+            return;
+        }
+        if let Some(known) = self.copies.lock().unwrap().get_mut(hash) {
+            known.remove(duplicate);
+        }
+    }
+
+    /// Back up `path` before `action_type` removes it
+    ///
+    /// Returns where the verified backup is, or `None` when the action keeps
+    /// the contents or no backup directory is configured. The action must not
+    /// proceed if this fails.
+    pub fn backup_before(
+        &self,
+        action_type: ActionType,
+        path: &Path,
+        hash: &Blake3Hash,
+    ) -> Result<Option<PathBuf>> {
+        let Some(backups) = self.backups.as_ref().filter(|_| removes_copy(action_type)) else {
+            return Ok(None);
+        };
+        backups.store(path, hash).map(Some).map_err(|e| {
+            Error::SafetyCheck(format!(
+                "refusing to {:?} {}: backup failed ({})",
+                action_type,
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Restore the backed-up contents with Blake3 hash `hash` to `to`
+    pub fn restore_from_backup(&self, hash: &Blake3Hash, to: &Path) -> Result<()> {
+        self.backup_store()?.restore(hash, to)
+    }
+
+    /// Remove backups past `Config::backup_retention`
+    pub fn prune_backups(&self) -> Result<BackupPruneReport> {
+        self.backup_store()?.prune(&self.retention)
+    }
+
+    fn backup_store(&self) -> Result<&BackupStore> {
+        self.backups
+            .as_ref()
+            .ok_or_else(|| Error::Configuration("no backup directory is configured".to_string()))
+    }
+}
+
+/// The root in `roots` that `path` is under, if any
+///
+/// Paths are compared by `path_identity::is_within`, so relative paths,
+/// symlinked directories and other spellings of a name are recognised.
+pub fn protected_root<'a>(roots: &'a [PathBuf], path: &Path) -> Option<&'a Path> {
+    roots
+        .iter()
+        .find(|root| is_within(path, root))
+        .map(PathBuf::as_path)
+}
+
+/// Whether two paths are one name for the same file: spelled alike, spelled
+/// differently on a filesystem that normalizes names, or reached through
+/// symlinked directories
+///
+/// Hard links are separate names, so removing one keeps the contents.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    let canonical = |path: &Path| fs::canonicalize(path).ok();
+    a == b
+        || canonical(a).is_some_and(|path| Some(path) == canonical(b))
+        || (same_file(a, b) && !hard_linked(a))
+}
+
+/// Whether the file at `path` has more than one name
+#[cfg(unix)]
+fn hard_linked(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.nlink() > 1)
+}
+
+#[cfg(not(unix))]
+fn hard_linked(_path: &Path) -> bool {
+    false
+}
+
+/// Whether an action leaves the duplicate's path without its own copy of the contents
+///
+/// A moved file keeps its contents under the duplicates directory.
+fn removes_copy(action_type: ActionType) -> bool {
+    match action_type {
+        ActionType::Move => false,
+        ActionType::Delete | ActionType::Symlink | ActionType::Hardlink => true,
+    }
+}
@@ -0,0 +1,148 @@
+/// Checks made before a run changes anything
+///
+/// A run must fit in the free space of every volume it writes to: moved files
+/// land on the volume of `duplicates_dir` unless they are already on it, and
+/// files about to be removed are first copied to the backup directory. It must
+/// also stay within the configured caps on how many files it touches, overall
+/// and per directory.
+///
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use blake3::Hash as Blake3Hash;
+use sysinfo::Disks;
+
+use crate::error::{Error, Result};
+use crate::types::ActionType;
+
+/// A file a run is about to act on
+#[derive(Debug, Clone, Copy)]
+pub struct PendingAction<'a> {
+    /// The action to apply
+    pub action_type: ActionType,
+
+    /// The duplicate to act on
+    pub path: &'a Path,
+
+    /// Its Blake3 hash, if known; backups of the same contents are stored once
+    pub hash: Option<Blake3Hash>,
+}
+
+/// A mounted volume and its free space
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Volume {
+    pub(super) mount_point: PathBuf,
+    pub(super) available: u64,
+}
+
+/// The volumes currently mounted
+pub(super) fn mounted_volumes() -> Vec<Volume> {
+    Disks::new_with_refreshed_list()
+        .list()
+        .iter()
+        .map(|disk| Volume {
+            mount_point: disk.mount_point().to_path_buf(),
+            available: disk.available_space(),
+        })
+        .collect()
+}
+
+/// Check that what `actions` will write fits on the volumes it goes to
+pub(super) fn check_free_space(
+    actions: &[PendingAction],
+    duplicates_dir: &Path,
+    backup_dir: Option<&Path>,
+    volumes: &[Volume],
+) -> Result<()> {
+    let duplicates_volume = volume_of(duplicates_dir, volumes);
+    let backup_volume = backup_dir.and_then(|dir| volume_of(dir, volumes));
+
+    let mut needed: HashMap<&Path, (u64, u64)> = HashMap::new();
+    let mut backed_up: HashSet<Blake3Hash> = HashSet::new();
+    for action in actions {
+        let target = match action.action_type {
+            // A rename on the same volume writes nothing
+            ActionType::Move => {
+                duplicates_volume.filter(|&volume| volume_of(action.path, volumes) != Some(volume))
+            }
+            _ => backup_volume.filter(|_| action.hash.is_none_or(|hash| backed_up.insert(hash))),
+        };
+        let Some(volume) = target else {
+            continue;
+        };
+        let size = fs::metadata(action.path).map_or(0, |metadata| metadata.len());
+        needed
+            .entry(volume.mount_point.as_path())
+            .or_insert((0, volume.available))
+            .0 += size;
+    }
+
+    for (mount_point, (bytes, available)) in needed {
+        if bytes > available {
+            return Err(Error::SafetyCheck(format!(
+                "not enough free space on {}: the run needs {} bytes, {} are available",
+                mount_point.display(),
+                bytes,
+                available
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check the caps on how many files one run may act on
+pub(super) fn check_limits(
+    actions: &[PendingAction],
+    max_files: Option<usize>,
+    max_directory_percent: Option<u8>,
+) -> Result<()> {
+    if let Some(max_files) = max_files.filter(|&max_files| actions.len() > max_files) {
+        return Err(Error::SafetyCheck(format!(
+            "the run would act on {} files, more than the limit of {}",
+            actions.len(),
+            max_files
+        )));
+    }
+
+    let Some(percent) = max_directory_percent else {
+        return Ok(());
+    };
+    let mut per_directory: BTreeMap<&Path, usize> = BTreeMap::new();
+    for action in actions {
+        let directory = match action.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        *per_directory.entry(directory).or_default() += 1;
+    }
+    for (directory, count) in per_directory {
+        let total = fs::read_dir(directory)
+            .map_err(|e| Error::SafetyCheck(format!("can't read {}: {}", directory.display(), e)))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .count();
+        if count * 100 > usize::from(percent) * total {
+            return Err(Error::SafetyCheck(format!(
+                "the run would act on {} of the {} files in {}, more than {}%",
+                count,
+                total,
+                directory.display(),
+                percent
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The volume holding `path`, or where it would be created
+fn volume_of<'a>(path: &Path, volumes: &'a [Volume]) -> Option<&'a Volume> {
+    let absolute = std::path::absolute(path).ok()?;
+    let existing = absolute
+        .ancestors()
+        .find_map(|ancestor| fs::canonicalize(ancestor).ok())?;
+    volumes
+        .iter()
+        .filter(|volume| existing.starts_with(&volume.mount_point))
+        .max_by_key(|volume| volume.mount_point.components().count())
+}
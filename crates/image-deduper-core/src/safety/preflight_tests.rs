@@ -0,0 +1,140 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::safety::preflight::{check_free_space, check_limits, Volume};
+use crate::safety::{PendingAction, SafetyManager};
+use crate::types::ActionType;
+
+/// Write `count` files of `size` bytes into `dir`
+fn write_files(dir: &Path, count: usize, size: usize) -> Vec<PathBuf> {
+    fs::create_dir_all(dir).unwrap();
+    (0..count)
+        .map(|n| {
+            let path = dir.join(format!("{}.jpg", n));
+            fs::write(&path, vec![n as u8; size]).unwrap();
+            path
+        })
+        .collect()
+}
+
+fn pending(action_type: ActionType, paths: &[PathBuf]) -> Vec<PendingAction<'_>> {
+    paths
+        .iter()
+        .map(|path| PendingAction {
+            action_type,
+            path,
+            hash: Some(blake3::hash(&fs::read(path).unwrap())),
+        })
+        .collect()
+}
+
+fn assert_refused(result: crate::Result<()>, expected: &str) {
+    match result {
+        Err(Error::SafetyCheck(reason)) => assert!(reason.contains(expected), "{}", reason),
+        other => panic!("expected a safety check failure, got {:?}", other),
+    }
+}
+
+/// Test the cap on the number of files per run
+#[test]
+fn test_max_files_per_run() {
+    let dir = TempDir::new().unwrap();
+    let paths = write_files(dir.path(), 3, 1);
+    let actions = pending(ActionType::Delete, &paths);
+
+    assert!(check_limits(&actions, Some(3), None).is_ok());
+    assert_refused(
+        check_limits(&actions, Some(2), None),
+        "more than the limit of 2",
+    );
+}
+
+/// Test the cap on the share of a directory acted on
+#[test]
+fn test_max_directory_percent() {
+    let dir = TempDir::new().unwrap();
+    let paths = write_files(&dir.path().join("photos"), 4, 1);
+
+    assert!(check_limits(&pending(ActionType::Move, &paths[..2]), None, Some(50)).is_ok());
+    assert_refused(
+        check_limits(&pending(ActionType::Move, &paths[..3]), None, Some(50)),
+        "3 of the 4 files",
+    );
+
+    // A directory that can't be listed refuses the run like any other check
+    let gone = PendingAction {
+        action_type: ActionType::Move,
+        path: &dir.path().join("gone/a.jpg"),
+        hash: None,
+    };
+    assert_refused(check_limits(&[gone], None, Some(50)), "can't read");
+}
+
+/// Test free space on the backup and duplicates volumes
+#[test]
+fn test_free_space() {
+    let dir = TempDir::new().unwrap();
+    let source = dir.path().join("photos");
+    let paths = write_files(&source, 2, 100);
+    let backup_dir = dir.path().join("backup");
+    let duplicates_dir = dir.path().join("duplicates");
+    let volumes = |available| {
+        vec![
+            Volume {
+                mount_point: fs::canonicalize(&source).unwrap(),
+                available: 1_000_000,
+            },
+            Volume {
+                mount_point: fs::canonicalize(dir.path()).unwrap(),
+                available,
+            },
+        ]
+    };
+
+    // Backups of both deleted files need 200 bytes
+    let deletes = pending(ActionType::Delete, &paths);
+    assert!(check_free_space(&deletes, &duplicates_dir, Some(&backup_dir), &volumes(200)).is_ok());
+    assert_refused(
+        check_free_space(&deletes, &duplicates_dir, Some(&backup_dir), &volumes(199)),
+        "needs 200 bytes, 199 are available",
+    );
+    assert!(check_free_space(&deletes, &duplicates_dir, None, &volumes(0)).is_ok());
+
+    // Moves to another volume need their size there; renames need nothing
+    let moves = pending(ActionType::Move, &paths);
+    assert_refused(
+        check_free_space(&moves, &duplicates_dir, None, &volumes(199)),
+        "needs 200 bytes",
+    );
+    assert!(check_free_space(&moves, &source.join("duplicates"), None, &volumes(0)).is_ok());
+}
+
+/// Test that run limits abort a run unless overridden
+#[test]
+fn test_preflight_override() {
+    let dir = TempDir::new().unwrap();
+    let paths = write_files(dir.path(), 2, 1);
+    let actions = pending(ActionType::Move, &paths);
+    let config = Config {
+        max_files_per_run: Some(1),
+        backup_dir: None,
+        ..Default::default()
+    };
+    let duplicates_dir = dir.path().join("duplicates");
+
+    let safety = SafetyManager::new(&config);
+    assert_refused(
+        safety.preflight(&actions, &duplicates_dir),
+        "set override_run_limits",
+    );
+
+    let safety = SafetyManager::new(&Config {
+        override_run_limits: true,
+        ..config
+    });
+    assert!(safety.preflight(&actions, &duplicates_dir).is_ok());
+}
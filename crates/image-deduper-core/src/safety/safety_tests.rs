@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::safety::SafetyManager;
+use crate::types::ActionType;
+
+/// Write a file and return its path and hash
+fn write(dir: &Path, name: &str, contents: &[u8]) -> (PathBuf, blake3::Hash) {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    (path, blake3::hash(contents))
+}
+
+/// Assert that a check was refused with a reason containing `expected`
+fn assert_refused(result: crate::Result<crate::safety::VerifiedPair>, expected: &str) {
+    match result {
+        Err(Error::SafetyCheck(reason)) => assert!(reason.contains(expected), "{}", reason),
+        other => panic!("expected a safety check failure, got {:?}", other),
+    }
+}
+
+/// Test that exact duplicates pass and report their verified hashes
+#[test]
+fn test_unchanged_duplicate_passes() {
+    let dir = TempDir::new().unwrap();
+    let (original, hash) = write(dir.path(), "a.jpg", b"image");
+    let (duplicate, _) = write(dir.path(), "b.jpg", b"image");
+    let safety = SafetyManager::new(&Config::default());
+
+    let verified = safety
+        .check_action(
+            ActionType::Delete,
+            &duplicate,
+            Some(hash),
+            &original,
+            Some(hash),
+        )
+        .unwrap();
+
+    assert_eq!(verified.duplicate, hash);
+    assert_eq!(verified.original, hash);
+}
+
+/// Test that files changed since the scan are refused
+#[test]
+fn test_changed_files_are_refused() {
+    let dir = TempDir::new().unwrap();
+    let (original, hash) = write(dir.path(), "a.jpg", b"image");
+    let (duplicate, _) = write(dir.path(), "b.jpg", b"edited image");
+    let safety = SafetyManager::new(&Config::default());
+
+    assert_refused(
+        safety.check_action(
+            ActionType::Move,
+            &duplicate,
+            Some(hash),
+            &original,
+            Some(hash),
+        ),
+        "b.jpg changed since the scan",
+    );
+
+    let stale = blake3::hash(b"old image");
+    assert_refused(
+        safety.check_action(
+            ActionType::Move,
+            &duplicate,
+            Some(blake3::hash(b"edited image")),
+            &original,
+            Some(stale),
+        ),
+        "a.jpg changed since the scan",
+    );
+
+    assert_refused(
+        safety.check_action(ActionType::Move, &duplicate, None, &original, Some(hash)),
+        "no Blake3 hash was recorded",
+    );
+}
+
+/// Test that a missing original is refused
+#[test]
+fn test_missing_original_is_refused() {
+    let dir = TempDir::new().unwrap();
+    let (duplicate, hash) = write(dir.path(), "b.jpg", b"image");
+    let original = dir.path().join("a.jpg");
+    let safety = SafetyManager::new(&Config::default());
+
+    assert_refused(
+        safety.check_action(
+            ActionType::Delete,
+            &duplicate,
+            Some(hash),
+            &original,
+            Some(hash),
+        ),
+        "missing or unreadable",
+    );
+}
+
+/// Test that an original which is the duplicate under another name is refused
+#[cfg(unix)]
+#[test]
+fn test_original_linked_to_duplicate_is_refused() {
+    let dir = TempDir::new().unwrap();
+    let (duplicate, hash) = write(dir.path(), "b.jpg", b"image");
+    let safety = SafetyManager::new(&Config::default());
+    let check = |original: &Path| {
+        safety.check_action(
+            ActionType::Delete,
+            &duplicate,
+            Some(hash),
+            original,
+            Some(hash),
+        )
+    };
+
+    let symlink = dir.path().join("a.jpg");
+    std::os::unix::fs::symlink(&duplicate, &symlink).unwrap();
+    assert_refused(check(&symlink), "not a regular file");
+
+    // The duplicate itself, reached through a symlinked directory
+    let linked_dir = dir.path().join("linked");
+    std::os::unix::fs::symlink(dir.path(), &linked_dir).unwrap();
+    assert_refused(check(&linked_dir.join("b.jpg")), "its own original");
+    assert!(duplicate.exists());
+}
+
+/// Test that a similar image's duplicate can be removed while its original
+/// keeps the image
+#[test]
+fn test_similar_duplicate_can_be_removed() {
+    let dir = TempDir::new().unwrap();
+    let (original, original_hash) = write(dir.path(), "a.jpg", b"image");
+    let (similar, similar_hash) = write(dir.path(), "b.jpg", b"resized image");
+    let safety = SafetyManager::new(&Config::default());
+    let check = |action_type| {
+        safety.check_action(
+            action_type,
+            &similar,
+            Some(similar_hash),
+            &original,
+            Some(original_hash),
+        )
+    };
+
+    for action_type in [ActionType::Move, ActionType::Delete, ActionType::Symlink] {
+        let verified = check(action_type).unwrap();
+        assert_eq!(verified.duplicate, similar_hash);
+        assert_eq!(verified.original, original_hash);
+    }
+
+    fs::remove_file(&original).unwrap();
+    assert_refused(check(ActionType::Delete), "missing or unreadable");
+}
+
+/// Test that files under a protected root are refused with their own error
+#[test]
+fn test_protected_files_are_refused() {
+    let dir = TempDir::new().unwrap();
+    let (original, hash) = write(dir.path(), "a.jpg", b"image");
+    let archive = dir.path().join("archive");
+    fs::create_dir(&archive).unwrap();
+    let (duplicate, _) = write(&archive, "b.jpg", b"image");
+    let safety = SafetyManager::new(&Config {
+        protected_roots: vec![archive],
+        ..Default::default()
+    });
+
+    let result = safety.check_action(
+        ActionType::Delete,
+        &duplicate,
+        Some(hash),
+        &original,
+        Some(hash),
+    );
+
+    assert!(
+        matches!(&result, Err(Error::ProtectedPath(path)) if *path == duplicate),
+        "{:?}",
+        result
+    );
+}
@@ -0,0 +1,31 @@
+// Files and databases shared by tests across modules
+use std::fs;
+use std::path::Path;
+
+use crate::persistence::ImageHashDB;
+use crate::processing::types::{ImageHashResult, PHash};
+use crate::Config;
+
+/// Configuration of a database inside `dir`
+pub fn db_config(dir: &Path) -> Config {
+    Config {
+        database_name: Some(dir.join("db").to_string_lossy().into_owned()),
+        ..Default::default()
+    }
+}
+
+/// Open a database inside `dir`
+pub fn open_db(dir: &Path) -> ImageHashDB {
+    ImageHashDB::new(&db_config(dir)).unwrap()
+}
+
+/// Write a file and return a hash result describing it
+pub fn hashed_file(dir: &Path, name: &str, contents: &[u8]) -> ImageHashResult {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    ImageHashResult {
+        cryptographic: blake3::hash(contents),
+        perceptual: PHash::Standard(42),
+        path,
+    }
+}
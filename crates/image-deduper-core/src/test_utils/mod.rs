@@ -1,5 +1,8 @@
 use std::path::PathBuf;
 
+#[cfg(test)]
+pub mod fixtures;
+
 #[cfg(test)]
 pub mod test_support;
 
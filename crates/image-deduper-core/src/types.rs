@@ -92,6 +92,9 @@ pub enum ActionType {
 
     /// Replace with symbolic link to original
     Symlink,
+
+    /// Replace with hard link to original
+    Hardlink,
 }
 
 /// Result of a deduplication action
@@ -106,6 +109,9 @@ pub struct ActionResult {
     /// Path of the original file
     pub original_path: PathBuf,
 
+    /// Where the duplicate was moved to, for moves
+    pub destination: Option<PathBuf>,
+
     /// Whether the action was successful
     pub success: bool,
 
@@ -113,6 +119,23 @@ pub struct ActionResult {
     pub error: Option<String>,
 }
 
+/// Counts of the images handled by `ImageDeduper::hash_and_persist`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistSummary {
+    /// Images hashed and stored in the database
+    pub new: usize,
+
+    /// Images found to be stored files that moved, whose entries were moved
+    /// instead of hashing them again
+    pub moved: usize,
+
+    /// Images already in the database, so not hashed again
+    pub skipped: usize,
+
+    /// Images that could not be hashed
+    pub failed: usize,
+}
+
 /// Memory usage tracker
 pub struct MemoryTracker {
     system: Mutex<System>,